@@ -1,10 +1,16 @@
 use crate::{
-    core::{BoxColor, Event, NotePosition, SequenceDiagram},
-    render::render_sequence,
+    core::{
+        Autonumber, BoxColor, Event, FragmentKind, Lifecycle, NotePosition, ParseError,
+        Participant, ParticipantKind, Selected, SequenceDiagram, Severity, ViewState, is_valid_id,
+    },
+    file::{FileFormat, FileWatch, OpenFile},
+    recovery::{Journal, Recovery},
+    render::{Charset, render_sequence, to_svg, to_text},
     theme::Theme,
     ui::{
-        EditorMode, EditorState, Selection, confirm::render_confirm_dialog, help::render_help,
-        input::render_input_popup, scroll::ScrollState, status_bar::render_status_bar,
+        EditorMode, EditorState, MetadataField, Selection, confirm::render_confirm_dialog,
+        help::render_help, input::render_input_popup, scroll::ScrollState,
+        status_bar::render_status_bar,
     },
 };
 use ratatui::{
@@ -14,7 +20,9 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
 };
+use std::fmt::Write as _;
 use std::fs;
+use std::path::PathBuf;
 use tui_world::keys;
 use tui_world::{KeyBinding, Keybindings, WidgetId, World};
 
@@ -24,17 +32,67 @@ pub const TEXT_INPUT: WidgetId = WidgetId("TextInput");
 pub const SELECT_PARTICIPANT: WidgetId = WidgetId("SelectParticipant");
 pub const SELECT_POSITION: WidgetId = WidgetId("SelectPosition");
 pub const SELECT_BOX_COLOR: WidgetId = WidgetId("SelectBoxColor");
+pub const SELECT_FRAGMENT_KIND: WidgetId = WidgetId("SelectFragmentKind");
+pub const SELECT_PARTICIPANT_KIND: WidgetId = WidgetId("SelectParticipantKind");
+pub const DIAGNOSTICS: WidgetId = WidgetId("Diagnostics");
+pub const METADATA: WidgetId = WidgetId("Metadata");
+pub const MARKDOWN_BLOCKS: WidgetId = WidgetId("MarkdownBlocks");
 
 #[derive(Default)]
 pub struct AppState {
     pub should_quit: bool,
     pub area: Rect,
+    /// Problems found while importing the diagram.
+    pub diagnostics: Vec<ParseError>,
+    /// The file the diagram is saved to, `None` until it has been saved.
+    pub file: Option<OpenFile>,
+    /// Counts changes to the diagram, see [`diagram_mut`].
+    pub revision: u64,
+    /// The revision last opened or saved, to tell whether the diagram has unsaved changes.
+    pub saved: u64,
+    /// Notices changes to `file` made by other programs.
+    pub watch: FileWatch,
+    /// Autosaves and backups.
+    pub journal: Journal,
+    /// A recovery file waiting for the user to restore or discard it.
+    pub recovery: Option<Recovery>,
+    /// A Save As target that already exists, waiting for the user to confirm replacing it.
+    pub overwrite: Option<OpenFile>,
+}
+
+/// Whether the diagram has changed since it was last opened or saved.
+pub fn is_dirty(world: &World) -> bool {
+    let state = world.get::<AppState>();
+    state.revision != state.saved
+}
+
+fn mark_saved(world: &mut World) {
+    let state = world.get_mut::<AppState>();
+    state.saved = state.revision;
+}
+
+/// The diagram, for a change to it. Every change goes through here or [`try_change`] so that
+/// it counts as unsaved, without comparing the whole diagram on every frame.
+fn diagram_mut(world: &mut World) -> &mut SequenceDiagram {
+    world.get_mut::<AppState>().revision += 1;
+    world.get_mut::<SequenceDiagram>()
+}
+
+/// Applies an edit that may find nothing to change, counting it as unsaved only if `change`
+/// returns true.
+fn try_change(world: &mut World, change: impl FnOnce(&mut SequenceDiagram) -> bool) -> bool {
+    let changed = change(world.get_mut::<SequenceDiagram>());
+    if changed {
+        world.get_mut::<AppState>().revision += 1;
+    }
+    changed
 }
 
 pub fn setup_world(world: &mut World, diagram: SequenceDiagram) {
     world.insert(Theme::default());
     world.insert(AppState::default());
     world.insert(diagram);
+    mark_saved(world);
     world.insert(EditorState::new());
     world.insert(ScrollState::new());
 
@@ -44,12 +102,204 @@ pub fn setup_world(world: &mut World, diagram: SequenceDiagram) {
     text_input_keybindings(world);
     confirm_keybindings(world);
     select_box_color_keybindings(world);
+    select_fragment_kind_keybindings(world);
+    select_participant_kind_keybindings(world);
+    diagnostics_keybindings(world);
+    metadata_keybindings(world);
+    markdown_keybindings(world);
+}
+
+/// Saves the diagram to `file` from now on. For a Markdown file without a picked block, asks
+/// which block to edit.
+pub fn open_file(world: &mut World, file: OpenFile) {
+    let pick = matches!(file.format, FileFormat::Markdown { block: None, .. });
+    let state = world.get_mut::<AppState>();
+    state.watch.reset(&file.path);
+    state.file = Some(file);
+    if pick {
+        let editor = world.get_mut::<EditorState>();
+        editor.mode = EditorMode::SelectMarkdownBlock;
+        editor.selected_index = 0;
+    }
+}
+
+/// Offers to restore the diagram from a recovery file left by a session that did not save
+/// its changes.
+pub fn offer_recovery(world: &mut World) {
+    let state = world.get::<AppState>();
+    if matches!(
+        state.file,
+        Some(OpenFile {
+            format: FileFormat::Markdown { block: None, .. },
+            ..
+        })
+    ) {
+        return;
+    }
+    let file = state.file.clone();
+    let diagram = world.get::<SequenceDiagram>().clone();
+    let recovery = world
+        .get_mut::<AppState>()
+        .journal
+        .find(file.as_ref(), &diagram);
+    if recovery.is_some() {
+        world.get_mut::<AppState>().recovery = recovery;
+        world.get_mut::<EditorState>().mode = EditorMode::ConfirmRestore;
+    }
+}
+
+/// Opens the list of import problems, unless there are none or another question is open.
+pub fn show_diagnostics(world: &mut World) {
+    if world.get::<AppState>().diagnostics.is_empty()
+        || world.get::<EditorState>().mode != EditorMode::Normal
+    {
+        return;
+    }
+    let editor = world.get_mut::<EditorState>();
+    editor.selected_index = 0;
+    editor.mode = EditorMode::Diagnostics;
+}
+
+/// Runs on every pass of the main loop.
+pub fn tick(world: &mut World) {
+    watch_file(world);
+    autosave(world);
+}
+
+/// Reloads the file when another program changed it, asking first if that would drop edits.
+fn watch_file(world: &mut World) {
+    // Only reload while no command is half done.
+    if !matches!(
+        world.get::<EditorState>().mode,
+        EditorMode::Normal | EditorMode::Help
+    ) {
+        return;
+    }
+    let state = world.get_mut::<AppState>();
+    let Some(file) = &state.file else {
+        return;
+    };
+    if !state.watch.changed(&file.path) {
+        return;
+    }
+    if is_dirty(world) {
+        world.get_mut::<EditorState>().mode = EditorMode::ConfirmReload;
+    } else {
+        reload(world);
+    }
+}
+
+/// Replaces the diagram with the file's, keeping the selection and scroll position as close
+/// as the new diagram allows. A file that no longer parses leaves the diagram as it is.
+fn reload(world: &mut World) {
+    let AppState { file, watch, .. } = world.get_mut::<AppState>();
+    let Some(file) = file else {
+        return;
+    };
+    watch.reset(&file.path);
+    let name = file.name();
+    let diagram = match file.reload() {
+        Ok(diagram) => diagram,
+        Err(e) => {
+            world
+                .get_mut::<EditorState>()
+                .set_status(format!("Reload failed: {e:#}"));
+            return;
+        }
+    };
+
+    let mut view = view_state(world);
+    if let Some(Selected::Participant(idx)) = view.selection {
+        // Follow the participant if the reload moved it.
+        let old = world.get::<SequenceDiagram>();
+        if let Some(new_idx) = old
+            .participants
+            .get(idx)
+            .and_then(|p| diagram.participant_index(&p.id))
+        {
+            view.selection = Some(Selected::Participant(new_idx));
+        }
+    }
+    view.selection = match view.selection {
+        Some(Selected::Participant(idx)) if diagram.participant_count() > 0 => Some(
+            Selected::Participant(idx.min(diagram.participant_count() - 1)),
+        ),
+        Some(Selected::Event(idx)) if diagram.event_count() > 0 => {
+            Some(Selected::Event(idx.min(diagram.event_count() - 1)))
+        }
+        _ => None,
+    };
+
+    *diagram_mut(world) = diagram;
+    mark_saved(world);
+    let state = world.get_mut::<AppState>();
+    state.diagnostics.clear();
+    state.journal.discard();
+    restore_view(world, view);
+    let editor = world.get_mut::<EditorState>();
+    editor.range_anchor = None;
+    editor.set_status(format!("Reloaded {name}"));
+}
+
+/// Writes unsaved changes to the recovery file every
+/// [`AUTOSAVE_INTERVAL`](crate::recovery::AUTOSAVE_INTERVAL).
+fn autosave(world: &mut World) {
+    if matches!(
+        world.get::<EditorState>().mode,
+        EditorMode::ConfirmRestore | EditorMode::SelectMarkdownBlock
+    ) || !world.get_mut::<AppState>().journal.autosave_due()
+    {
+        return;
+    }
+    if !is_dirty(world) {
+        world.get_mut::<AppState>().journal.discard();
+        return;
+    }
+    let view = view_state(world);
+    let diagram = world.get::<SequenceDiagram>().clone();
+    let state = world.get_mut::<AppState>();
+    let file = state.file.clone();
+    if let Err(e) = state.journal.autosave(file.as_ref(), &diagram, view) {
+        world
+            .get_mut::<EditorState>()
+            .set_status(format!("Autosave failed: {e}"));
+    }
+}
+
+/// The selection and scroll position, as saved in JSON documents.
+pub fn view_state(world: &World) -> ViewState {
+    let selection = match world.get::<EditorState>().selection {
+        Selection::None => None,
+        Selection::Participant(idx) => Some(Selected::Participant(idx)),
+        Selection::Event(idx) => Some(Selected::Event(idx)),
+    };
+    ViewState {
+        selection,
+        scroll: world.get::<ScrollState>().offset,
+    }
+}
+
+/// Restores a saved selection and scroll position, ignoring positions past the diagram.
+pub fn restore_view(world: &mut World, view: ViewState) {
+    let diagram = world.get::<SequenceDiagram>();
+    let (participant_count, event_count) = (diagram.participant_count(), diagram.event_count());
+    let selection = match view.selection {
+        Some(Selected::Participant(idx)) if idx < participant_count => Selection::Participant(idx),
+        Some(Selected::Event(idx)) if idx < event_count => Selection::Event(idx),
+        _ => Selection::None,
+    };
+    world.get_mut::<EditorState>().selection = selection;
+    world.get_mut::<ScrollState>().offset = view.scroll.min(event_count.saturating_sub(1));
 }
 
 fn normal_keybindings(world: &mut World) {
     let kb = world.get_mut::<Keybindings>();
 
     kb.bind(NORMAL, KeyBinding::ctrl('c'), "Quit", |world| {
+        if is_dirty(world) {
+            world.get_mut::<EditorState>().mode = EditorMode::ConfirmQuit;
+            return;
+        }
         world.get_mut::<AppState>().should_quit = true;
     });
 
@@ -135,7 +385,7 @@ fn normal_keybindings(world: &mut World) {
         let selection = world.get::<EditorState>().selection;
         match selection {
             Selection::Participant(idx) => {
-                world.get_mut::<SequenceDiagram>().remove_participant(idx);
+                diagram_mut(world).remove_participant(idx);
                 let new_count = world.get::<SequenceDiagram>().participant_count();
                 let editor = world.get_mut::<EditorState>();
                 if new_count == 0 {
@@ -145,7 +395,7 @@ fn normal_keybindings(world: &mut World) {
                 }
             }
             Selection::Event(idx) => {
-                world.get_mut::<SequenceDiagram>().remove_event(idx);
+                diagram_mut(world).remove_event(idx);
                 let new_count = world.get::<SequenceDiagram>().event_count();
                 let editor = world.get_mut::<EditorState>();
                 if new_count == 0 {
@@ -155,7 +405,10 @@ fn normal_keybindings(world: &mut World) {
                 }
             }
             Selection::None => {
-                world.get_mut::<SequenceDiagram>().events.pop();
+                let event_count = world.get::<SequenceDiagram>().event_count();
+                if let Some(last) = event_count.checked_sub(1) {
+                    diagram_mut(world).remove_event(last);
+                }
             }
         }
     });
@@ -163,48 +416,15 @@ fn normal_keybindings(world: &mut World) {
     kb.bind(NORMAL, 'B', "Remove box", |world| {
         let selection = world.get::<EditorState>().selection;
         if let Selection::Participant(idx) = selection {
-            let box_idx = world
-                .get::<SequenceDiagram>()
-                .boxes
-                .iter()
-                .position(|b| b.start <= idx && idx <= b.end);
+            let box_idx = world.get::<SequenceDiagram>().box_index_at(idx);
             if let Some(box_idx) = box_idx {
-                world.get_mut::<SequenceDiagram>().remove_box_at(box_idx);
+                diagram_mut(world).remove_box_at(box_idx);
             } else {
                 world.get_mut::<EditorState>().set_status("No box here");
             }
         }
     });
 
-    kb.bind(NORMAL, 'd', "Delete selected", |world| {
-        let selection = world.get::<EditorState>().selection;
-        match selection {
-            Selection::Participant(idx) => {
-                world.get_mut::<SequenceDiagram>().remove_participant(idx);
-                let new_count = world.get::<SequenceDiagram>().participant_count();
-                let editor = world.get_mut::<EditorState>();
-                if new_count == 0 {
-                    editor.clear_selection();
-                } else {
-                    editor.selection = Selection::Participant(idx.min(new_count - 1));
-                }
-            }
-            Selection::Event(idx) => {
-                world.get_mut::<SequenceDiagram>().remove_event(idx);
-                let new_count = world.get::<SequenceDiagram>().event_count();
-                let editor = world.get_mut::<EditorState>();
-                if new_count == 0 {
-                    editor.clear_selection();
-                } else {
-                    editor.selection = Selection::Event(idx.min(new_count - 1));
-                }
-            }
-            Selection::None => {
-                world.get_mut::<SequenceDiagram>().events.pop();
-            }
-        }
-    });
-
     kb.bind_many(
         NORMAL,
         keys!['l', KeyCode::Right],
@@ -259,16 +479,20 @@ fn normal_keybindings(world: &mut World) {
         |world| {
             let selection = world.get::<EditorState>().selection;
             match selection {
-                Selection::Participant(idx) if idx > 0 => {
-                    world
-                        .get_mut::<SequenceDiagram>()
-                        .swap_participants(idx, idx - 1);
-                    world.get_mut::<EditorState>().selection = Selection::Participant(idx - 1);
+                Selection::Participant(idx) => {
+                    let mut moved = None;
+                    try_change(world, |diagram| {
+                        moved = diagram.move_participant(idx, false);
+                        moved.is_some()
+                    });
+                    if let Some(idx) = moved {
+                        world.get_mut::<EditorState>().selection = Selection::Participant(idx);
+                    }
                 }
                 Selection::Event(idx) => {
-                    world.get_mut::<SequenceDiagram>().point_event_left(idx);
+                    try_change(world, |diagram| diagram.point_event_left(idx));
                 }
-                _ => {}
+                Selection::None => {}
             }
         },
     );
@@ -281,16 +505,17 @@ fn normal_keybindings(world: &mut World) {
             let selection = world.get::<EditorState>().selection;
             match selection {
                 Selection::Participant(idx) => {
-                    let participant_count = world.get::<SequenceDiagram>().participant_count();
-                    if idx + 1 < participant_count {
-                        world
-                            .get_mut::<SequenceDiagram>()
-                            .swap_participants(idx, idx + 1);
-                        world.get_mut::<EditorState>().selection = Selection::Participant(idx + 1);
+                    let mut moved = None;
+                    try_change(world, |diagram| {
+                        moved = diagram.move_participant(idx, true);
+                        moved.is_some()
+                    });
+                    if let Some(idx) = moved {
+                        world.get_mut::<EditorState>().selection = Selection::Participant(idx);
                     }
                 }
                 Selection::Event(idx) => {
-                    world.get_mut::<SequenceDiagram>().point_event_right(idx);
+                    try_change(world, |diagram| diagram.point_event_right(idx));
                 }
                 Selection::None => {}
             }
@@ -303,12 +528,10 @@ fn normal_keybindings(world: &mut World) {
         "Move message/note down",
         |world| {
             let selection = world.get::<EditorState>().selection;
-            if let Selection::Event(idx) = selection {
-                let event_count = world.get::<SequenceDiagram>().event_count();
-                if idx + 1 < event_count {
-                    world.get_mut::<SequenceDiagram>().events.swap(idx, idx + 1);
-                    world.get_mut::<EditorState>().selection = Selection::Event(idx + 1);
-                }
+            if let Selection::Event(idx) = selection
+                && try_change(world, |diagram| diagram.move_event(idx, idx + 1))
+            {
+                world.get_mut::<EditorState>().selection = Selection::Event(idx + 1);
             }
         },
     );
@@ -321,8 +544,8 @@ fn normal_keybindings(world: &mut World) {
             let selection = world.get::<EditorState>().selection;
             if let Selection::Event(idx) = selection
                 && idx > 0
+                && try_change(world, |diagram| diagram.move_event(idx, idx - 1))
             {
-                world.get_mut::<SequenceDiagram>().events.swap(idx, idx - 1);
                 world.get_mut::<EditorState>().selection = Selection::Event(idx - 1);
             }
         },
@@ -341,13 +564,15 @@ fn normal_keybindings(world: &mut World) {
                         diagram.events.get(idx).cloned()
                     };
                     match event_data {
-                        Some(Event::Message { from, to, text }) => {
+                        Some(Event::Message { from, to, text, .. }) => {
+                            let diagram = world.get::<SequenceDiagram>();
+                            let (from, to) = (diagram.position_of(from), diagram.position_of(to));
                             let editor = world.get_mut::<EditorState>();
                             editor.editing_event_index = Some(idx);
-                            editor.message_from = Some(from);
-                            editor.message_to = Some(to);
+                            editor.message_from = from;
+                            editor.message_to = to;
                             editor.input_buffer = text;
-                            editor.selected_index = from;
+                            editor.selected_index = from.unwrap_or_default();
                             editor.mode = EditorMode::EditSelectFrom;
                         }
                         Some(Event::Note {
@@ -356,27 +581,56 @@ fn normal_keybindings(world: &mut World) {
                             participant_end,
                             text,
                         }) => {
+                            let diagram = world.get::<SequenceDiagram>();
+                            let start = diagram.position_of(participant_start);
+                            let end = diagram.position_of(participant_end);
                             let editor = world.get_mut::<EditorState>();
                             editor.editing_event_index = Some(idx);
                             editor.note_position = position;
-                            editor.note_participant_start = Some(participant_start);
-                            editor.note_participant_end = Some(participant_end);
+                            editor.note_participant_start = start;
+                            editor.note_participant_end = end;
                             editor.input_buffer = text;
-                            editor.selected_index = participant_start;
+                            editor.selected_index = start.unwrap_or_default();
                             editor.mode = EditorMode::EditNoteParticipant;
                         }
-                        None => {}
+                        Some(Event::FragmentStart { kind, label }) => {
+                            let editor = world.get_mut::<EditorState>();
+                            editor.editing_event_index = Some(idx);
+                            editor.fragment_kind = kind;
+                            editor.input_buffer = label;
+                            editor.mode = EditorMode::EditFragmentKind;
+                        }
+                        Some(Event::FragmentSection { label }) => {
+                            let editor = world.get_mut::<EditorState>();
+                            editor.editing_event_index = Some(idx);
+                            editor.input_buffer = label;
+                            editor.mode = EditorMode::EditFragmentLabel;
+                        }
+                        Some(Event::HighlightStart { color }) => {
+                            let editor = world.get_mut::<EditorState>();
+                            editor.editing_event_index = Some(idx);
+                            editor.box_color = BoxColor::all()
+                                .iter()
+                                .copied()
+                                .find(|c| c.light() == color)
+                                .unwrap_or(BoxColor::Rgb(color));
+                            editor.mode = EditorMode::EditHighlightColor;
+                        }
+                        Some(
+                            Event::Activation { .. } | Event::FragmentEnd | Event::HighlightEnd,
+                        )
+                        | None => {}
                     }
                 }
                 Selection::Participant(idx) => {
-                    let name = {
+                    let id = {
                         let diagram = world.get::<SequenceDiagram>();
-                        diagram.participants.get(idx).cloned()
+                        diagram.participants.get(idx).map(|p| p.id.clone())
                     };
-                    if let Some(name) = name {
+                    if let Some(id) = id {
                         let editor = world.get_mut::<EditorState>();
                         editor.selected_index = idx;
-                        editor.input_buffer = name;
+                        editor.input_buffer = id;
                         editor.mode = EditorMode::RenameParticipant;
                     }
                 }
@@ -394,11 +648,13 @@ fn normal_keybindings(world: &mut World) {
                     diagram.events.get(idx).cloned()
                 };
                 match event_data {
-                    Some(Event::Message { from, to, text }) => {
+                    Some(Event::Message { from, to, text, .. }) => {
+                        let diagram = world.get::<SequenceDiagram>();
+                        let (from, to) = (diagram.position_of(from), diagram.position_of(to));
                         let editor = world.get_mut::<EditorState>();
                         editor.editing_event_index = Some(idx);
-                        editor.message_from = Some(from);
-                        editor.message_to = Some(to);
+                        editor.message_from = from;
+                        editor.message_to = to;
                         editor.input_buffer = text;
                         editor.mode = EditorMode::EditMessage;
                     }
@@ -408,26 +664,48 @@ fn normal_keybindings(world: &mut World) {
                         participant_end,
                         text,
                     }) => {
+                        let diagram = world.get::<SequenceDiagram>();
+                        let start = diagram.position_of(participant_start);
+                        let end = diagram.position_of(participant_end);
                         let editor = world.get_mut::<EditorState>();
                         editor.editing_event_index = Some(idx);
                         editor.note_position = position;
-                        editor.note_participant_start = Some(participant_start);
-                        editor.note_participant_end = Some(participant_end);
+                        editor.note_participant_start = start;
+                        editor.note_participant_end = end;
                         editor.input_buffer = text;
                         editor.mode = EditorMode::EditNoteText;
                     }
-                    None => {}
+                    Some(Event::FragmentStart { kind, label }) => {
+                        let editor = world.get_mut::<EditorState>();
+                        editor.editing_event_index = Some(idx);
+                        editor.fragment_kind = kind;
+                        editor.input_buffer = label;
+                        editor.mode = EditorMode::EditFragmentLabel;
+                    }
+                    Some(Event::FragmentSection { label }) => {
+                        let editor = world.get_mut::<EditorState>();
+                        editor.editing_event_index = Some(idx);
+                        editor.input_buffer = label;
+                        editor.mode = EditorMode::EditFragmentLabel;
+                    }
+                    Some(
+                        Event::Activation { .. }
+                        | Event::FragmentEnd
+                        | Event::HighlightStart { .. }
+                        | Event::HighlightEnd,
+                    )
+                    | None => {}
                 }
             }
             Selection::Participant(idx) => {
-                let name = {
+                let id = {
                     let diagram = world.get::<SequenceDiagram>();
-                    diagram.participants.get(idx).cloned()
+                    diagram.participants.get(idx).map(|p| p.id.clone())
                 };
-                if let Some(name) = name {
+                if let Some(id) = id {
                     let editor = world.get_mut::<EditorState>();
                     editor.selected_index = idx;
-                    editor.input_buffer = name;
+                    editor.input_buffer = id;
                     editor.mode = EditorMode::RenameParticipant;
                 }
             }
@@ -435,18 +713,191 @@ fn normal_keybindings(world: &mut World) {
         }
     });
 
-    kb.bind(NORMAL, 'b', "Add box", |world| {
+    kb.bind(NORMAL, 'a', "Cycle arrowhead", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            try_change(world, |diagram| diagram.cycle_arrow_head(idx));
+        }
+    });
+
+    kb.bind(NORMAL, '-', "Toggle dotted line", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            try_change(world, |diagram| diagram.toggle_arrow_line(idx));
+        }
+    });
+
+    kb.bind(NORMAL, '+', "Toggle activation", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            try_change(world, |diagram| diagram.cycle_activation(idx));
+        }
+    });
+
+    kb.bind(NORMAL, 'c', "Toggle create target", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            try_change(world, |diagram| {
+                diagram.toggle_lifecycle(idx, Lifecycle::CreateTarget)
+            });
+        }
+    });
+
+    kb.bind(NORMAL, 'x', "Toggle destroy target", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            try_change(world, |diagram| {
+                diagram.toggle_lifecycle(idx, Lifecycle::DestroyTarget)
+            });
+        }
+    });
+
+    kb.bind(NORMAL, 'v', "Mark range", |world| {
+        let editor = world.get_mut::<EditorState>();
+        if let Selection::Event(idx) = editor.selection {
+            editor.range_anchor = if editor.range_anchor.is_some() {
+                None
+            } else {
+                Some(idx)
+            };
+        }
+    });
+
+    kb.bind(NORMAL, 'f', "Wrap in fragment", |world| {
+        let editor = world.get_mut::<EditorState>();
+        if let Some(range) = editor.event_range() {
+            editor.wrap_range = Some(range);
+            editor.fragment_kind = FragmentKind::default();
+            editor.mode = EditorMode::SelectFragmentKind;
+        } else {
+            editor.set_status("Select messages/notes to wrap");
+        }
+    });
+
+    kb.bind(NORMAL, 'R', "Highlight region", |world| {
+        let editor = world.get_mut::<EditorState>();
+        if let Some(range) = editor.event_range() {
+            editor.wrap_range = Some(range);
+            editor.box_color = BoxColor::default();
+            editor.mode = EditorMode::SelectHighlightColor;
+        } else {
+            editor.set_status("Select messages/notes to highlight");
+        }
+    });
+
+    kb.bind(NORMAL, 'o', "Add fragment section", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            let editor = world.get_mut::<EditorState>();
+            editor.insert_after_index = Some(idx);
+            editor.input_buffer.clear();
+            editor.mode = EditorMode::InputFragmentSection;
+        }
+    });
+
+    kb.bind(NORMAL, 'u', "Unwrap fragment/highlight", |world| {
+        if let Selection::Event(idx) = world.get::<EditorState>().selection {
+            if try_change(world, |diagram| diagram.unwrap_block(idx)) {
+                let event_count = world.get::<SequenceDiagram>().event_count();
+                let editor = world.get_mut::<EditorState>();
+                editor.range_anchor = None;
+                if event_count == 0 {
+                    editor.clear_selection();
+                } else {
+                    editor.selection = Selection::Event(idx.min(event_count - 1));
+                }
+            } else {
+                world
+                    .get_mut::<EditorState>()
+                    .set_status("Not inside a fragment or highlight");
+            }
+        }
+    });
+
+    kb.bind(NORMAL, '#', "Autonumber", |world| {
+        let autonumber = world
+            .get::<SequenceDiagram>()
+            .autonumber
+            .unwrap_or_default();
+        let editor = world.get_mut::<EditorState>();
+        editor.input_buffer = format!("{} {}", autonumber.start, autonumber.step);
+        editor.mode = EditorMode::InputAutonumber;
+    });
+
+    kb.bind(NORMAL, 'g', "Go to message", |world| {
+        let editor = world.get_mut::<EditorState>();
+        editor.input_buffer.clear();
+        editor.mode = EditorMode::InputGotoMessage;
+    });
+
+    kb.bind(NORMAL, 'D', "Show import diagnostics", |world| {
+        if world.get::<AppState>().diagnostics.is_empty() {
+            world
+                .get_mut::<EditorState>()
+                .set_status("No import diagnostics");
+        } else {
+            let editor = world.get_mut::<EditorState>();
+            editor.selected_index = 0;
+            editor.mode = EditorMode::Diagnostics;
+        }
+    });
+
+    kb.bind(NORMAL, 'T', "Edit title and metadata", |world| {
+        let editor = world.get_mut::<EditorState>();
+        editor.selected_index = 0;
+        editor.mode = EditorMode::EditMetadata;
+    });
+
+    kb.bind(NORMAL, 't', "Change participant type", |world| {
+        if let Selection::Participant(idx) = world.get::<EditorState>().selection {
+            let kind = world.get::<SequenceDiagram>().participants[idx].kind;
+            let editor = world.get_mut::<EditorState>();
+            editor.selected_index = idx;
+            editor.participant_kind = kind;
+            editor.mode = EditorMode::SelectParticipantKind;
+        }
+    });
+
+    kb.bind(NORMAL, 'b', "Add or edit box", |world| {
         let participant_count = world.get::<SequenceDiagram>().participant_count();
         if participant_count >= 1 {
             let selection = world.get::<EditorState>().selection;
-            let editor = world.get_mut::<EditorState>();
-            editor.mode = EditorMode::SelectBoxStart;
-            editor.selected_index = match selection {
+            let selected = match selection {
                 Selection::Participant(idx) => idx,
                 _ => 0,
             };
-            editor.box_start = None;
-            editor.box_end = None;
+            // A participant inside a box selects that box for editing
+            let diagram = world.get::<SequenceDiagram>();
+            let existing = matches!(selection, Selection::Participant(_))
+                .then(|| diagram.box_index_at(selected))
+                .flatten()
+                .and_then(|b| {
+                    let existing = diagram.boxes[b].clone();
+                    Some((b, diagram.box_span(&existing)?, existing))
+                });
+            let editor = world.get_mut::<EditorState>();
+            editor.mode = EditorMode::SelectBoxStart;
+            if let Some((b, (start, end), existing)) = existing {
+                editor.editing_box = Some(b);
+                editor.selected_index = start;
+                editor.box_start = Some(start);
+                editor.box_end = Some(end);
+                editor.box_color = existing.color;
+                editor.input_buffer = existing.label;
+            } else {
+                editor.selected_index = selected;
+                editor.box_start = None;
+                editor.box_end = None;
+            }
+        }
+    });
+
+    kb.bind(NORMAL, 'i', "Move participant into/out of box", |world| {
+        if let Selection::Participant(idx) = world.get::<EditorState>().selection {
+            let mut moved = None;
+            try_change(world, |diagram| {
+                moved = diagram.toggle_box_membership(idx);
+                moved.is_some()
+            });
+            let editor = world.get_mut::<EditorState>();
+            match moved {
+                Some(new_idx) => editor.selection = Selection::Participant(new_idx),
+                None => editor.set_status("No box to move into or out of"),
+            }
         }
     });
 
@@ -457,6 +908,15 @@ fn normal_keybindings(world: &mut World) {
         }
     });
 
+    kb.bind(NORMAL, KeyBinding::ctrl('s'), "Save", |world| {
+        match world.get::<AppState>().file.clone() {
+            Some(file) => save(world, file),
+            None => start_save_as(world),
+        }
+    });
+
+    kb.bind(NORMAL, KeyBinding::ctrl('o'), "Save as", start_save_as);
+
     kb.bind(NORMAL, 'E', "Export to Mermaid", |world| {
         let diagram = world.get::<SequenceDiagram>();
         let mermaid = diagram.to_mermaid();
@@ -470,6 +930,41 @@ fn normal_keybindings(world: &mut World) {
         }
     });
 
+    kb.bind(NORMAL, 'P', "Export to PlantUML", |world| {
+        let plantuml = world.get::<SequenceDiagram>().to_plantuml();
+        let status = match fs::write("diagram.puml", plantuml) {
+            Ok(()) => "Exported to diagram.puml".to_string(),
+            Err(e) => format!("Export failed: {e}"),
+        };
+        world.get_mut::<EditorState>().set_status(status);
+    });
+
+    kb.bind(NORMAL, 'W', "Export to JSON", |world| {
+        let json = world.get::<SequenceDiagram>().to_json(view_state(world));
+        let status = match fs::write("diagram.json", json) {
+            Ok(()) => "Exported to diagram.json".to_string(),
+            Err(e) => format!("Export failed: {e}"),
+        };
+        world.get_mut::<EditorState>().set_status(status);
+    });
+
+    kb.bind(NORMAL, 'S', "Export to SVG", |world| {
+        let svg = to_svg(world.get::<SequenceDiagram>());
+        let status = match fs::write("diagram.svg", svg) {
+            Ok(()) => "Exported to diagram.svg".to_string(),
+            Err(e) => format!("Export failed: {e}"),
+        };
+        world.get_mut::<EditorState>().set_status(status);
+    });
+
+    kb.bind(NORMAL, 'X', "Export as text", |world| {
+        export_text(world, Charset::Unicode, "diagram.txt");
+    });
+
+    kb.bind(NORMAL, 'A', "Export as ASCII text", |world| {
+        export_text(world, Charset::Ascii, "diagram-ascii.txt");
+    });
+
     kb.bind(NORMAL, '?', "Help", |world| {
         let editor = world.get_mut::<EditorState>();
         editor.mode = if editor.mode == EditorMode::Help {
@@ -592,7 +1087,25 @@ fn text_input_keybindings(world: &mut World) {
         KeyBinding::key(KeyCode::Esc),
         "Cancel",
         |world| {
-            world.get_mut::<EditorState>().reset();
+            let editor = world.get_mut::<EditorState>();
+            if editor.mode == EditorMode::InputMetadataField {
+                editor.input_buffer.clear();
+                editor.mode = EditorMode::EditMetadata;
+            } else {
+                editor.reset();
+            }
+        },
+    );
+
+    kb.bind(
+        TEXT_INPUT,
+        KeyBinding::new(KeyCode::Enter, KeyModifiers::ALT),
+        "New line",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            if editor.mode.is_multiline() {
+                editor.input_buffer.push('\n');
+            }
         },
     );
 
@@ -616,14 +1129,61 @@ fn confirm_keybindings(world: &mut World) {
     let kb = world.get_mut::<Keybindings>();
 
     kb.bind(CONFIRM, 'y', "Yes", |world| {
-        let diagram = world.get_mut::<SequenceDiagram>();
-        diagram.participants.clear();
-        diagram.events.clear();
-        diagram.boxes.clear();
-        world.get_mut::<EditorState>().reset();
+        match world.get::<EditorState>().mode {
+            EditorMode::ConfirmQuit => {
+                world.get_mut::<AppState>().should_quit = true;
+            }
+            EditorMode::ConfirmReload => {
+                world.get_mut::<EditorState>().reset();
+                reload(world);
+            }
+            EditorMode::ConfirmOverwrite => {
+                world.get_mut::<EditorState>().reset();
+                if let Some(file) = world.get_mut::<AppState>().overwrite.take() {
+                    save(world, file);
+                }
+            }
+            EditorMode::ConfirmRestore => {
+                let state = world.get_mut::<AppState>();
+                let Some(recovery) = state.recovery.take() else {
+                    return;
+                };
+                // The problems were found in the file, and point into its diagram.
+                state.diagnostics.clear();
+                *diagram_mut(world) = recovery.diagram;
+                world.get_mut::<EditorState>().reset();
+                restore_view(world, recovery.view);
+                world
+                    .get_mut::<EditorState>()
+                    .set_status("Restored autosaved changes");
+            }
+            _ => {
+                world.get_mut::<AppState>().diagnostics.clear();
+                *diagram_mut(world) = SequenceDiagram::new();
+                world.get_mut::<EditorState>().reset();
+            }
+        }
     });
 
     kb.bind_many(CONFIRM, keys!['n', KeyCode::Esc], "No", |world| {
+        match world.get::<EditorState>().mode {
+            EditorMode::ConfirmRestore => {
+                let state = world.get_mut::<AppState>();
+                state.recovery = None;
+                state.journal.discard();
+            }
+            EditorMode::ConfirmOverwrite => {
+                world.get_mut::<AppState>().overwrite = None;
+            }
+            // Keep the edits; saving them overwrites the other program's changes.
+            EditorMode::ConfirmReload => {
+                let state = world.get_mut::<AppState>();
+                if let Some(file) = &state.file {
+                    state.watch.reset(&file.path);
+                }
+            }
+            _ => {}
+        }
         world.get_mut::<EditorState>().reset();
     });
 }
@@ -668,15 +1228,244 @@ fn select_box_color_keybindings(world: &mut World) {
     );
 }
 
-fn handle_input_confirm(world: &mut World) {
-    let mode = world.get::<EditorState>().mode.clone();
-    match mode {
-        EditorMode::InputParticipant => {
-            let name = world.get::<EditorState>().input_buffer.trim().to_string();
-            if !name.is_empty() {
-                world.get_mut::<SequenceDiagram>().add_participant(name);
-            }
-            world.get_mut::<EditorState>().reset();
+fn select_fragment_kind_keybindings(world: &mut World) {
+    let kb = world.get_mut::<Keybindings>();
+
+    kb.bind(
+        SELECT_FRAGMENT_KIND,
+        KeyBinding::key(KeyCode::Enter),
+        "Confirm",
+        handle_input_confirm,
+    );
+
+    kb.bind(
+        SELECT_FRAGMENT_KIND,
+        KeyBinding::key(KeyCode::Esc),
+        "Cancel",
+        |world| {
+            world.get_mut::<EditorState>().reset();
+        },
+    );
+
+    kb.bind_many(
+        SELECT_FRAGMENT_KIND,
+        keys!['h', 'k', KeyCode::Left, KeyCode::Up],
+        "Previous",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            editor.fragment_kind = editor.fragment_kind.prev();
+        },
+    );
+
+    kb.bind_many(
+        SELECT_FRAGMENT_KIND,
+        keys!['j', 'l', KeyCode::Right, KeyCode::Down],
+        "Next",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            editor.fragment_kind = editor.fragment_kind.next();
+        },
+    );
+}
+
+fn select_participant_kind_keybindings(world: &mut World) {
+    let kb = world.get_mut::<Keybindings>();
+
+    kb.bind(
+        SELECT_PARTICIPANT_KIND,
+        KeyBinding::key(KeyCode::Enter),
+        "Confirm",
+        handle_input_confirm,
+    );
+
+    kb.bind(
+        SELECT_PARTICIPANT_KIND,
+        KeyBinding::key(KeyCode::Esc),
+        "Cancel",
+        |world| {
+            world.get_mut::<EditorState>().reset();
+        },
+    );
+
+    kb.bind_many(
+        SELECT_PARTICIPANT_KIND,
+        keys!['h', 'k', KeyCode::Left, KeyCode::Up],
+        "Previous",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            editor.participant_kind = editor.participant_kind.prev();
+        },
+    );
+
+    kb.bind_many(
+        SELECT_PARTICIPANT_KIND,
+        keys!['j', 'l', KeyCode::Right, KeyCode::Down],
+        "Next",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            editor.participant_kind = editor.participant_kind.next();
+        },
+    );
+}
+
+fn diagnostics_keybindings(world: &mut World) {
+    let kb = world.get_mut::<Keybindings>();
+
+    kb.bind(
+        DIAGNOSTICS,
+        KeyBinding::key(KeyCode::Enter),
+        "Go to event",
+        |world| {
+            let idx = world.get::<EditorState>().selected_index;
+            let event = world
+                .get::<AppState>()
+                .diagnostics
+                .get(idx)
+                .and_then(|d| d.event);
+            let event = event.and_then(|id| world.get::<SequenceDiagram>().events.index_of(id));
+            let editor = world.get_mut::<EditorState>();
+            if let Some(event) = event {
+                editor.selection = Selection::Event(event);
+                editor.range_anchor = None;
+            }
+            editor.reset();
+        },
+    );
+
+    kb.bind_many(
+        DIAGNOSTICS,
+        keys![KeyCode::Esc, 'q', 'D'],
+        "Close",
+        |world| {
+            world.get_mut::<EditorState>().reset();
+        },
+    );
+
+    kb.bind_many(DIAGNOSTICS, keys!['k', KeyCode::Up], "Previous", |world| {
+        let editor = world.get_mut::<EditorState>();
+        editor.selected_index = editor.selected_index.saturating_sub(1);
+    });
+
+    kb.bind_many(DIAGNOSTICS, keys!['j', KeyCode::Down], "Next", |world| {
+        let count = world.get::<AppState>().diagnostics.len();
+        let editor = world.get_mut::<EditorState>();
+        editor.selected_index = (editor.selected_index + 1).min(count.saturating_sub(1));
+    });
+}
+
+fn markdown_keybindings(world: &mut World) {
+    let kb = world.get_mut::<Keybindings>();
+
+    kb.bind(
+        MARKDOWN_BLOCKS,
+        KeyBinding::key(KeyCode::Enter),
+        "Edit block",
+        |world| {
+            let idx = world.get::<EditorState>().selected_index;
+            let Some(OpenFile {
+                path,
+                format: FileFormat::Markdown { file, block },
+            }) = &mut world.get_mut::<AppState>().file
+            else {
+                return;
+            };
+            *block = Some(idx);
+            let (diagram, diagnostics) =
+                SequenceDiagram::from_mermaid_tolerant(&file.block_text(idx));
+            let diagnostics = diagnostics
+                .into_iter()
+                .map(|d| file.blocks[idx].locate(d))
+                .collect();
+            let status = format!("Editing diagram {} of {}", idx + 1, path.display());
+
+            world.get_mut::<AppState>().diagnostics = diagnostics;
+            *diagram_mut(world) = diagram;
+            mark_saved(world);
+            let editor = world.get_mut::<EditorState>();
+            editor.reset();
+            editor.set_status(status);
+            offer_recovery(world);
+            show_diagnostics(world);
+        },
+    );
+
+    kb.bind_many(
+        MARKDOWN_BLOCKS,
+        keys![KeyCode::Esc, 'q', KeyBinding::ctrl('c')],
+        "Quit",
+        |world| {
+            world.get_mut::<AppState>().should_quit = true;
+        },
+    );
+
+    kb.bind_many(
+        MARKDOWN_BLOCKS,
+        keys!['k', KeyCode::Up],
+        "Previous",
+        |world| {
+            let editor = world.get_mut::<EditorState>();
+            editor.selected_index = editor.selected_index.saturating_sub(1);
+        },
+    );
+
+    kb.bind_many(
+        MARKDOWN_BLOCKS,
+        keys!['j', KeyCode::Down],
+        "Next",
+        |world| {
+            let count = match &world.get::<AppState>().file {
+                Some(OpenFile {
+                    format: FileFormat::Markdown { file, .. },
+                    ..
+                }) => file.blocks.len(),
+                _ => 0,
+            };
+            let editor = world.get_mut::<EditorState>();
+            editor.selected_index = (editor.selected_index + 1).min(count.saturating_sub(1));
+        },
+    );
+}
+
+fn metadata_keybindings(world: &mut World) {
+    let kb = world.get_mut::<Keybindings>();
+
+    kb.bind_many(
+        METADATA,
+        keys![KeyCode::Enter, 'e'],
+        "Edit field",
+        |world| {
+            let field = MetadataField::ALL[world.get::<EditorState>().selected_index];
+            let value = field.value(&world.get::<SequenceDiagram>().metadata);
+            let editor = world.get_mut::<EditorState>();
+            editor.input_buffer = value;
+            editor.mode = EditorMode::InputMetadataField;
+        },
+    );
+
+    kb.bind_many(METADATA, keys![KeyCode::Esc, 'q', 'T'], "Close", |world| {
+        world.get_mut::<EditorState>().reset();
+    });
+
+    kb.bind_many(METADATA, keys!['k', KeyCode::Up], "Previous", |world| {
+        let editor = world.get_mut::<EditorState>();
+        editor.selected_index = editor.selected_index.saturating_sub(1);
+    });
+
+    kb.bind_many(METADATA, keys!['j', KeyCode::Down], "Next", |world| {
+        let editor = world.get_mut::<EditorState>();
+        editor.selected_index = (editor.selected_index + 1).min(MetadataField::ALL.len() - 1);
+    });
+}
+
+fn handle_input_confirm(world: &mut World) {
+    let mode = world.get::<EditorState>().mode.clone();
+    match mode {
+        EditorMode::InputParticipant => {
+            let name = world.get::<EditorState>().input_buffer.trim().to_string();
+            if !name.is_empty() {
+                diagram_mut(world).add_participant(Participant::from_mermaid_str(&name));
+            }
+            world.get_mut::<EditorState>().reset();
         }
         EditorMode::SelectFrom => {
             let selected = world.get::<EditorState>().selected_index;
@@ -698,10 +1487,12 @@ fn handle_input_confirm(world: &mut World) {
             if !text.is_empty()
                 && let (Some(from), Some(to)) = (editor_state.message_from, editor_state.message_to)
             {
-                let diagram = world.get_mut::<SequenceDiagram>();
+                let diagram = diagram_mut(world);
                 let event_idx = match editor_state.insert_after_index {
                     Some(usize::MAX) => {
-                        diagram.events.insert(0, Event::Message { from, to, text });
+                        if let Some(message) = diagram.message(from, to, text) {
+                            diagram.events.insert(0, message);
+                        }
                         0
                     }
                     Some(after_idx) => {
@@ -735,13 +1526,43 @@ fn handle_input_confirm(world: &mut World) {
         }
         EditorMode::RenameParticipant => {
             let editor_state = world.get::<EditorState>();
-            let name = editor_state.input_buffer.trim().to_string();
+            let id = editor_state.input_buffer.trim().to_string();
             let idx = editor_state.selected_index;
-            if !name.is_empty() {
-                let diagram = world.get_mut::<SequenceDiagram>();
-                if let Some(participant) = diagram.participants.get_mut(idx) {
-                    *participant = name;
-                }
+            if !id.is_empty() && !is_valid_id(&id) {
+                world
+                    .get_mut::<EditorState>()
+                    .set_status("Participant id can't contain arrows or any of :;,#@<=>");
+                return;
+            }
+            if world
+                .get::<SequenceDiagram>()
+                .participant_index(&id)
+                .is_some_and(|other| other != idx)
+            {
+                world
+                    .get_mut::<EditorState>()
+                    .set_status("Participant id already exists");
+                return;
+            }
+            let label = diagram_mut(world)
+                .participants
+                .get_mut(idx)
+                .map(|participant| {
+                    if !id.is_empty() {
+                        participant.id = id;
+                    }
+                    participant.label.clone().unwrap_or_default()
+                });
+            let editor = world.get_mut::<EditorState>();
+            editor.input_buffer = label.unwrap_or_default();
+            editor.mode = EditorMode::RenameParticipantLabel;
+        }
+        EditorMode::RenameParticipantLabel => {
+            let editor_state = world.get::<EditorState>();
+            let label = editor_state.input_buffer.trim().to_string();
+            let idx = editor_state.selected_index;
+            if let Some(participant) = diagram_mut(world).participants.get_mut(idx) {
+                participant.label = (!label.is_empty()).then_some(label);
             }
             world.get_mut::<EditorState>().reset();
         }
@@ -781,19 +1602,13 @@ fn handle_input_confirm(world: &mut World) {
                     editor_state.note_participant_end,
                 )
             {
-                let diagram = world.get_mut::<SequenceDiagram>();
+                let diagram = diagram_mut(world);
                 let position = editor_state.note_position;
                 let event_idx = match editor_state.insert_after_index {
                     Some(usize::MAX) => {
-                        diagram.events.insert(
-                            0,
-                            Event::Note {
-                                position,
-                                participant_start: start,
-                                participant_end: end,
-                                text,
-                            },
-                        );
+                        if let Some(note) = diagram.note(position, start, end, text) {
+                            diagram.events.insert(0, note);
+                        }
                         0
                     }
                     Some(after_idx) => {
@@ -842,19 +1657,27 @@ fn handle_input_confirm(world: &mut World) {
             let editor = world.get_mut::<EditorState>();
             editor.box_start = Some(selected);
             editor.mode = EditorMode::SelectBoxEnd;
-            editor.selected_index = selected;
+            // When editing, start from the current end so the range is only grown or shrunk
+            editor.selected_index = match (editor.editing_box, editor.box_end) {
+                (Some(_), Some(end)) => end.max(selected),
+                _ => selected,
+            };
         }
         EditorMode::SelectBoxEnd => {
             let selected = world.get::<EditorState>().selected_index;
             let editor = world.get_mut::<EditorState>();
             editor.box_end = Some(selected);
-            editor.box_color = BoxColor::default();
+            if editor.editing_box.is_none() {
+                editor.box_color = BoxColor::default();
+            }
             editor.mode = EditorMode::SelectBoxColor;
         }
         EditorMode::SelectBoxColor => {
             let editor = world.get_mut::<EditorState>();
             editor.mode = EditorMode::InputBoxLabel;
-            editor.input_buffer.clear();
+            if editor.editing_box.is_none() {
+                editor.input_buffer.clear();
+            }
         }
         EditorMode::InputBoxLabel => {
             let editor_state = world.get::<EditorState>().clone();
@@ -864,12 +1687,10 @@ fn handle_input_confirm(world: &mut World) {
                 if start > end {
                     std::mem::swap(&mut start, &mut end);
                 }
-                let ok = world.get_mut::<SequenceDiagram>().add_box(
-                    label,
-                    editor_state.box_color,
-                    start,
-                    end,
-                );
+                let ok = try_change(world, |diagram| match editor_state.editing_box {
+                    Some(b) => diagram.update_box(b, label, editor_state.box_color, start, end),
+                    None => diagram.add_box(label, editor_state.box_color, start, end),
+                });
                 if !ok {
                     world
                         .get_mut::<EditorState>()
@@ -878,22 +1699,197 @@ fn handle_input_confirm(world: &mut World) {
             }
             world.get_mut::<EditorState>().reset();
         }
+        EditorMode::SelectFragmentKind => {
+            let editor = world.get_mut::<EditorState>();
+            editor.mode = EditorMode::InputFragmentLabel;
+            editor.input_buffer.clear();
+        }
+        EditorMode::EditFragmentKind => {
+            world.get_mut::<EditorState>().mode = EditorMode::EditFragmentLabel;
+        }
+        EditorMode::InputFragmentLabel => {
+            let editor_state = world.get::<EditorState>().clone();
+            let label = editor_state.input_buffer.trim().to_string();
+            if let Some((start, end)) = editor_state.wrap_range {
+                let ok = try_change(world, |diagram| {
+                    diagram.wrap_in_fragment(start, end, editor_state.fragment_kind, label)
+                });
+                let editor = world.get_mut::<EditorState>();
+                if ok {
+                    editor.selection = Selection::Event(start);
+                    editor.range_anchor = None;
+                } else {
+                    editor.set_status("Fragments cannot overlap");
+                }
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::EditFragmentLabel => {
+            save_fragment_changes(world);
+        }
+        EditorMode::InputAutonumber => {
+            let input = world.get::<EditorState>().input_buffer.trim().to_string();
+            if input.is_empty() || input == "off" {
+                diagram_mut(world).autonumber = None;
+            } else if let Some(autonumber) = Autonumber::from_mermaid_args(&input) {
+                diagram_mut(world).autonumber = Some(autonumber);
+            } else {
+                world
+                    .get_mut::<EditorState>()
+                    .set_status("Autonumber needs a start and an optional step");
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::InputMetadataField => {
+            let field = MetadataField::ALL[world.get::<EditorState>().selected_index];
+            let input = world.get::<EditorState>().input_buffer.clone();
+            let result = field.set(&mut diagram_mut(world).metadata, &input);
+            let editor = world.get_mut::<EditorState>();
+            if let Err(e) = result {
+                editor.set_status(e.to_string());
+            }
+            editor.input_buffer.clear();
+            editor.mode = EditorMode::EditMetadata;
+        }
+        EditorMode::InputSavePath => {
+            let input = world.get::<EditorState>().input_buffer.trim().to_string();
+            if !input.is_empty() {
+                let path = PathBuf::from(&input);
+                let state = world.get::<AppState>();
+                let file = match &state.file {
+                    Some(file) => file.save_as(path),
+                    None => OpenFile::create(path),
+                };
+                let current = state.file.as_ref().map(|file| &file.path);
+                match file {
+                    Ok(file) if file.path.exists() && current != Some(&file.path) => {
+                        world.get_mut::<AppState>().overwrite = Some(file);
+                        world.get_mut::<EditorState>().reset();
+                        world.get_mut::<EditorState>().mode = EditorMode::ConfirmOverwrite;
+                        return;
+                    }
+                    Ok(file) => save(world, file),
+                    Err(e) => world.get_mut::<EditorState>().set_status(e.to_string()),
+                }
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::InputGotoMessage => {
+            let input = world.get::<EditorState>().input_buffer.trim().to_string();
+            let found = input
+                .parse()
+                .ok()
+                .and_then(|n| world.get::<SequenceDiagram>().find_message_number(n));
+            let editor = world.get_mut::<EditorState>();
+            if let Some(idx) = found {
+                editor.selection = Selection::Event(idx);
+                editor.range_anchor = None;
+            } else {
+                editor.set_status(format!("No message {input}"));
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::InputFragmentSection => {
+            let editor_state = world.get::<EditorState>().clone();
+            let label = editor_state.input_buffer.trim().to_string();
+            if let Some(after_idx) = editor_state.insert_after_index {
+                if try_change(world, |diagram| {
+                    diagram.add_fragment_section(after_idx, label)
+                }) {
+                    world.get_mut::<EditorState>().selection = Selection::Event(after_idx + 1);
+                } else {
+                    world
+                        .get_mut::<EditorState>()
+                        .set_status("Sections need an alt, par or critical fragment");
+                }
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::SelectHighlightColor => {
+            let editor_state = world.get::<EditorState>().clone();
+            if let Some((start, end)) = editor_state.wrap_range {
+                let color = editor_state.box_color.light();
+                let ok = try_change(world, |diagram| {
+                    diagram.wrap_in_highlight(start, end, color)
+                });
+                let editor = world.get_mut::<EditorState>();
+                if ok {
+                    editor.selection = Selection::Event(start);
+                    editor.range_anchor = None;
+                } else {
+                    editor.set_status("Highlights cannot cut through fragments");
+                }
+            }
+            reset_keeping_status(world);
+        }
+        EditorMode::SelectParticipantKind => {
+            let editor_state = world.get::<EditorState>();
+            let (idx, kind) = (editor_state.selected_index, editor_state.participant_kind);
+            if let Some(participant) = diagram_mut(world).participants.get_mut(idx) {
+                participant.kind = kind;
+            }
+            world.get_mut::<EditorState>().reset();
+        }
+        EditorMode::EditHighlightColor => {
+            let editor_state = world.get::<EditorState>().clone();
+            if let Some(idx) = editor_state.editing_event_index
+                && let Some(Event::HighlightStart { color }) =
+                    diagram_mut(world).events.get_mut(idx)
+            {
+                *color = editor_state.box_color.light();
+            }
+            world.get_mut::<EditorState>().reset();
+        }
         _ => {}
     }
 }
 
+/// Resets the editor mode without discarding a status message set by the confirmed action.
+fn reset_keeping_status(world: &mut World) {
+    let editor = world.get_mut::<EditorState>();
+    let status = editor.status_message.take();
+    editor.reset();
+    editor.status_message = status;
+}
+
+fn save_fragment_changes(world: &mut World) {
+    let editor_state = world.get::<EditorState>().clone();
+    let new_label = editor_state.input_buffer.trim().to_string();
+    if let Some(idx) = editor_state.editing_event_index {
+        match diagram_mut(world).events.get_mut(idx) {
+            Some(Event::FragmentStart { kind, label }) => {
+                *kind = editor_state.fragment_kind;
+                *label = new_label;
+            }
+            Some(Event::FragmentSection { label }) => {
+                *label = new_label;
+            }
+            _ => {}
+        }
+    }
+    world.get_mut::<EditorState>().reset();
+}
+
 fn save_event_changes(world: &mut World) {
     let editor_state = world.get::<EditorState>().clone();
     let text = editor_state.input_buffer.trim().to_string();
+    let diagram = world.get::<SequenceDiagram>();
+    let from = editor_state
+        .message_from
+        .and_then(|p| diagram.participant_at(p));
+    let to = editor_state
+        .message_to
+        .and_then(|p| diagram.participant_at(p));
     if let Some(idx) = editor_state.editing_event_index
-        && let (Some(from), Some(to)) = (editor_state.message_from, editor_state.message_to)
+        && let (Some(from), Some(to)) = (from, to)
         && !text.is_empty()
     {
-        let diagram = world.get_mut::<SequenceDiagram>();
+        let diagram = diagram_mut(world);
         if let Some(Event::Message {
             from: f,
             to: t,
             text: txt,
+            ..
         }) = diagram.events.get_mut(idx)
         {
             *f = from;
@@ -907,14 +1903,18 @@ fn save_event_changes(world: &mut World) {
 fn save_note_changes(world: &mut World) {
     let editor_state = world.get::<EditorState>().clone();
     let text = editor_state.input_buffer.trim().to_string();
+    let diagram = world.get::<SequenceDiagram>();
+    let start = editor_state
+        .note_participant_start
+        .and_then(|p| diagram.participant_at(p));
+    let end = editor_state
+        .note_participant_end
+        .and_then(|p| diagram.participant_at(p));
     if let Some(idx) = editor_state.editing_event_index
-        && let (Some(start), Some(end)) = (
-            editor_state.note_participant_start,
-            editor_state.note_participant_end,
-        )
+        && let (Some(start), Some(end)) = (start, end)
         && !text.is_empty()
     {
-        let diagram = world.get_mut::<SequenceDiagram>();
+        let diagram = diagram_mut(world);
         if let Some(Event::Note {
             position: pos,
             participant_start: s,
@@ -947,9 +1947,24 @@ pub fn active_widgets(world: &World) -> Vec<WidgetId> {
     let mode = &world.get::<EditorState>().mode;
     match mode {
         EditorMode::Normal | EditorMode::Help => vec![NORMAL],
-        EditorMode::ConfirmClear => vec![CONFIRM],
+        EditorMode::ConfirmClear
+        | EditorMode::ConfirmQuit
+        | EditorMode::ConfirmRestore
+        | EditorMode::ConfirmReload
+        | EditorMode::ConfirmOverwrite => {
+            vec![CONFIRM]
+        }
         EditorMode::SelectNotePosition | EditorMode::EditNotePosition => vec![SELECT_POSITION],
-        EditorMode::SelectBoxColor => vec![SELECT_BOX_COLOR],
+        EditorMode::SelectBoxColor
+        | EditorMode::SelectHighlightColor
+        | EditorMode::EditHighlightColor => vec![SELECT_BOX_COLOR],
+        EditorMode::SelectFragmentKind | EditorMode::EditFragmentKind => {
+            vec![SELECT_FRAGMENT_KIND]
+        }
+        EditorMode::SelectParticipantKind => vec![SELECT_PARTICIPANT_KIND],
+        EditorMode::Diagnostics => vec![DIAGNOSTICS],
+        EditorMode::EditMetadata => vec![METADATA],
+        EditorMode::SelectMarkdownBlock => vec![MARKDOWN_BLOCKS],
         m if m.is_selecting_participant() => vec![SELECT_PARTICIPANT],
         m if m.is_text_input() => vec![TEXT_INPUT],
         _ => vec![],
@@ -982,9 +1997,17 @@ pub fn render(frame: &mut Frame, world: &mut World) {
         | EditorMode::InputMessage
         | EditorMode::EditMessage
         | EditorMode::RenameParticipant
+        | EditorMode::RenameParticipantLabel
         | EditorMode::InputNoteText
         | EditorMode::EditNoteText
-        | EditorMode::InputBoxLabel => {
+        | EditorMode::InputBoxLabel
+        | EditorMode::InputFragmentLabel
+        | EditorMode::EditFragmentLabel
+        | EditorMode::InputFragmentSection
+        | EditorMode::InputAutonumber
+        | EditorMode::InputGotoMessage
+        | EditorMode::InputMetadataField
+        | EditorMode::InputSavePath => {
             render_input_popup(frame, world);
         }
         EditorMode::SelectFrom
@@ -1006,15 +2029,36 @@ pub fn render(frame: &mut Frame, world: &mut World) {
             let active = vec![NORMAL];
             render_help(frame, area, theme, keybindings, &active);
         }
-        EditorMode::ConfirmClear => {
+        EditorMode::ConfirmClear
+        | EditorMode::ConfirmQuit
+        | EditorMode::ConfirmRestore
+        | EditorMode::ConfirmReload
+        | EditorMode::ConfirmOverwrite => {
             render_confirm_dialog(frame, world);
         }
         EditorMode::SelectBoxStart | EditorMode::SelectBoxEnd => {
             render_box_participant_selector(frame, area, world);
         }
-        EditorMode::SelectBoxColor => {
+        EditorMode::SelectBoxColor
+        | EditorMode::SelectHighlightColor
+        | EditorMode::EditHighlightColor => {
             render_box_color_selector(frame, area, world);
         }
+        EditorMode::SelectFragmentKind | EditorMode::EditFragmentKind => {
+            render_fragment_kind_selector(frame, area, world);
+        }
+        EditorMode::SelectParticipantKind => {
+            render_participant_kind_selector(frame, area, world);
+        }
+        EditorMode::Diagnostics => {
+            render_diagnostics(frame, area, world);
+        }
+        EditorMode::EditMetadata => {
+            render_metadata(frame, area, world);
+        }
+        EditorMode::SelectMarkdownBlock => {
+            render_markdown_blocks(frame, area, world);
+        }
         EditorMode::Normal => {}
     }
 }
@@ -1111,7 +2155,8 @@ fn render_dual_participant_selector(frame: &mut Frame, area: Rect, world: &World
 
     let col_width = inner.width / 2;
 
-    for (i, name) in participants.iter().enumerate() {
+    for (i, participant) in participants.iter().enumerate() {
+        let name = participant.display_name();
         if i as u16 >= inner.height {
             break;
         }
@@ -1197,7 +2242,8 @@ fn render_note_participant_selector(frame: &mut Frame, area: Rect, world: &World
     let inner = block.inner(popup_area);
     frame.render_widget(block, popup_area);
 
-    for (i, name) in participants.iter().enumerate() {
+    for (i, participant) in participants.iter().enumerate() {
+        let name = participant.display_name();
         if i as u16 >= inner.height {
             break;
         }
@@ -1232,7 +2278,7 @@ fn render_note_position_selector(frame: &mut Frame, area: Rect, world: &World) {
     let participant_name = editor
         .note_participant_start
         .and_then(|i| diagram.participants.get(i))
-        .map_or("?", String::as_str);
+        .map_or("?", Participant::display_name);
 
     let positions = [
         (NotePosition::Right, "Right of"),
@@ -1294,10 +2340,11 @@ fn render_box_participant_selector(frame: &mut Frame, area: Rect, world: &World)
 
     frame.render_widget(ratatui::widgets::Clear, popup_area);
 
-    let title = if is_selecting_end {
-        " Box: End Participant "
-    } else {
-        " Box: Start Participant "
+    let title = match (editor.editing_box.is_some(), is_selecting_end) {
+        (true, true) => " Edit Box: End Participant ",
+        (true, false) => " Edit Box: Start Participant ",
+        (false, true) => " Box: End Participant ",
+        (false, false) => " Box: Start Participant ",
     };
     let block = Block::default()
         .title(title)
@@ -1307,7 +2354,8 @@ fn render_box_participant_selector(frame: &mut Frame, area: Rect, world: &World)
     let inner = block.inner(popup_area);
     frame.render_widget(block, popup_area);
 
-    for (i, name) in participants.iter().enumerate() {
+    for (i, participant) in participants.iter().enumerate() {
+        let name = participant.display_name();
         if i as u16 >= inner.height {
             break;
         }
@@ -1343,7 +2391,10 @@ fn render_box_color_selector(frame: &mut Frame, area: Rect, world: &World) {
     let theme = world.get::<Theme>();
 
     let current_color = editor.box_color;
-    let colors = BoxColor::all();
+    let mut colors = BoxColor::all().to_vec();
+    if !current_color.is_named() {
+        colors.push(current_color);
+    }
 
     let popup_width = 30.min(area.width.saturating_sub(4));
     let popup_height = (colors.len() as u16 + 4).min(area.height.saturating_sub(4));
@@ -1352,8 +2403,13 @@ fn render_box_color_selector(frame: &mut Frame, area: Rect, world: &World) {
 
     frame.render_widget(ratatui::widgets::Clear, popup_area);
 
+    let title = if editor.mode == EditorMode::SelectBoxColor {
+        " Box Color "
+    } else {
+        " Highlight Color "
+    };
     let block = Block::default()
-        .title(" Box Color ")
+        .title(title)
         .borders(Borders::ALL)
         .border_style(theme.border);
 
@@ -1378,7 +2434,7 @@ fn render_box_color_selector(frame: &mut Frame, area: Rect, world: &World) {
         let line = Line::from(vec![
             Span::raw(prefix),
             Span::styled("\u{25a0} ", Style::default().fg(swatch_color)),
-            Span::styled(color.as_mermaid_str(), name_style),
+            Span::styled(color.as_mermaid_string(), name_style),
         ]);
 
         frame.render_widget(
@@ -1408,20 +2464,437 @@ fn render_box_color_selector(frame: &mut Frame, area: Rect, world: &World) {
     }
 }
 
-fn box_swatch_color(color: BoxColor) -> ratatui::style::Color {
-    use ratatui::style::Color;
-    match color {
-        BoxColor::Blue => Color::Rgb(100, 150, 220),
-        BoxColor::Green => Color::Rgb(80, 180, 100),
-        BoxColor::Red => Color::Rgb(220, 80, 80),
-        BoxColor::Yellow => Color::Rgb(200, 180, 50),
-        BoxColor::Orange => Color::Rgb(220, 130, 50),
-        BoxColor::Purple => Color::Rgb(170, 80, 200),
+fn render_fragment_kind_selector(frame: &mut Frame, area: Rect, world: &World) {
+    let editor = world.get::<EditorState>();
+    let theme = world.get::<Theme>();
+
+    let current_kind = editor.fragment_kind;
+    let kinds = FragmentKind::all();
+
+    let popup_width = 30.min(area.width.saturating_sub(4));
+    let popup_height = (kinds.len() as u16 + 2).min(area.height.saturating_sub(4));
+
+    let popup_area = centered_rect(popup_width, popup_height, area);
+
+    frame.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Fragment ")
+        .borders(Borders::ALL)
+        .border_style(theme.border);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    for (i, kind) in kinds.iter().enumerate() {
+        let y = inner.y + i as u16;
+        if y >= inner.y + inner.height {
+            break;
+        }
+
+        let is_selected = *kind == current_kind;
+        let prefix = if is_selected { "▶ " } else { "  " };
+        let style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        frame.render_widget(
+            Paragraph::new(format!("{prefix}{}", kind.as_mermaid_str())).style(style),
+            Rect {
+                x: inner.x,
+                y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+}
+
+fn render_diagnostics(frame: &mut Frame, area: Rect, world: &World) {
+    let editor = world.get::<EditorState>();
+    let theme = world.get::<Theme>();
+    let diagnostics = &world.get::<AppState>().diagnostics;
+
+    let popup_width = 70.min(area.width.saturating_sub(4));
+    let popup_height = (diagnostics.len() as u16 + 4).min(area.height.saturating_sub(4));
+    let popup_area = centered_rect(popup_width, popup_height, area);
+
+    frame.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(" Import Diagnostics ({}) ", diagnostics.len()))
+        .borders(Borders::ALL)
+        .border_style(theme.border);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    // Keep the selected entry in view
+    let rows = inner.height.saturating_sub(2) as usize;
+    let first = editor.selected_index.saturating_sub(rows.saturating_sub(1));
+
+    for (row, (i, diagnostic)) in diagnostics
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .enumerate()
+    {
+        let is_selected = i == editor.selected_index;
+        let prefix = if is_selected { "▶ " } else { "  " };
+        let severity_style = match diagnostic.severity {
+            Severity::Error => theme.accent,
+            Severity::Warning => theme.muted,
+        };
+        let message_style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        let line = Line::from(vec![
+            Span::raw(prefix),
+            Span::styled(
+                format!("{}:{:<4} ", diagnostic.line, diagnostic.columns.start),
+                theme.muted,
+            ),
+            Span::styled(
+                format!("{}: ", diagnostic.severity.as_str()),
+                severity_style,
+            ),
+            Span::styled(diagnostic.message.as_str(), message_style),
+        ]);
+
+        frame.render_widget(
+            Paragraph::new(line),
+            Rect {
+                x: inner.x,
+                y: inner.y + row as u16,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+
+    if inner.height > 0 {
+        let hint_y = inner.y + inner.height - 1;
+        frame.render_widget(
+            Paragraph::new("Enter: go to event | Esc: close")
+                .style(theme.muted)
+                .alignment(Alignment::Right),
+            Rect {
+                x: inner.x,
+                y: hint_y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+}
+
+fn render_markdown_blocks(frame: &mut Frame, area: Rect, world: &World) {
+    let editor = world.get::<EditorState>();
+    let theme = world.get::<Theme>();
+    let Some(
+        source @ OpenFile {
+            format: FileFormat::Markdown { file, .. },
+            ..
+        },
+    ) = &world.get::<AppState>().file
+    else {
+        return;
+    };
+    let blocks = &file.blocks;
+
+    let popup_width = 60.min(area.width.saturating_sub(4));
+    let popup_height = (blocks.len() as u16 + 4).min(area.height.saturating_sub(4));
+    let popup_area = centered_rect(popup_width, popup_height, area);
+
+    frame.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(
+            " Sequence diagrams in {} ",
+            source.path.file_name().map_or_else(
+                || source.path.display().to_string(),
+                |name| name.to_string_lossy().to_string()
+            )
+        ))
+        .borders(Borders::ALL)
+        .border_style(theme.border);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    // Keep the selected entry in view
+    let rows = inner.height.saturating_sub(2) as usize;
+    let first = editor.selected_index.saturating_sub(rows.saturating_sub(1));
+
+    for (row, (i, markdown_block)) in blocks.iter().enumerate().skip(first).take(rows).enumerate() {
+        let is_selected = i == editor.selected_index;
+        let prefix = if is_selected { "▶ " } else { "  " };
+        let heading_style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        let line = Line::from(vec![
+            Span::raw(prefix),
+            Span::styled(format!("{}. ", i + 1), theme.muted),
+            Span::styled(
+                markdown_block.heading.as_deref().unwrap_or("(no heading)"),
+                heading_style,
+            ),
+            Span::styled(format!("  line {}", markdown_block.line), theme.muted),
+        ]);
+
+        frame.render_widget(
+            Paragraph::new(line),
+            Rect {
+                x: inner.x,
+                y: inner.y + row as u16,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+
+    if inner.height > 0 {
+        let hint_y = inner.y + inner.height - 1;
+        frame.render_widget(
+            Paragraph::new("Enter: edit | Esc: quit")
+                .style(theme.muted)
+                .alignment(Alignment::Right),
+            Rect {
+                x: inner.x,
+                y: hint_y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+}
+
+fn render_metadata(frame: &mut Frame, area: Rect, world: &World) {
+    let editor = world.get::<EditorState>();
+    let theme = world.get::<Theme>();
+    let metadata = &world.get::<SequenceDiagram>().metadata;
+
+    let popup_width = 60.min(area.width.saturating_sub(4));
+    let popup_height = (MetadataField::ALL.len() as u16 + 4).min(area.height.saturating_sub(4));
+    let popup_area = centered_rect(popup_width, popup_height, area);
+
+    frame.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Diagram Metadata ")
+        .borders(Borders::ALL)
+        .border_style(theme.border);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    for (i, field) in MetadataField::ALL.iter().enumerate() {
+        let y = inner.y + i as u16;
+        if y >= inner.y + inner.height {
+            break;
+        }
+
+        let is_selected = i == editor.selected_index;
+        let prefix = if is_selected { "▶ " } else { "  " };
+        let value_style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        // Multi-line values are shortened to their first line
+        let value = field.value(metadata);
+        let mut lines = value.lines();
+        let mut shown = lines.next().unwrap_or("").to_string();
+        if lines.next().is_some() {
+            shown.push_str(" …");
+        }
+
+        let line = Line::from(vec![
+            Span::raw(prefix),
+            Span::styled(format!("{:<24}", field.label()), theme.muted),
+            Span::styled(shown, value_style),
+        ]);
+
+        frame.render_widget(
+            Paragraph::new(line),
+            Rect {
+                x: inner.x,
+                y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+
+    if inner.height > MetadataField::ALL.len() as u16 {
+        let hint_y = inner.y + inner.height - 1;
+        frame.render_widget(
+            Paragraph::new("Enter: edit | Esc: close")
+                .style(theme.muted)
+                .alignment(Alignment::Right),
+            Rect {
+                x: inner.x,
+                y: hint_y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+}
+
+fn render_participant_kind_selector(frame: &mut Frame, area: Rect, world: &World) {
+    let editor = world.get::<EditorState>();
+    let theme = world.get::<Theme>();
+
+    let current_kind = editor.participant_kind;
+    let kinds = ParticipantKind::all();
+
+    let popup_width = 30.min(area.width.saturating_sub(4));
+    let popup_height = (kinds.len() as u16 + 4).min(area.height.saturating_sub(4));
+
+    let popup_area = centered_rect(popup_width, popup_height, area);
+
+    frame.render_widget(ratatui::widgets::Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Participant Type ")
+        .borders(Borders::ALL)
+        .border_style(theme.border);
+
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    for (i, kind) in kinds.iter().enumerate() {
+        let y = inner.y + i as u16;
+        if y >= inner.y + inner.height {
+            break;
+        }
+
+        let is_selected = *kind == current_kind;
+        let prefix = if is_selected { "▶ " } else { "  " };
+        let name_style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        let line = Line::from(vec![
+            Span::raw(prefix),
+            Span::styled(format!("{} ", participant_kind_glyph(*kind)), theme.muted),
+            Span::styled(kind.as_mermaid_str(), name_style),
+        ]);
+
+        frame.render_widget(
+            Paragraph::new(line),
+            Rect {
+                x: inner.x,
+                y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+
+    if inner.height > kinds.len() as u16 {
+        let hint_y = inner.y + inner.height - 1;
+        frame.render_widget(
+            Paragraph::new("Enter: confirm | Esc: cancel")
+                .style(theme.muted)
+                .alignment(Alignment::Right),
+            Rect {
+                x: inner.x,
+                y: hint_y,
+                width: inner.width,
+                height: 1,
+            },
+        );
+    }
+}
+
+fn participant_kind_glyph(kind: ParticipantKind) -> &'static str {
+    match kind {
+        ParticipantKind::Participant => "□",
+        ParticipantKind::Actor => "☺",
+        ParticipantKind::Boundary => "⊢",
+        ParticipantKind::Control => "↻",
+        ParticipantKind::Entity => "○",
+        ParticipantKind::Database => "▭",
+        ParticipantKind::Collections => "▣",
+        ParticipantKind::Queue => "⇉",
+    }
+}
+
+fn box_swatch_color(color: BoxColor) -> ratatui::style::Color {
+    use ratatui::style::Color;
+    match color {
+        BoxColor::Blue => Color::Rgb(100, 150, 220),
+        BoxColor::Green => Color::Rgb(80, 180, 100),
+        BoxColor::Red => Color::Rgb(220, 80, 80),
+        BoxColor::Yellow => Color::Rgb(200, 180, 50),
+        BoxColor::Orange => Color::Rgb(220, 130, 50),
+        BoxColor::Purple => Color::Rgb(170, 80, 200),
         BoxColor::Aqua => Color::Rgb(60, 190, 200),
         BoxColor::Gray => Color::Rgb(150, 150, 150),
+        BoxColor::Rgb(color) => Color::Rgb(color.r, color.g, color.b),
+        BoxColor::Transparent => Color::Reset,
+    }
+}
+
+/// Writes the diagram to `file`, which becomes the diagram's file if the save succeeds.
+fn save(world: &mut World, mut file: OpenFile) {
+    let view = view_state(world);
+    let backup = world.get::<AppState>().journal.backup(&file.path);
+    match file.save(world.get::<SequenceDiagram>(), view) {
+        Ok(()) => {
+            let mut status = format!("Saved to {}", file.path.display());
+            if let Err(e) = backup {
+                let _ = write!(status, " (backup failed: {e})");
+            }
+            let state = world.get_mut::<AppState>();
+            state.watch.reset(&file.path);
+            state.file = Some(file);
+            state.journal.discard();
+            mark_saved(world);
+            world.get_mut::<EditorState>().set_status(status);
+        }
+        Err(e) => {
+            world
+                .get_mut::<EditorState>()
+                .set_status(format!("Save failed: {e:#}"));
+        }
     }
 }
 
+fn start_save_as(world: &mut World) {
+    let path = world.get::<AppState>().file.as_ref().map_or_else(
+        || "diagram.mmd".to_string(),
+        |file| file.path.display().to_string(),
+    );
+    let editor = world.get_mut::<EditorState>();
+    editor.mode = EditorMode::InputSavePath;
+    editor.input_buffer = path;
+}
+
+fn export_text(world: &mut World, charset: Charset, path: &str) {
+    let status = match to_text(world.get::<SequenceDiagram>(), charset)
+        .and_then(|text| Ok(fs::write(path, text)?))
+    {
+        Ok(()) => format!("Exported to {path}"),
+        Err(e) => format!("Export failed: {e}"),
+    };
+    world.get_mut::<EditorState>().set_status(status);
+}
+
 fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
     let [area] = Layout::vertical([Constraint::Length(height)])
         .flex(Flex::Center)
@@ -0,0 +1,63 @@
+use std::fmt;
+use std::ops::Range;
+
+use super::models::EventId;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The statement could not be imported.
+    Error,
+    /// The statement is not supported but is kept as is.
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found while importing, located by its 1-based line and column span.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub severity: Severity,
+    pub line: usize,
+    /// Columns of the offending text, end exclusive.
+    pub columns: Range<usize>,
+    pub message: String,
+    /// The event closest to the offending line, if any.
+    pub event: Option<EventId>,
+}
+
+impl ParseError {
+    /// Formats the problem like a compiler message, quoting the offending line of `source`.
+    pub fn to_report(&self, path: &str, source: &str) -> String {
+        let text = source.split('\n').nth(self.line - 1).unwrap_or("");
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let gutter = " ".repeat(self.line.to_string().len());
+        let marker = format!(
+            "{}{}",
+            " ".repeat(self.columns.start - 1),
+            "^".repeat(self.columns.len().max(1))
+        );
+        format!(
+            "{}: {}\n{gutter}--> {path}:{}:{}\n{gutter} |\n{} | {text}\n{gutter} | {marker}\n",
+            self.severity.as_str(),
+            self.message,
+            self.line,
+            self.columns.start,
+            self.line,
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.columns.start, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,180 @@
+use super::models::Metadata;
+use anyhow::{Result, bail};
+
+/// Reads the YAML front matter between `---` lines, one line at a time. Only nested maps of
+/// scalar values are supported, which covers `title` and `config`.
+#[derive(Default)]
+pub(crate) struct FrontMatterReader {
+    /// Indentation and key of the maps enclosing the current line.
+    parents: Vec<(usize, String)>,
+}
+
+pub(crate) enum FrontMatterEntry {
+    /// A `key:` line opening a nested map.
+    Map,
+    /// A `key: value` line, with the dotted path of the key.
+    Value(String, String),
+}
+
+impl FrontMatterReader {
+    pub fn read(&mut self, line: &str) -> Result<FrontMatterEntry> {
+        let indent = line.len() - line.trim_start().len();
+        let Some((key, value)) = line.trim().split_once(':') else {
+            bail!("Expected 'key: value'");
+        };
+        let key = key.trim();
+        if key.is_empty() || key.starts_with('-') {
+            bail!("Only maps are supported in front matter");
+        }
+
+        while self.parents.last().is_some_and(|(i, _)| *i >= indent) {
+            self.parents.pop();
+        }
+        let mut path: Vec<&str> = self.parents.iter().map(|(_, k)| k.as_str()).collect();
+        path.push(key);
+        let path = path.join(".");
+
+        let value = value.trim();
+        if value.is_empty() {
+            self.parents.push((indent, key.to_string()));
+            Ok(FrontMatterEntry::Map)
+        } else {
+            Ok(FrontMatterEntry::Value(path, value.to_string()))
+        }
+    }
+}
+
+/// Writes the front matter of `metadata`, or nothing if there is none.
+pub(crate) fn write_front_matter(metadata: &Metadata) -> Vec<String> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    if let Some(title) = metadata
+        .title
+        .as_ref()
+        .filter(|_| metadata.title_in_front_matter)
+    {
+        entries.push(("title".to_string(), quote(title)));
+    }
+    entries.extend(metadata.extra.iter().cloned());
+    entries.extend(
+        metadata
+            .config
+            .iter()
+            .map(|(key, value)| (format!("config.{key}"), value.clone())),
+    );
+    if entries.is_empty() {
+        return Vec::new();
+    }
+
+    let entries: Vec<(Vec<&str>, &str)> = entries
+        .iter()
+        .map(|(key, value)| (key.split('.').collect(), value.as_str()))
+        .collect();
+    let mut lines = vec!["---".to_string()];
+    write_map(&entries, 0, &mut lines);
+    lines.push("---".to_string());
+    lines
+}
+
+/// Writes entries sharing a key prefix as one nested map, in order of first appearance.
+fn write_map(entries: &[(Vec<&str>, &str)], depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    let mut written: Vec<&str> = Vec::new();
+    for (path, value) in entries {
+        let key = path[0];
+        if path.len() == 1 {
+            lines.push(format!("{indent}{key}: {value}"));
+            continue;
+        }
+        if written.contains(&key) {
+            continue;
+        }
+        written.push(key);
+        lines.push(format!("{indent}{key}:"));
+        let children: Vec<(Vec<&str>, &str)> = entries
+            .iter()
+            .filter(|(p, _)| p.len() > 1 && p[0] == key)
+            .map(|(p, v)| (p[1..].to_vec(), *v))
+            .collect();
+        write_map(&children, depth + 1, lines);
+    }
+}
+
+/// Reads a YAML scalar, removing quotes.
+pub(crate) fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut text = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('r') => text.push('\r'),
+                Some(c) => text.push(c),
+                None => text.push('\\'),
+            }
+        }
+        text
+    } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].replace("''", "'")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes text as a YAML scalar, quoting it where plain text would be misread.
+fn quote(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text.trim() == text
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.contains('\n')
+        && !text.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ]);
+    if plain {
+        text.to_string()
+    } else {
+        format!(
+            "\"{}\"",
+            text.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_roundtrip() {
+        for text in [
+            "Login",
+            "Line one\nline \"two\"",
+            "C:\\path\\new",
+            "it's: here",
+        ] {
+            assert_eq!(unquote(&quote(text)), text);
+        }
+        assert_eq!(quote("a\nb"), "\"a\\nb\"");
+        assert_eq!(unquote("'it''s'"), "it's");
+    }
+
+    #[test]
+    fn test_front_matter_title_roundtrip() {
+        let mut diagram = crate::core::SequenceDiagram::new();
+        diagram.metadata.title = Some("Login\n\"happy\" path".to_string());
+        diagram.metadata.title_in_front_matter = true;
+
+        let mermaid = diagram.to_mermaid();
+        let reloaded = crate::core::SequenceDiagram::from_mermaid(&mermaid).unwrap();
+        assert_eq!(reloaded.metadata.title, diagram.metadata.title);
+        assert_eq!(reloaded.to_mermaid(), mermaid);
+    }
+}
@@ -0,0 +1,453 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+use super::models::{
+    Activation, Arrow, Autonumber, BoxColor, Event, FragmentKind, Lifecycle, Metadata,
+    NotePosition, Participant, ParticipantId, RgbColor,
+};
+use super::sequence::SequenceDiagram;
+
+/// The schema version written by [`SequenceDiagram::to_json`].
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upgrades a document by one schema version, in place.
+type Migration = fn(&mut Value) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+const _: () = assert!(MIGRATIONS.len() + 1 == SCHEMA_VERSION as usize);
+
+/// Whether a file is a JSON document, by its extension or its first character.
+pub fn is_json(path: &Path, content: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        || content.trim_start().starts_with('{')
+}
+
+/// Editor state saved with a JSON document, which Mermaid has no place for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Selected>,
+    /// The index of the first event shown.
+    pub scroll: usize,
+}
+
+/// The selected participant or event, by position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selected {
+    Participant(usize),
+    Event(usize),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    #[serde(default)]
+    metadata: Metadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    autonumber: Option<Autonumber>,
+    #[serde(default)]
+    participants: Vec<Participant>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    boxes: Vec<JsonBox>,
+    #[serde(default)]
+    events: Vec<JsonEvent>,
+    #[serde(default)]
+    view: ViewState,
+}
+
+/// A [`ParticipantBox`](super::models::ParticipantBox) by the positions of its first and last participant.
+#[derive(Serialize, Deserialize)]
+struct JsonBox {
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    color: BoxColor,
+    start: usize,
+    end: usize,
+}
+
+/// An [`Event`] that refers to participants by their Mermaid id.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonEvent {
+    Message {
+        from: String,
+        to: String,
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        arrow: Arrow,
+        #[serde(default, skip_serializing_if = "is_default")]
+        activation: Activation,
+        #[serde(default, skip_serializing_if = "is_default")]
+        lifecycle: Lifecycle,
+    },
+    Note {
+        #[serde(default)]
+        position: NotePosition,
+        /// One participant, or the two a note spans.
+        participants: Vec<String>,
+        #[serde(default)]
+        text: String,
+    },
+    Activation {
+        participant: String,
+        active: bool,
+    },
+    FragmentStart {
+        kind: FragmentKind,
+        #[serde(default)]
+        label: String,
+    },
+    FragmentSection {
+        #[serde(default)]
+        label: String,
+    },
+    FragmentEnd,
+    HighlightStart {
+        color: RgbColor,
+    },
+    HighlightEnd,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl SequenceDiagram {
+    /// Writes the diagram and the editor `view` as a versioned JSON document.
+    pub fn to_json(&self, view: ViewState) -> String {
+        let name = |id: ParticipantId| self.mermaid_id(id).unwrap_or_default().to_string();
+        let events = self
+            .events
+            .iter()
+            .map(|event| match event {
+                Event::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    activation,
+                    lifecycle,
+                } => JsonEvent::Message {
+                    from: name(*from),
+                    to: name(*to),
+                    text: text.clone(),
+                    arrow: *arrow,
+                    activation: *activation,
+                    lifecycle: *lifecycle,
+                },
+                Event::Note {
+                    position,
+                    participant_start,
+                    participant_end,
+                    text,
+                } => JsonEvent::Note {
+                    position: *position,
+                    participants: if participant_start == participant_end {
+                        vec![name(*participant_start)]
+                    } else {
+                        vec![name(*participant_start), name(*participant_end)]
+                    },
+                    text: text.clone(),
+                },
+                Event::Activation {
+                    participant,
+                    active,
+                } => JsonEvent::Activation {
+                    participant: name(*participant),
+                    active: *active,
+                },
+                Event::FragmentStart { kind, label } => JsonEvent::FragmentStart {
+                    kind: *kind,
+                    label: label.clone(),
+                },
+                Event::FragmentSection { label } => JsonEvent::FragmentSection {
+                    label: label.clone(),
+                },
+                Event::FragmentEnd => JsonEvent::FragmentEnd,
+                Event::HighlightStart { color } => JsonEvent::HighlightStart { color: *color },
+                Event::HighlightEnd => JsonEvent::HighlightEnd,
+            })
+            .collect();
+
+        let document = Document {
+            version: SCHEMA_VERSION,
+            metadata: self.metadata.clone(),
+            autonumber: self.autonumber,
+            participants: self.participants.iter().cloned().collect(),
+            boxes: self
+                .boxes
+                .iter()
+                .filter_map(|b| {
+                    let (start, end) = self.box_span(b)?;
+                    Some(JsonBox {
+                        label: b.label.clone(),
+                        color: b.color,
+                        start,
+                        end,
+                    })
+                })
+                .collect(),
+            events,
+            view,
+        };
+        let mut out = serde_json::to_string_pretty(&document).unwrap_or_default();
+        out.push('\n');
+        out
+    }
+
+    /// Reads a JSON document written by any schema version up to [`SCHEMA_VERSION`].
+    pub fn from_json(input: &str) -> Result<(Self, ViewState)> {
+        let mut value: Value = serde_json::from_str(input).context("Invalid JSON")?;
+        migrate(&mut value, MIGRATIONS)?;
+        let document: Document =
+            serde_json::from_value(value).context("Invalid diagram document")?;
+
+        let mut diagram = Self {
+            metadata: document.metadata,
+            autonumber: document.autonumber,
+            ..Self::default()
+        };
+        for participant in document.participants {
+            if diagram.participant_index(&participant.id).is_some() {
+                bail!("Duplicate participant '{}'", participant.id);
+            }
+            diagram.add_participant(participant);
+        }
+
+        let mut open_blocks = Vec::new();
+        for (idx, event) in document.events.into_iter().enumerate() {
+            let lookup = |id: &str| {
+                diagram
+                    .participant_index(id)
+                    .and_then(|i| diagram.participant_at(i))
+                    .with_context(|| format!("Event {idx} refers to unknown participant '{id}'"))
+            };
+            let event = match event {
+                JsonEvent::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    activation,
+                    lifecycle,
+                } => Event::Message {
+                    from: lookup(&from)?,
+                    to: lookup(&to)?,
+                    text,
+                    arrow,
+                    activation,
+                    lifecycle,
+                },
+                JsonEvent::Note {
+                    position,
+                    participants,
+                    text,
+                } => {
+                    let (start, end) = match participants.as_slice() {
+                        [one] => (one, one),
+                        [start, end] => (start, end),
+                        _ => bail!("Note at event {idx} must span one or two participants"),
+                    };
+                    Event::Note {
+                        position,
+                        participant_start: lookup(start)?,
+                        participant_end: lookup(end)?,
+                        text,
+                    }
+                }
+                JsonEvent::Activation {
+                    participant,
+                    active,
+                } => Event::Activation {
+                    participant: lookup(&participant)?,
+                    active,
+                },
+                JsonEvent::FragmentStart { kind, label } => {
+                    open_blocks.push(true);
+                    Event::FragmentStart { kind, label }
+                }
+                JsonEvent::FragmentSection { label } => {
+                    if open_blocks.last() != Some(&true) {
+                        bail!("Fragment section at event {idx} is outside a fragment");
+                    }
+                    Event::FragmentSection { label }
+                }
+                JsonEvent::FragmentEnd => {
+                    if open_blocks.pop() != Some(true) {
+                        bail!("Fragment end at event {idx} does not close a fragment");
+                    }
+                    Event::FragmentEnd
+                }
+                JsonEvent::HighlightStart { color } => {
+                    open_blocks.push(false);
+                    Event::HighlightStart { color }
+                }
+                JsonEvent::HighlightEnd => {
+                    if open_blocks.pop() != Some(false) {
+                        bail!("Highlight end at event {idx} does not close a highlight");
+                    }
+                    Event::HighlightEnd
+                }
+            };
+            diagram.events.push(event);
+        }
+        if !open_blocks.is_empty() {
+            bail!("Unclosed fragment or highlight");
+        }
+
+        for b in document.boxes {
+            if !diagram.add_box(b.label, b.color, b.start, b.end) {
+                bail!(
+                    "Box {}..={} is out of range or overlaps another box",
+                    b.start,
+                    b.end
+                );
+            }
+        }
+
+        Ok((diagram, document.view))
+    }
+}
+
+/// Upgrades `document` to the current schema version with `migrations`.
+fn migrate(document: &mut Value, migrations: &[Migration]) -> Result<()> {
+    let version = document
+        .get("version")
+        .and_then(Value::as_u64)
+        .context("Missing schema version")?;
+    let current = migrations.len() as u64 + 1;
+    if version == 0 {
+        bail!("Invalid schema version 0");
+    }
+    if version > current {
+        bail!("Schema version {version} is newer than the supported version {current}");
+    }
+    for (step, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        migration(document)
+            .with_context(|| format!("Failed to migrate from version {}", step + 1))?;
+        document["version"] = Value::from(step as u64 + 2);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_roundtrip() {
+        let input = "sequenceDiagram
+    title Round trip
+    autonumber 10 5
+    box rgba(10, 20, 30, 0.5) Back end
+        participant A as Alice
+        actor B
+    end
+    A-)+B: hi<br/>there
+    Note over A,B: both
+    activate A
+    par every minute
+        B--x-A: bye
+    and then
+        rect rgb(200, 220, 255)
+            create participant C
+            A->>C: go
+        end
+    end
+    destroy C
+    A->>C: stop";
+        let mut diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        // JSON does not keep the imported text, so compare against the canonical Mermaid.
+        diagram.source = None;
+        let view = ViewState {
+            selection: Some(Selected::Event(2)),
+            scroll: 1,
+        };
+        let json = diagram.to_json(view);
+        let (parsed, parsed_view) = SequenceDiagram::from_json(&json).unwrap();
+
+        assert_eq!(parsed_view, view);
+        assert_eq!(parsed.to_mermaid(), diagram.to_mermaid());
+        assert_eq!(parsed.boxes[0].color, diagram.boxes[0].color);
+        assert_eq!(parsed.to_json(view), json);
+    }
+
+    #[test]
+    fn test_json_written_by_hand() {
+        let json = r##"{
+            "version": 1,
+            "participants": [{ "id": "A" }, { "id": "DB", "kind": "database" }],
+            "boxes": [{ "color": "#ff000080", "start": 1, "end": 1 }],
+            "events": [
+                { "type": "message", "from": "A", "to": "DB", "text": "query" },
+                { "type": "note", "participants": ["DB"], "text": "slow" }
+            ],
+            "future_field": true
+        }"##;
+        let (diagram, view) = SequenceDiagram::from_json(json).unwrap();
+
+        assert_eq!(view, ViewState::default());
+        assert_eq!(
+            diagram.to_mermaid(),
+            "sequenceDiagram
+    participant A
+    box rgba(255, 0, 0, 0.5)
+        participant DB@{ \"type\": \"database\" }
+    end
+    A->>DB: query
+    Note right of DB: slow
+"
+        );
+    }
+
+    #[test]
+    fn test_json_invalid() {
+        let error = |json: &str| {
+            SequenceDiagram::from_json(json)
+                .map(|_| ())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(error("{}"), "Missing schema version");
+        assert_eq!(
+            error(r#"{ "version": 99 }"#),
+            "Schema version 99 is newer than the supported version 1"
+        );
+        assert_eq!(
+            error(r#"{ "version": 1, "events": [{ "type": "message", "from": "A", "to": "B" }] }"#),
+            "Event 0 refers to unknown participant 'A'"
+        );
+        assert_eq!(
+            error(r#"{ "version": 1, "events": [{ "type": "fragment_end" }] }"#),
+            "Fragment end at event 0 does not close a fragment"
+        );
+    }
+
+    #[test]
+    fn test_json_migrations() {
+        fn rename_people(document: &mut Value) -> Result<()> {
+            let Some(people) = document.as_object_mut().and_then(|d| d.remove("people")) else {
+                bail!("Missing 'people'");
+            };
+            document["participants"] = people;
+            Ok(())
+        }
+
+        let mut document: Value =
+            serde_json::from_str(r#"{ "version": 1, "people": [{ "id": "A" }] }"#).unwrap();
+        migrate(&mut document, &[rename_people]).unwrap();
+
+        assert_eq!(document["version"], 2);
+        assert_eq!(document["participants"][0]["id"], "A");
+    }
+}
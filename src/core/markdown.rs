@@ -0,0 +1,348 @@
+use anyhow::{Result, bail};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use super::diagnostic::ParseError;
+
+/// Whether a file is Markdown, by its extension.
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["md", "markdown", "mdx"].contains(&e.to_lowercase().as_str()))
+}
+
+/// A ```` ```mermaid ```` fence holding a sequence diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MermaidBlock {
+    /// The closest heading above the block, without its `#` marks.
+    pub heading: Option<String>,
+    /// The 1-based line of the first line inside the fence.
+    pub line: usize,
+    /// Byte range of the lines inside the fence.
+    body: Range<usize>,
+    /// Indentation of the fence, removed from the lines inside it.
+    indent: usize,
+}
+
+impl MermaidBlock {
+    /// Moves a diagnostic from the block's text to its place in the Markdown file.
+    pub fn locate(&self, mut diagnostic: ParseError) -> ParseError {
+        diagnostic.line += self.line - 1;
+        diagnostic.columns =
+            diagnostic.columns.start + self.indent..diagnostic.columns.end + self.indent;
+        diagnostic
+    }
+}
+
+/// Picks a block by its 1-based number or by the heading above it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockSelector {
+    Number(usize),
+    Heading(String),
+}
+
+impl BlockSelector {
+    pub fn parse(s: &str) -> Self {
+        match s.trim().parse() {
+            Ok(number) => Self::Number(number),
+            Err(_) => Self::Heading(s.trim().trim_start_matches('#').trim().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for BlockSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{number}"),
+            Self::Heading(heading) => write!(f, "'{heading}'"),
+        }
+    }
+}
+
+/// A Markdown file and the sequence diagrams fenced in it.
+#[derive(Clone, Debug)]
+pub struct MarkdownFile {
+    content: String,
+    pub blocks: Vec<MermaidBlock>,
+}
+
+impl MarkdownFile {
+    pub fn parse(content: String) -> Self {
+        let blocks = find_blocks(&content);
+        Self { content, blocks }
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Returns the index of the block picked by `selector`.
+    pub fn select(&self, selector: &BlockSelector) -> Result<usize> {
+        match selector {
+            BlockSelector::Number(number) => {
+                if *number == 0 || *number > self.blocks.len() {
+                    bail!(
+                        "No sequence diagram {number}, the file has {}",
+                        self.blocks.len()
+                    );
+                }
+                Ok(number - 1)
+            }
+            BlockSelector::Heading(heading) => {
+                let matches: Vec<usize> = (0..self.blocks.len())
+                    .filter(|&i| {
+                        self.blocks[i]
+                            .heading
+                            .as_ref()
+                            .is_some_and(|h| h.eq_ignore_ascii_case(heading))
+                    })
+                    .collect();
+                match matches.as_slice() {
+                    [] => bail!("No sequence diagram under the heading '{heading}'"),
+                    [idx] => Ok(*idx),
+                    _ => bail!(
+                        "{} sequence diagrams are under the heading '{heading}', choose one by number",
+                        matches.len()
+                    ),
+                }
+            }
+        }
+    }
+
+    /// The Mermaid text of block `idx`, with the fence's indentation removed.
+    pub fn block_text(&self, idx: usize) -> String {
+        let block = &self.blocks[idx];
+        self.content[block.body.clone()]
+            .split_inclusive('\n')
+            .map(|line| strip_indent(line, block.indent))
+            .collect()
+    }
+
+    /// Replaces the text of block `idx` with `mermaid`, leaving the rest of the file as is.
+    pub fn replace_block(&mut self, idx: usize, mermaid: &str) {
+        let block = &self.blocks[idx];
+        let newline = if self.content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let indent = " ".repeat(block.indent);
+        let mut body = String::new();
+        for line in mermaid.lines() {
+            if !line.is_empty() {
+                body.push_str(&indent);
+            }
+            body.push_str(line);
+            body.push_str(newline);
+        }
+        self.content.replace_range(block.body.clone(), &body);
+        self.blocks = find_blocks(&self.content);
+    }
+}
+
+/// An open code fence: its character, length and indentation.
+struct Fence {
+    marker: char,
+    len: usize,
+    indent: usize,
+}
+
+/// Reads a fence opening or closing line, returning the fence and the text after it.
+fn read_fence(line: &str) -> Option<(Fence, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    let info = &rest[len..];
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some((
+        Fence {
+            marker,
+            len,
+            indent,
+        },
+        info.trim(),
+    ))
+}
+
+/// Reads an ATX heading such as `## Login flow`.
+fn read_heading(line: &str) -> Option<String> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.len() - trimmed.trim_start_matches('#').len();
+    let text = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(text.is_empty() || text.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let text = text.trim();
+    let text = text.trim_end_matches('#');
+    Some(text.trim().to_string())
+}
+
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
+
+/// Whether the text of a mermaid block declares a sequence diagram, after its front matter
+/// and comments.
+fn is_sequence_diagram(text: &str) -> bool {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("%%"));
+    let mut line = lines.next();
+    if line == Some("---") {
+        line = lines
+            .by_ref()
+            .find(|l| *l == "---")
+            .and_then(|_| lines.next());
+    }
+    line.is_some_and(|l| l.starts_with("sequenceDiagram"))
+}
+
+fn find_blocks(content: &str) -> Vec<MermaidBlock> {
+    let mut blocks = Vec::new();
+    let mut heading = None;
+    // The open fence, whether it is a mermaid block, and where its body starts.
+    let mut open: Option<(Fence, bool, usize, usize)> = None;
+    let mut offset = 0;
+
+    for (number, line) in content.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+
+        match &open {
+            None => {
+                if let Some((fence, info)) = read_fence(text) {
+                    let language = info.split_whitespace().next().unwrap_or("");
+                    let is_mermaid = language.eq_ignore_ascii_case("mermaid");
+                    open = Some((fence, is_mermaid, offset, number + 2));
+                } else if let Some(text) = read_heading(text) {
+                    heading = Some(text);
+                }
+            }
+            Some((fence, is_mermaid, body_start, first_line)) => {
+                let closes = read_fence(text).is_some_and(|(closing, info)| {
+                    closing.marker == fence.marker && closing.len >= fence.len && info.is_empty()
+                });
+                if closes {
+                    if *is_mermaid {
+                        blocks.push(MermaidBlock {
+                            heading: heading.clone(),
+                            line: *first_line,
+                            body: *body_start..start,
+                            indent: fence.indent,
+                        });
+                    }
+                    open = None;
+                }
+            }
+        }
+    }
+    // An unclosed fence runs to the end of the file.
+    if let Some((fence, true, body_start, first_line)) = open {
+        blocks.push(MermaidBlock {
+            heading,
+            line: first_line,
+            body: body_start..content.len(),
+            indent: fence.indent,
+        });
+    }
+
+    blocks.retain(|block| is_sequence_diagram(&content[block.body.clone()]));
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SequenceDiagram;
+
+    const README: &str = "# Project
+
+Some text.
+
+```mermaid
+flowchart LR
+    A --> B
+```
+
+## Login
+
+```mermaid
+sequenceDiagram
+    Alice->>Bob: Hello
+```
+
+~~~rust
+// ```mermaid
+# not a heading
+~~~
+
+## Logout ##
+
+- Steps:
+  ```Mermaid
+  %% the logout flow
+  sequenceDiagram
+      Alice->>Bob: Bye
+  ```
+
+Trailing text.
+";
+
+    #[test]
+    fn test_markdown_blocks() {
+        let file = MarkdownFile::parse(README.to_string());
+
+        assert_eq!(file.blocks.len(), 2);
+        assert_eq!(file.blocks[0].heading.as_deref(), Some("Login"));
+        assert_eq!(file.blocks[0].line, 13);
+        assert_eq!(file.blocks[1].heading.as_deref(), Some("Logout"));
+        assert_eq!(
+            file.block_text(1),
+            "%% the logout flow\nsequenceDiagram\n    Alice->>Bob: Bye\n"
+        );
+
+        assert_eq!(file.select(&BlockSelector::parse("2")).unwrap(), 1);
+        assert_eq!(file.select(&BlockSelector::parse("## login")).unwrap(), 0);
+        assert!(file.select(&BlockSelector::parse("3")).is_err());
+        assert!(file.select(&BlockSelector::parse("Project")).is_err());
+    }
+
+    #[test]
+    fn test_markdown_replace_block() {
+        let mut file = MarkdownFile::parse(README.to_string());
+        let mut diagram = SequenceDiagram::from_mermaid(&file.block_text(1)).unwrap();
+        diagram.add_message(1, 0, "Ok".to_string());
+        let mermaid = diagram.to_mermaid();
+        file.replace_block(1, &mermaid);
+
+        assert_eq!(file.blocks.len(), 2);
+        assert_eq!(file.block_text(1), mermaid);
+        assert!(file.content().contains("      Bob->>Alice: Ok\n  ```\n"));
+        let (before, after) = README.split_once("  %% the logout flow").unwrap();
+        let after = &after[after.find("  ```").unwrap()..];
+        assert!(file.content().starts_with(before));
+        assert!(file.content().ends_with(after));
+
+        // An unchanged diagram writes back the same file.
+        let mut file = MarkdownFile::parse(README.to_string());
+        let diagram = SequenceDiagram::from_mermaid(&file.block_text(0)).unwrap();
+        file.replace_block(0, &diagram.to_mermaid());
+        assert_eq!(file.content(), README);
+    }
+}
@@ -1,5 +1,21 @@
+mod diagnostic;
+mod front_matter;
+mod json;
+mod markdown;
 mod models;
+mod plantuml;
 mod sequence;
+mod source;
+mod store;
+mod syntax;
 
-pub use models::{BoxColor, Event, NotePosition};
-pub use sequence::SequenceDiagram;
+pub use diagnostic::{ParseError, Severity};
+pub use json::{Selected, ViewState, is_json};
+pub use markdown::{BlockSelector, MarkdownFile, is_markdown};
+pub use models::{
+    Arrow, ArrowHead, Autonumber, BoxColor, Event, FragmentKind, Lifecycle, LineStyle, Metadata,
+    NotePosition, Participant, ParticipantKind, RgbColor, wrap_text,
+};
+pub use plantuml::is_plantuml;
+pub use sequence::{BlockBounds, SequenceDiagram};
+pub use syntax::is_valid_id;
@@ -1,4 +1,10 @@
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use super::store::StableId;
+use super::syntax::{encode_text, is_valid_id, sanitize_id};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NotePosition {
     #[default]
     Right,
@@ -32,30 +38,714 @@ impl NotePosition {
     }
 }
 
-#[derive(Clone)]
+/// Refers to a participant of a diagram however participants are reordered or renamed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParticipantId(u32);
+
+impl StableId for ParticipantId {
+    fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+}
+
+/// Refers to an event of a diagram however events are moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EventId(u32);
+
+impl StableId for EventId {
+    fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+}
+
+/// A participant, referenced in messages by `id` and shown by `label` if it has one
+/// (`participant API as Public API Gateway`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub kind: ParticipantKind,
+}
+
+impl Participant {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            label: None,
+            kind: ParticipantKind::default(),
+        }
+    }
+
+    pub fn with_label(id: impl Into<String>, label: impl Into<String>) -> Self {
+        let label = label.into();
+        Self {
+            label: (!label.is_empty()).then_some(label),
+            ..Self::new(id)
+        }
+    }
+
+    /// Parses `id` or `id as label`, as written after the `participant` keyword.
+    ///
+    /// A name that can't be written as an id is kept as the label of a sanitized id.
+    pub fn from_mermaid_str(s: &str) -> Self {
+        let (id, label) = match s.split_once(" as ") {
+            Some((id, label)) => (id.trim(), label.trim()),
+            None => (s.trim(), ""),
+        };
+        if id.is_empty() || is_valid_id(id) {
+            Self::with_label(id, label)
+        } else if label.is_empty() {
+            Self::with_label(sanitize_id(id), id)
+        } else {
+            Self::with_label(sanitize_id(id), label)
+        }
+    }
+
+    /// Writes the full declaration, e.g. `actor A as Alice` or
+    /// `participant DB@{ "type": "database" }`.
+    pub fn as_mermaid_string(&self) -> String {
+        let mut out = match self.kind {
+            ParticipantKind::Participant | ParticipantKind::Actor => {
+                format!("{} {}", self.kind.as_mermaid_str(), self.id)
+            }
+            kind => format!(
+                "participant {}@{{ \"type\": \"{}\" }}",
+                self.id,
+                kind.as_mermaid_str()
+            ),
+        };
+        if let Some(label) = &self.label {
+            out.push_str(" as ");
+            out.push_str(&encode_text(label));
+        }
+        out
+    }
+
+    /// The text shown in the participant header.
+    pub fn display_name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.id)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticipantKind {
+    #[default]
+    Participant,
+    Actor,
+    Boundary,
+    Control,
+    Entity,
+    Database,
+    Collections,
+    Queue,
+}
+
+impl ParticipantKind {
+    pub fn as_mermaid_str(self) -> &'static str {
+        match self {
+            Self::Participant => "participant",
+            Self::Actor => "actor",
+            Self::Boundary => "boundary",
+            Self::Control => "control",
+            Self::Entity => "entity",
+            Self::Database => "database",
+            Self::Collections => "collections",
+            Self::Queue => "queue",
+        }
+    }
+
+    pub fn from_mermaid_str(s: &str) -> Option<Self> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|kind| kind.as_mermaid_str() == s)
+    }
+
+    /// Reads the `type` field of a metadata block such as `{ "type": "database" }`.
+    pub fn from_metadata(s: &str) -> Option<Self> {
+        let inner = s.trim().strip_prefix('{')?.strip_suffix('}')?;
+        inner.split(',').find_map(|field| {
+            let (key, value) = field.split_once(':')?;
+            let unquote = |v: &str| v.trim().trim_matches('"').to_string();
+            if unquote(key) == "type" {
+                Self::from_mermaid_str(&unquote(value))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Participant => Self::Actor,
+            Self::Actor => Self::Boundary,
+            Self::Boundary => Self::Control,
+            Self::Control => Self::Entity,
+            Self::Entity => Self::Database,
+            Self::Database => Self::Collections,
+            Self::Collections => Self::Queue,
+            Self::Queue => Self::Participant,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        match self {
+            Self::Participant => Self::Queue,
+            Self::Actor => Self::Participant,
+            Self::Boundary => Self::Actor,
+            Self::Control => Self::Boundary,
+            Self::Entity => Self::Control,
+            Self::Database => Self::Entity,
+            Self::Collections => Self::Database,
+            Self::Queue => Self::Collections,
+        }
+    }
+
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Participant,
+            Self::Actor,
+            Self::Boundary,
+            Self::Control,
+            Self::Entity,
+            Self::Database,
+            Self::Collections,
+            Self::Queue,
+        ]
+    }
+}
+
+impl From<String> for Participant {
+    fn from(id: String) -> Self {
+        Self::new(id)
+    }
+}
+
+impl PartialEq<&str> for Participant {
+    fn eq(&self, other: &&str) -> bool {
+        self.id == *other
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    Dotted,
+}
+
+impl LineStyle {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Solid => Self::Dotted,
+            Self::Dotted => Self::Solid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrowHead {
+    #[default]
+    Arrow,
+    Open,
+    Cross,
+    Async,
+}
+
+impl ArrowHead {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Arrow => Self::Open,
+            Self::Open => Self::Cross,
+            Self::Cross => Self::Async,
+            Self::Async => Self::Arrow,
+        }
+    }
+}
+
+/// The arrow of a message, e.g. `->>` (solid with arrowhead) or `--x` (dotted with cross).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Arrow {
+    pub line: LineStyle,
+    pub head: ArrowHead,
+}
+
+impl Arrow {
+    /// All arrows, ordered so that longer mermaid tokens come before their prefixes.
+    const ALL: [Self; 8] = [
+        Self::new(LineStyle::Dotted, ArrowHead::Arrow),
+        Self::new(LineStyle::Solid, ArrowHead::Arrow),
+        Self::new(LineStyle::Dotted, ArrowHead::Cross),
+        Self::new(LineStyle::Solid, ArrowHead::Cross),
+        Self::new(LineStyle::Dotted, ArrowHead::Async),
+        Self::new(LineStyle::Solid, ArrowHead::Async),
+        Self::new(LineStyle::Dotted, ArrowHead::Open),
+        Self::new(LineStyle::Solid, ArrowHead::Open),
+    ];
+
+    pub const fn new(line: LineStyle, head: ArrowHead) -> Self {
+        Self { line, head }
+    }
+
+    pub fn as_mermaid_str(self) -> &'static str {
+        match (self.line, self.head) {
+            (LineStyle::Solid, ArrowHead::Arrow) => "->>",
+            (LineStyle::Dotted, ArrowHead::Arrow) => "-->>",
+            (LineStyle::Solid, ArrowHead::Open) => "->",
+            (LineStyle::Dotted, ArrowHead::Open) => "-->",
+            (LineStyle::Solid, ArrowHead::Cross) => "-x",
+            (LineStyle::Dotted, ArrowHead::Cross) => "--x",
+            (LineStyle::Solid, ArrowHead::Async) => "-)",
+            (LineStyle::Dotted, ArrowHead::Async) => "--)",
+        }
+    }
+
+    /// Finds the first arrow token in `s` and returns its byte position together with the arrow.
+    pub fn find_in(s: &str) -> Option<(usize, Self)> {
+        s.match_indices('-').find_map(|(pos, _)| {
+            Self::ALL
+                .into_iter()
+                .find(|arrow| s[pos..].starts_with(arrow.as_mermaid_str()))
+                .map(|arrow| (pos, arrow))
+        })
+    }
+}
+
+/// Activation shorthand on a message: `->>+` activates the receiver, `->>-` deactivates the sender.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    #[default]
+    None,
+    Activate,
+    Deactivate,
+}
+
+impl Activation {
+    pub fn as_mermaid_str(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Activate => "+",
+            Self::Deactivate => "-",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Activate,
+            Self::Activate => Self::Deactivate,
+            Self::Deactivate => Self::None,
+        }
+    }
+}
+
+/// Marks a message as creating or destroying one of its participants
+/// (`create participant X` / `destroy X` before the message).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    #[default]
+    None,
+    CreateTarget,
+    DestroyTarget,
+    DestroySender,
+}
+
+impl Lifecycle {
+    /// Returns the participant created by a message from `from` to `to`.
+    pub fn created<P>(self, to: P) -> Option<P> {
+        (self == Self::CreateTarget).then_some(to)
+    }
+
+    /// Returns the participant destroyed by a message from `from` to `to`.
+    pub fn destroyed<P>(self, from: P, to: P) -> Option<P> {
+        match self {
+            Self::DestroyTarget => Some(to),
+            Self::DestroySender => Some(from),
+            Self::None | Self::CreateTarget => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Event {
     Message {
-        from: usize,
-        to: usize,
+        from: ParticipantId,
+        to: ParticipantId,
         text: String,
+        arrow: Arrow,
+        activation: Activation,
+        lifecycle: Lifecycle,
     },
     Note {
         position: NotePosition,
-        participant_start: usize,
-        participant_end: usize,
+        participant_start: ParticipantId,
+        participant_end: ParticipantId,
         text: String,
     },
+    /// A standalone `activate X` or `deactivate X` statement.
+    Activation {
+        participant: ParticipantId,
+        active: bool,
+    },
+    /// Opens a control-flow fragment such as `loop` or `alt`.
+    FragmentStart { kind: FragmentKind, label: String },
+    /// Starts a new section of the enclosing fragment (`else`, `and` or `option`).
+    FragmentSection { label: String },
+    /// Closes the innermost open fragment.
+    FragmentEnd,
+    /// Opens a `rect` highlight region with a background colour.
+    HighlightStart { color: RgbColor },
+    /// Closes the innermost open highlight region.
+    HighlightEnd,
 }
 
 impl Event {
-    pub const fn height(&self) -> u16 {
+    /// Returns `true` for fragment and highlight markers, which structure events into blocks.
+    pub fn is_block_marker(&self) -> bool {
+        matches!(
+            self,
+            Self::FragmentStart { .. }
+                | Self::FragmentSection { .. }
+                | Self::FragmentEnd
+                | Self::HighlightStart { .. }
+                | Self::HighlightEnd
+        )
+    }
+
+    pub fn is_block_start(&self) -> bool {
+        matches!(
+            self,
+            Self::FragmentStart { .. } | Self::HighlightStart { .. }
+        )
+    }
+
+    pub fn is_block_end(&self) -> bool {
+        matches!(self, Self::FragmentEnd | Self::HighlightEnd)
+    }
+
+    /// Returns the participants referenced by this event.
+    pub fn participants(&self) -> Vec<ParticipantId> {
+        match self {
+            Self::Message { from, to, .. } => vec![*from, *to],
+            Self::Note {
+                participant_start,
+                participant_end,
+                ..
+            } => vec![*participant_start, *participant_end],
+            Self::Activation { participant, .. } => vec![*participant],
+            Self::FragmentStart { .. }
+            | Self::FragmentSection { .. }
+            | Self::FragmentEnd
+            | Self::HighlightStart { .. }
+            | Self::HighlightEnd => Vec::new(),
+        }
+    }
+
+    /// The number of rows the event takes with its text wrapped to `text_width` columns.
+    pub fn height(&self, text_width: u16) -> u16 {
+        match self {
+            Self::Message { text, .. } => wrap_text(text, text_width).len() as u16 + 2,
+            Self::Note { text, .. } => wrap_text(text, text_width).len() as u16 + 1,
+            Self::Activation { .. }
+            | Self::FragmentStart { .. }
+            | Self::FragmentSection { .. }
+            | Self::FragmentEnd
+            | Self::HighlightStart { .. }
+            | Self::HighlightEnd => 1,
+        }
+    }
+}
+
+/// Splits `text` at line breaks and wraps each line to `width` columns, breaking words that
+/// don't fit. A width of `0` only splits at line breaks.
+pub fn wrap_text(text: &str, width: u16) -> Vec<String> {
+    let width = usize::from(width);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in paragraph.split(' ') {
+            let word_width = word.chars().count();
+            if width > 0 && line_width > 0 && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            for c in word.chars() {
+                if width > 0 && line_width == width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push(c);
+                line_width += 1;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Automatic message numbering (`autonumber [start [step]]`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Autonumber {
+    pub start: u32,
+    pub step: u32,
+}
+
+impl Default for Autonumber {
+    fn default() -> Self {
+        Self { start: 1, step: 1 }
+    }
+}
+
+impl Autonumber {
+    /// Parses the arguments after `autonumber`. Returns `None` for malformed numbers.
+    pub fn from_mermaid_args(args: &str) -> Option<Self> {
+        let mut parts = args.split_whitespace();
+        let mut autonumber = Self::default();
+        if let Some(start) = parts.next() {
+            autonumber.start = start.parse().ok()?;
+        }
+        if let Some(step) = parts.next() {
+            autonumber.step = step.parse().ok()?;
+        }
+        parts.next().is_none().then_some(autonumber)
+    }
+
+    pub fn as_mermaid_string(self) -> String {
+        match (self.start, self.step) {
+            (1, 1) => "autonumber".to_string(),
+            (start, 1) => format!("autonumber {start}"),
+            (start, step) => format!("autonumber {start} {step}"),
+        }
+    }
+
+    /// The number of the `n`th message, counting from zero.
+    pub fn number(self, n: u32) -> u32 {
+        self.start.saturating_add(n.saturating_mul(self.step))
+    }
+}
+
+/// Diagram information that is not drawn as an event.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Whether the title is written in the YAML front matter instead of a `title` statement.
+    #[serde(skip_serializing_if = "is_false")]
+    pub title_in_front_matter: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acc_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acc_descr: Option<String>,
+    /// Front-matter `config` values by dotted key, e.g. `sequence.mirrorActors`, with the
+    /// value in YAML syntax.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub config: Vec<(String, String)>,
+    /// Other front-matter values, such as `displayMode`, kept as is.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentKind {
+    #[default]
+    Loop,
+    Alt,
+    Opt,
+    Par,
+    Critical,
+    Break,
+}
+
+impl FragmentKind {
+    pub fn as_mermaid_str(self) -> &'static str {
+        match self {
+            Self::Loop => "loop",
+            Self::Alt => "alt",
+            Self::Opt => "opt",
+            Self::Par => "par",
+            Self::Critical => "critical",
+            Self::Break => "break",
+        }
+    }
+
+    pub fn from_mermaid_str(s: &str) -> Option<Self> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|kind| kind.as_mermaid_str() == s)
+    }
+
+    /// The keyword that separates sections of this fragment, if it has any.
+    pub fn section_keyword(self) -> Option<&'static str> {
+        match self {
+            Self::Alt => Some("else"),
+            Self::Par => Some("and"),
+            Self::Critical => Some("option"),
+            Self::Loop | Self::Opt | Self::Break => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
         match self {
-            Self::Message { .. } => 3,
-            Self::Note { .. } => 2,
+            Self::Loop => Self::Alt,
+            Self::Alt => Self::Opt,
+            Self::Opt => Self::Par,
+            Self::Par => Self::Critical,
+            Self::Critical => Self::Break,
+            Self::Break => Self::Loop,
         }
     }
+
+    pub fn prev(self) -> Self {
+        match self {
+            Self::Loop => Self::Break,
+            Self::Alt => Self::Loop,
+            Self::Opt => Self::Alt,
+            Self::Par => Self::Opt,
+            Self::Critical => Self::Par,
+            Self::Break => Self::Critical,
+        }
+    }
+
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Loop,
+            Self::Alt,
+            Self::Opt,
+            Self::Par,
+            Self::Critical,
+            Self::Break,
+        ]
+    }
+}
+
+/// A colour written as `rgb(r, g, b)` or `rgba(r, g, b, a)`, as used by `rect` regions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct RgbColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity in `0..=255`, or `None` for the `rgb(...)` form.
+    pub alpha: Option<u8>,
+}
+
+impl RgbColor {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            r,
+            g,
+            b,
+            alpha: None,
+        }
+    }
+
+    pub fn from_mermaid_str(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            return Self::from_hex(hex);
+        }
+        let (args, has_alpha) = if let Some(rest) = s.strip_prefix("rgba(") {
+            (rest.strip_suffix(')')?, true)
+        } else {
+            (s.strip_prefix("rgb(")?.strip_suffix(')')?, false)
+        };
+        let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        if parts.len() != if has_alpha { 4 } else { 3 } {
+            return None;
+        }
+        let alpha = if has_alpha {
+            let a: f32 = parts[3].parse().ok()?;
+            if !(0.0..=1.0).contains(&a) {
+                return None;
+            }
+            Some((a * 255.0).round() as u8)
+        } else {
+            None
+        };
+        Some(Self {
+            r: parts[0].parse().ok()?,
+            g: parts[1].parse().ok()?,
+            b: parts[2].parse().ok()?,
+            alpha,
+        })
+    }
+
+    /// Reads `rgb`, `rrggbb` or `rrggbbaa` hex digits.
+    fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize, len: usize| {
+            let digits = &hex[i * len..(i + 1) * len];
+            let value = u8::from_str_radix(digits, 16).ok()?;
+            Some(if len == 1 { value * 17 } else { value })
+        };
+        let len = match hex.len() {
+            3 => 1,
+            6 | 8 => 2,
+            _ => return None,
+        };
+        Some(Self {
+            r: channel(0, len)?,
+            g: channel(1, len)?,
+            b: channel(2, len)?,
+            alpha: if hex.len() == 8 { channel(3, 2) } else { None },
+        })
+    }
+
+    /// Writes `#rrggbb`, or `#rrggbbaa` when the colour has an opacity.
+    pub fn as_hex_string(self) -> String {
+        let Self { r, g, b, alpha } = self;
+        match alpha {
+            None => format!("#{r:02x}{g:02x}{b:02x}"),
+            Some(a) => format!("#{r:02x}{g:02x}{b:02x}{a:02x}"),
+        }
+    }
+
+    pub fn as_mermaid_string(self) -> String {
+        let Self { r, g, b, alpha } = self;
+        match alpha {
+            None => format!("rgb({r}, {g}, {b})"),
+            Some(a) => {
+                let a = format!("{:.2}", f32::from(a) / 255.0);
+                let a = a.trim_end_matches('0').trim_end_matches('.');
+                format!("rgba({r}, {g}, {b}, {a})")
+            }
+        }
+    }
+}
+
+/// Written as hex, which unlike `rgba(...)` keeps the opacity exactly.
+impl Serialize for RgbColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_hex_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for RgbColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::from_mermaid_str(&s).ok_or_else(|| de::Error::custom(format!("invalid colour '{s}'")))
+    }
 }
 
+/// The colour of a participant box: one of the named palette, an arbitrary RGB(A) value or
+/// `transparent`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub enum BoxColor {
     #[default]
@@ -67,19 +757,23 @@ pub enum BoxColor {
     Purple,
     Aqua,
     Gray,
+    Rgb(RgbColor),
+    Transparent,
 }
 
 impl BoxColor {
-    pub fn as_mermaid_str(self) -> &'static str {
+    pub fn as_mermaid_string(self) -> String {
         match self {
-            Self::Blue => "Blue",
-            Self::Green => "Green",
-            Self::Red => "Red",
-            Self::Yellow => "Yellow",
-            Self::Orange => "Orange",
-            Self::Purple => "Purple",
-            Self::Aqua => "Aqua",
-            Self::Gray => "Gray",
+            Self::Blue => "Blue".to_string(),
+            Self::Green => "Green".to_string(),
+            Self::Red => "Red".to_string(),
+            Self::Yellow => "Yellow".to_string(),
+            Self::Orange => "Orange".to_string(),
+            Self::Purple => "Purple".to_string(),
+            Self::Aqua => "Aqua".to_string(),
+            Self::Gray => "Gray".to_string(),
+            Self::Rgb(color) => color.as_mermaid_string(),
+            Self::Transparent => "transparent".to_string(),
         }
     }
 
@@ -93,10 +787,52 @@ impl BoxColor {
             "purple" => Some(Self::Purple),
             "aqua" | "cyan" => Some(Self::Aqua),
             "gray" | "grey" => Some(Self::Gray),
-            _ => None,
+            "transparent" => Some(Self::Transparent),
+            _ => RgbColor::from_mermaid_str(s).map(Self::Rgb),
+        }
+    }
+
+    /// Splits a leading colour off a `box` header, returning the colour and the rest.
+    ///
+    /// `rgb(...)` and `rgba(...)` may contain spaces, so they extend to the closing parenthesis.
+    pub fn split_prefix(s: &str) -> Option<(Self, &str)> {
+        let s = s.trim_start();
+        let lower = s.to_ascii_lowercase();
+        let end = if lower.starts_with("rgb(") || lower.starts_with("rgba(") {
+            s.find(')')? + 1
+        } else {
+            s.find(char::is_whitespace).unwrap_or(s.len())
+        };
+        let color = Self::from_mermaid_str(&s[..end])?;
+        Some((color, s[end..].trim()))
+    }
+
+    /// A light shade of the colour, used for `rect` highlights picked from the palette and for
+    /// drawing on a white background.
+    pub fn light(self) -> RgbColor {
+        match self {
+            Self::Blue => RgbColor::new(191, 223, 255),
+            Self::Green => RgbColor::new(200, 240, 200),
+            Self::Red => RgbColor::new(255, 200, 200),
+            Self::Yellow => RgbColor::new(255, 245, 190),
+            Self::Orange => RgbColor::new(255, 220, 180),
+            Self::Purple => RgbColor::new(225, 200, 245),
+            Self::Aqua => RgbColor::new(190, 240, 240),
+            Self::Gray => RgbColor::new(220, 220, 220),
+            Self::Rgb(color) => color,
+            Self::Transparent => RgbColor {
+                alpha: Some(0),
+                ..RgbColor::default()
+            },
         }
     }
 
+    /// Whether this is one of the colours offered by the picker.
+    pub fn is_named(self) -> bool {
+        !matches!(self, Self::Rgb(_) | Self::Transparent)
+    }
+
+    /// The next named colour; custom colours move to the start of the palette.
     pub fn next(self) -> Self {
         match self {
             Self::Blue => Self::Green,
@@ -106,13 +842,14 @@ impl BoxColor {
             Self::Orange => Self::Purple,
             Self::Purple => Self::Aqua,
             Self::Aqua => Self::Gray,
-            Self::Gray => Self::Blue,
+            Self::Gray | Self::Rgb(_) | Self::Transparent => Self::Blue,
         }
     }
 
+    /// The previous named colour; custom colours move to the end of the palette.
     pub fn prev(self) -> Self {
         match self {
-            Self::Blue => Self::Gray,
+            Self::Blue | Self::Rgb(_) | Self::Transparent => Self::Gray,
             Self::Green => Self::Blue,
             Self::Red => Self::Green,
             Self::Yellow => Self::Red,
@@ -123,6 +860,7 @@ impl BoxColor {
         }
     }
 
+    /// The named palette.
     pub fn all() -> &'static [Self] {
         &[
             Self::Blue,
@@ -137,10 +875,34 @@ impl BoxColor {
     }
 }
 
+/// Written as the colour name, `transparent` or, for custom colours, hex.
+impl Serialize for BoxColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Rgb(color) => color.serialize(serializer),
+            color => serializer.serialize_str(&color.as_mermaid_string().to_lowercase()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BoxColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::from_mermaid_str(&s).ok_or_else(|| de::Error::custom(format!("invalid colour '{s}'")))
+    }
+}
+
+/// A box around the participants from `first` to `last` in display order. Members are kept
+/// by id, so moving participants never changes which ones a box holds.
 #[derive(Clone, Debug)]
 pub struct ParticipantBox {
     pub label: String,
     pub color: BoxColor,
-    pub start: usize,
-    pub end: usize,
+    pub first: ParticipantId,
+    pub last: ParticipantId,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !value
 }
@@ -0,0 +1,1153 @@
+use std::fmt::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use super::diagnostic::{ParseError, Severity};
+use super::models::{
+    Activation, Arrow, ArrowHead, Autonumber, BoxColor, Event, FragmentKind, Lifecycle, LineStyle,
+    NotePosition, Participant, ParticipantId, ParticipantKind, RgbColor,
+};
+use super::sequence::SequenceDiagram;
+use super::syntax::sanitize_id;
+
+/// Statements PlantUML draws that have no counterpart here, by their first word or, for
+/// symbols, by how the line starts.
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("==", "Dividers are not supported, ignored"),
+    ("...", "Delays are not supported, ignored"),
+    ("|||", "Spacers are not supported, ignored"),
+    ("!", "Preprocessor directives are not supported, ignored"),
+    ("ref", "References are not supported, ignored"),
+    ("return", "'return' is not supported, ignored"),
+    ("autoactivate", "'autoactivate' is not supported, ignored"),
+    ("skinparam", "Skin parameters are not supported, ignored"),
+    ("hide", "'hide' is not supported, ignored"),
+    ("show", "'show' is not supported, ignored"),
+    ("newpage", "Page breaks are not supported, ignored"),
+    ("header", "Headers are not supported, ignored"),
+    ("footer", "Footers are not supported, ignored"),
+    ("legend", "Legends are not supported, ignored"),
+];
+
+/// File extensions used for PlantUML sources.
+const EXTENSIONS: &[&str] = &["puml", "plantuml", "pu", "iuml", "wsd"];
+
+/// Whether a file is PlantUML rather than Mermaid, by its extension or its `@startuml` line.
+pub fn is_plantuml(path: &Path, content: &str) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    if extension.is_some_and(|e| EXTENSIONS.contains(&e.as_str())) {
+        return true;
+    }
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('\''))
+        .is_some_and(|line| line.starts_with("@startuml"))
+}
+
+impl SequenceDiagram {
+    /// Parses a PlantUML sequence diagram, failing on the first statement it cannot import.
+    ///
+    /// Unsupported statements are dropped with a warning and don't fail the import.
+    pub fn from_plantuml(input: &str) -> Result<Self, ParseError> {
+        let (diagram, diagnostics) = Self::from_plantuml_tolerant(input);
+        match diagnostics
+            .into_iter()
+            .find(|d| d.severity == Severity::Error)
+        {
+            Some(error) => Err(error),
+            None => Ok(diagram),
+        }
+    }
+
+    /// Parses everything it understands and reports the rest. Unlike Mermaid, PlantUML
+    /// statements that cannot be imported are not kept.
+    pub fn from_plantuml_tolerant(input: &str) -> (Self, Vec<ParseError>) {
+        let mut parser = PlantUmlParser::default();
+        let mut started = false;
+        let mut ended = false;
+        let mut block_comment = false;
+
+        for (number, line) in input.split('\n').enumerate() {
+            let trimmed = line.trim();
+            parser.line = number + 1;
+            parser.columns = trimmed_columns(line);
+
+            if block_comment {
+                block_comment = !trimmed.ends_with("'/");
+                continue;
+            }
+            if trimmed.starts_with("/'") {
+                block_comment = trimmed.len() < 4 || !trimmed.ends_with("'/");
+                continue;
+            }
+            if trimmed.is_empty() || trimmed.starts_with('\'') {
+                continue;
+            }
+            if !started {
+                started = true;
+                if trimmed.starts_with("@startuml") {
+                    continue;
+                }
+                parser.report(Severity::Error, "First line must be '@startuml'");
+            }
+            if trimmed.starts_with("@enduml") {
+                ended = true;
+                break;
+            }
+            if parser.note.is_some() {
+                parser.note_line(trimmed);
+                continue;
+            }
+
+            match parser.statement(trimmed) {
+                Ok(true) => {}
+                Ok(false) => parser.report(Severity::Warning, "Unsupported statement, ignored"),
+                Err(error) => parser.report(Severity::Error, error.to_string()),
+            }
+        }
+
+        if !started {
+            parser.line = 1;
+            parser.columns = 1..1;
+            parser.report(Severity::Error, "First line must be '@startuml'");
+        } else if !ended {
+            parser.report(Severity::Warning, "Missing '@enduml'");
+        }
+        if let Some((.., at)) = parser.note.take() {
+            parser.report_at(at, Severity::Error, "Unclosed note");
+        }
+        if let Some((.., at)) = parser.current_box.take() {
+            parser.report_at(at, Severity::Error, "Unclosed box");
+        }
+        if let Some((_, at)) = parser.pending_create.take() {
+            parser.report_at(
+                at,
+                Severity::Error,
+                "'create' must be followed by a message",
+            );
+        }
+        while let Some((block, at)) = parser.open_blocks.pop() {
+            if let Some(kind) = block {
+                let keyword = kind.as_mermaid_str();
+                parser.report_at(at, Severity::Error, format!("Unclosed '{keyword}' block"));
+                parser.diagram.events.push(Event::FragmentEnd);
+            }
+        }
+
+        let PlantUmlParser {
+            diagram,
+            diagnostics,
+            ..
+        } = parser;
+        let diagnostics = diagram.locate_events(diagnostics);
+        (diagram, diagnostics)
+    }
+
+    /// Writes the diagram as PlantUML. Highlights have no PlantUML form and are written as
+    /// comments; open arrowheads become plain arrows.
+    pub fn to_plantuml(&self) -> String {
+        let mut lines = vec!["@startuml".to_string()];
+        if let Some(title) = &self.metadata.title {
+            lines.push(format!("title {}", encode(title)));
+        }
+        if let Some(autonumber) = self.autonumber {
+            lines.push(autonumber.as_mermaid_string());
+        }
+
+        let created: Vec<ParticipantId> = self
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Message { to, lifecycle, .. } => lifecycle.created(*to),
+                _ => None,
+            })
+            .collect();
+        let declare = |lines: &mut Vec<String>, idx: usize, indent: &str| {
+            if let (Some(participant), Some(id)) =
+                (self.participants.get(idx), self.participants.id(idx))
+                && !created.contains(&id)
+            {
+                lines.push(format!("{indent}{}", declaration(participant)));
+            }
+        };
+        let mut i = 0;
+        while i < self.participants.len() {
+            if let Some((b, (_, end))) = self
+                .boxes
+                .iter()
+                .filter_map(|b| Some((b, self.box_span(b)?)))
+                .find(|(_, (start, _))| *start == i)
+            {
+                let mut header = "box".to_string();
+                if !b.label.is_empty() {
+                    let _ = write!(header, " \"{}\"", quoted(&b.label));
+                }
+                let _ = write!(header, " {}", box_color(b.color));
+                lines.push(header);
+                for j in i..=end {
+                    declare(&mut lines, j, "  ");
+                }
+                lines.push("end box".to_string());
+                i = end + 1;
+            } else {
+                declare(&mut lines, i, "");
+                i += 1;
+            }
+        }
+
+        let name = |id: ParticipantId| self.mermaid_id(id).map(plantuml_name);
+        let mut depth = 0;
+        for event in &self.events {
+            let indent = "  ".repeat(depth);
+            match event {
+                Event::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    activation,
+                    lifecycle,
+                } => {
+                    if let Some(participant) = lifecycle
+                        .created(*to)
+                        .and_then(|p| self.participants.by_id(p))
+                    {
+                        lines.push(format!("{indent}create {}", declaration(participant)));
+                    }
+                    let (Some(from_name), Some(to_name)) = (name(*from), name(*to)) else {
+                        continue;
+                    };
+                    let line = match arrow.line {
+                        LineStyle::Solid => "-",
+                        LineStyle::Dotted => "--",
+                    };
+                    let head = match arrow.head {
+                        ArrowHead::Arrow | ArrowHead::Open => ">",
+                        ArrowHead::Async => ">>",
+                        ArrowHead::Cross => ">x",
+                    };
+                    let mut markers = String::new();
+                    match activation {
+                        Activation::None => {}
+                        Activation::Activate => markers.push_str(" ++"),
+                        Activation::Deactivate => markers.push_str(" --"),
+                    }
+                    if *lifecycle == Lifecycle::DestroyTarget {
+                        markers.push_str(" !!");
+                    }
+                    let text = if text.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" : {}", encode(text))
+                    };
+                    lines.push(format!(
+                        "{indent}{from_name} {line}{head} {to_name}{markers}{text}"
+                    ));
+                    if *lifecycle == Lifecycle::DestroySender {
+                        lines.push(format!("{indent}destroy {from_name}"));
+                    }
+                }
+                Event::Activation {
+                    participant,
+                    active,
+                } => {
+                    if let Some(name) = name(*participant) {
+                        let keyword = if *active { "activate" } else { "deactivate" };
+                        lines.push(format!("{indent}{keyword} {name}"));
+                    }
+                }
+                Event::Note {
+                    position,
+                    participant_start,
+                    participant_end,
+                    text,
+                } => {
+                    let participants = if participant_start == participant_end {
+                        name(*participant_start)
+                    } else {
+                        name(*participant_start)
+                            .zip(name(*participant_end))
+                            .map(|(start, end)| format!("{start}, {end}"))
+                    };
+                    if let Some(participants) = participants {
+                        lines.push(format!(
+                            "{indent}note {} {participants} : {}",
+                            position.as_str(),
+                            encode(text)
+                        ));
+                    }
+                }
+                Event::FragmentStart { kind, label } => {
+                    lines.push(with_label(&indent, kind.as_mermaid_str(), label));
+                    depth += 1;
+                }
+                Event::FragmentSection { label } => {
+                    let indent = "  ".repeat(depth.saturating_sub(1));
+                    lines.push(with_label(&indent, "else", label));
+                }
+                Event::HighlightStart { color } => {
+                    lines.push(format!("{indent}' rect {}", color.as_mermaid_string()));
+                }
+                Event::FragmentEnd => {
+                    depth = depth.saturating_sub(1);
+                    lines.push(format!("{}end", "  ".repeat(depth)));
+                }
+                Event::HighlightEnd => lines.push(format!("{indent}' end rect")),
+            }
+        }
+
+        lines.push("@enduml".to_string());
+        lines.join("\n") + "\n"
+    }
+}
+
+/// State of an import in progress.
+#[derive(Default)]
+struct PlantUmlParser {
+    diagram: SequenceDiagram,
+    /// Problems found so far, with the index of the event they are nearest to.
+    diagnostics: Vec<(ParseError, usize)>,
+    /// The current line number and its columns.
+    line: usize,
+    columns: Range<usize>,
+    current_box: Option<(BoxColor, String, usize, Location)>,
+    /// Open blocks, innermost last; `None` is a `group`, which is imported without its frame.
+    open_blocks: Vec<(Option<FragmentKind>, Location)>,
+    /// A participant declared with `create`, waiting for its message.
+    pending_create: Option<(ParticipantId, Location)>,
+    /// A multi-line note being read: its position, participants and lines.
+    note: Option<(
+        NotePosition,
+        ParticipantId,
+        ParticipantId,
+        Vec<String>,
+        Location,
+    )>,
+}
+
+/// A line number, its columns and the number of events before it.
+type Location = (usize, Range<usize>, usize);
+
+impl PlantUmlParser {
+    fn location(&self) -> Location {
+        (self.line, self.columns.clone(), self.diagram.events.len())
+    }
+
+    fn report(&mut self, severity: Severity, message: impl Into<String>) {
+        self.report_at(self.location(), severity, message);
+    }
+
+    fn report_at(
+        &mut self,
+        (line, columns, event): Location,
+        severity: Severity,
+        message: impl Into<String>,
+    ) {
+        let diagnostic = ParseError {
+            severity,
+            line,
+            columns,
+            message: message.into(),
+            event: None,
+        };
+        self.diagnostics.push((diagnostic, event));
+    }
+
+    /// Reports a construct that is recognised but cannot be imported.
+    fn unsupported(&mut self, message: &str) {
+        self.report(Severity::Warning, message);
+    }
+
+    /// Reads a line of a multi-line note, which ends at `end note`.
+    fn note_line(&mut self, trimmed: &str) {
+        let closing = trimmed.to_lowercase();
+        let closing: Vec<&str> = closing.split_whitespace().collect();
+        if matches!(
+            closing.as_slice(),
+            ["end", "note" | "hnote" | "rnote"] | ["endnote" | "endhnote" | "endrnote"]
+        ) {
+            if let Some((position, start, end, lines, _)) = self.note.take() {
+                self.diagram.events.push(Event::Note {
+                    position,
+                    participant_start: start,
+                    participant_end: end,
+                    text: lines.join("\n"),
+                });
+            }
+        } else if let Some((.., lines, _)) = &mut self.note {
+            lines.push(decode(trimmed));
+        }
+    }
+
+    /// Parses a statement, returning whether it is supported.
+    fn statement(&mut self, trimmed: &str) -> Result<bool> {
+        let (keyword, rest) = trimmed
+            .split_once(char::is_whitespace)
+            .map_or((trimmed, ""), |(k, r)| (k, r.trim()));
+        let keyword = keyword.to_lowercase();
+
+        if let Some((_, message)) = UNSUPPORTED.iter().find(|(prefix, _)| {
+            if prefix.starts_with(char::is_alphabetic) {
+                keyword == *prefix
+            } else {
+                trimmed.starts_with(prefix)
+            }
+        }) {
+            self.unsupported(message);
+            return Ok(true);
+        }
+
+        match keyword.as_str() {
+            "title" => {
+                if rest.is_empty() {
+                    bail!("Multi-line titles are not supported");
+                }
+                self.diagram.metadata.title = Some(decode(rest));
+                self.diagram.metadata.title_in_front_matter = false;
+                return Ok(true);
+            }
+            "autonumber" => {
+                if rest == "stop" {
+                    self.diagram.autonumber = None;
+                } else if let Some(autonumber) = Autonumber::from_mermaid_args(rest) {
+                    self.diagram.autonumber = Some(autonumber);
+                } else {
+                    bail!("Only 'autonumber [start [step]]' is supported");
+                }
+                return Ok(true);
+            }
+            "create" => {
+                let (kind, declaration) = rest
+                    .split_once(char::is_whitespace)
+                    .and_then(|(word, declaration)| {
+                        Some((participant_kind(&word.to_lowercase())?, declaration))
+                    })
+                    .unwrap_or((ParticipantKind::default(), rest));
+                let id = self.declare(kind, declaration)?;
+                self.pending_create = Some((id, self.location()));
+                return Ok(true);
+            }
+            "activate" | "deactivate" => {
+                if rest.is_empty() {
+                    bail!("Invalid activation syntax");
+                }
+                let (name, _) = read_name(rest);
+                let participant = self.diagram.ensure_participant(&sanitize_id(&name));
+                self.diagram.events.push(Event::Activation {
+                    participant,
+                    active: keyword == "activate",
+                });
+                return Ok(true);
+            }
+            "destroy" => {
+                let (name, _) = read_name(rest);
+                return self.destroy(&name);
+            }
+            "note" | "hnote" | "rnote" => return self.note(rest),
+            "box" => {
+                let (label, color) = match rest.rfind('#') {
+                    Some(pos) if !rest[pos..].contains('"') => {
+                        (rest[..pos].trim(), Some(&rest[pos + 1..]))
+                    }
+                    _ => (rest, None),
+                };
+                let color = color.map_or_else(BoxColor::default, |color| {
+                    plantuml_color(color).unwrap_or_else(|| {
+                        self.report(
+                            Severity::Warning,
+                            format!("Unknown box colour '{color}', using the default"),
+                        );
+                        BoxColor::default()
+                    })
+                });
+                let label = decode(&unquote(label));
+                let start = self.diagram.participants.len();
+                self.current_box = Some((color, label, start, self.location()));
+                return Ok(true);
+            }
+            "else" => {
+                let expected = self.open_blocks.last().and_then(|(block, _)| *block);
+                if expected.and_then(FragmentKind::section_keyword).is_none() {
+                    bail!("Unexpected 'else'");
+                }
+                self.diagram.events.push(Event::FragmentSection {
+                    label: decode(rest),
+                });
+                return Ok(true);
+            }
+            "group" => {
+                self.open_blocks.push((None, self.location()));
+                self.unsupported("Groups are not supported, only their contents are imported");
+                return Ok(true);
+            }
+            "end" => return self.end(&rest.to_lowercase()),
+            _ => {}
+        }
+
+        if let Some(kind) = participant_kind(&keyword) {
+            self.declare(kind, rest)?;
+            return Ok(true);
+        }
+        if let Some(kind) = FragmentKind::from_mermaid_str(&keyword) {
+            self.open_blocks.push((Some(kind), self.location()));
+            self.diagram.events.push(Event::FragmentStart {
+                kind,
+                label: decode(rest),
+            });
+            return Ok(true);
+        }
+
+        match split_message(trimmed) {
+            Some(message) => {
+                self.message(message?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Declares a participant, returning its id.
+    fn declare(&mut self, kind: ParticipantKind, declaration: &str) -> Result<ParticipantId> {
+        let (name, rest) = read_name(declaration);
+        if name.is_empty() {
+            bail!("Invalid participant declaration");
+        }
+        let mut rest = rest.trim();
+        let mut id = name.clone();
+        let mut label = None;
+        if let Some(alias) = rest.strip_prefix("as ") {
+            let alias = alias.trim_start();
+            let (alias_name, after) = read_name(alias);
+            // The display name is the quoted side, or the first one if neither is quoted
+            if alias.starts_with('"') && !declaration.trim_start().starts_with('"') {
+                label = Some(alias_name);
+            } else {
+                id = alias_name;
+                label = Some(name);
+            }
+            rest = after.trim();
+        }
+        if !rest.is_empty() {
+            self.report(
+                Severity::Warning,
+                format!("'{rest}' in participant declaration is not supported, ignored"),
+            );
+        }
+
+        let sanitized = sanitize_id(&id);
+        if sanitized != id && label.is_none() {
+            label = Some(id.clone());
+        }
+        let participant = self.diagram.ensure_participant(&sanitized);
+        if let Some(existing) = self.diagram.participants.by_id_mut(participant) {
+            existing.kind = kind;
+            if let Some(label) = label.filter(|label| *label != sanitized) {
+                existing.label = Some(decode(&label));
+            }
+        }
+        Ok(participant)
+    }
+
+    fn note(&mut self, rest: &str) -> Result<bool> {
+        let (position, after) = if let Some(after) = rest.strip_prefix("left of ") {
+            (NotePosition::Left, after)
+        } else if let Some(after) = rest.strip_prefix("right of ") {
+            (NotePosition::Right, after)
+        } else if let Some(after) = rest.strip_prefix("over ") {
+            (NotePosition::Over, after)
+        } else if rest.starts_with("left") || rest.starts_with("right") {
+            self.unsupported("Notes attached to messages are not supported, ignored");
+            return Ok(true);
+        } else if rest.starts_with("across") {
+            self.unsupported("Notes across all participants are not supported, ignored");
+            return Ok(true);
+        } else {
+            bail!("Invalid note position");
+        };
+
+        let (participants, text) = match after.split_once(':') {
+            Some((participants, text)) => (participants, Some(decode(text.trim()))),
+            None => (after, None),
+        };
+        // Drop a trailing colour such as `#lightblue`
+        let participants = participants.split('#').next().unwrap_or_default();
+        let names: Vec<String> = participants
+            .split(',')
+            .map(|name| read_name(name.trim()).0)
+            .collect();
+        let (start, end) = match names.as_slice() {
+            [name] if !name.is_empty() => (name, name),
+            [start, end] if position == NotePosition::Over => (start, end),
+            _ => bail!("Invalid note participants"),
+        };
+        let start = self.diagram.ensure_participant(&sanitize_id(start));
+        let end = self.diagram.ensure_participant(&sanitize_id(end));
+
+        match text {
+            Some(text) => {
+                self.diagram.events.push(Event::Note {
+                    position,
+                    participant_start: start,
+                    participant_end: end,
+                    text,
+                });
+            }
+            None => self.note = Some((position, start, end, Vec::new(), self.location())),
+        }
+        Ok(true)
+    }
+
+    fn end(&mut self, rest: &str) -> Result<bool> {
+        match rest {
+            "box" => {
+                let Some((color, label, start, _)) = self.current_box.take() else {
+                    bail!("Unexpected 'end box'");
+                };
+                let end = self.diagram.participants.len().saturating_sub(1);
+                if self.diagram.participants.len() > start {
+                    self.diagram.add_box(label, color, start, end);
+                }
+            }
+            "" => match self.open_blocks.pop() {
+                Some((Some(_), _)) => {
+                    self.diagram.events.push(Event::FragmentEnd);
+                }
+                Some((None, _)) => {}
+                None => bail!("Unexpected 'end'"),
+            },
+            _ => bail!("Unexpected 'end {rest}'"),
+        }
+        Ok(true)
+    }
+
+    /// Marks the previous message as destroying `name`.
+    fn destroy(&mut self, name: &str) -> Result<bool> {
+        if name.is_empty() {
+            bail!("Invalid destroy syntax");
+        }
+        let id = self.diagram.ensure_participant(&sanitize_id(name));
+        if let Some(Event::Message {
+            from,
+            to,
+            lifecycle: lifecycle @ Lifecycle::None,
+            ..
+        }) = self
+            .diagram
+            .events
+            .len()
+            .checked_sub(1)
+            .and_then(|last| self.diagram.events.get_mut(last))
+        {
+            if *to == id {
+                *lifecycle = Lifecycle::DestroyTarget;
+                return Ok(true);
+            }
+            if *from == id {
+                *lifecycle = Lifecycle::DestroySender;
+                return Ok(true);
+            }
+        }
+        bail!("'destroy {name}' must follow a message involving it");
+    }
+
+    fn message(&mut self, message: PlantUmlMessage) {
+        if message.both_ways {
+            self.report(
+                Severity::Warning,
+                "Two-headed arrows are not supported, imported as one-headed",
+            );
+        }
+        if message.unknown_head {
+            self.report(
+                Severity::Warning,
+                "This arrowhead is not supported, imported as a plain arrow",
+            );
+        }
+        let from = self.diagram.ensure_participant(&sanitize_id(&message.from));
+        let to = self.diagram.ensure_participant(&sanitize_id(&message.to));
+
+        let mut lifecycle = Lifecycle::None;
+        let mut activation = Activation::None;
+        for marker in message.markers {
+            match marker {
+                "++" => activation = Activation::Activate,
+                "--" => activation = Activation::Deactivate,
+                "**" => lifecycle = Lifecycle::CreateTarget,
+                _ => lifecycle = Lifecycle::DestroyTarget,
+            }
+        }
+        if let Some((created, at)) = self.pending_create.take() {
+            if created == to {
+                lifecycle = Lifecycle::CreateTarget;
+            } else {
+                self.report_at(
+                    at,
+                    Severity::Error,
+                    "'create' must be followed by a message to the created participant",
+                );
+            }
+        }
+
+        self.diagram.events.push(Event::Message {
+            from,
+            to,
+            text: message.text,
+            arrow: message.arrow,
+            activation,
+            lifecycle,
+        });
+    }
+}
+
+/// A message statement such as `Alice -> Bob ++ : Hello`.
+struct PlantUmlMessage {
+    from: String,
+    to: String,
+    arrow: Arrow,
+    /// `++`, `--`, `**` and `!!` after the target.
+    markers: Vec<&'static str>,
+    text: String,
+    both_ways: bool,
+    unknown_head: bool,
+}
+
+/// Splits a message statement, or returns `None` if the statement has no arrow.
+fn split_message(statement: &str) -> Option<Result<PlantUmlMessage>> {
+    let (head, text) = match split_outside_quotes(statement, ':') {
+        Some((head, text)) => (head, decode(text.trim())),
+        None => (statement, String::new()),
+    };
+    if head.trim_start().starts_with('[') {
+        return Some(Err(anyhow::anyhow!(
+            "Messages from outside the diagram ('[->') are not supported"
+        )));
+    }
+
+    let (left, rest) = read_name(head);
+    let rest = rest.trim_start();
+    let (arrow, rest) = read_arrow(rest)?;
+    let rest = rest.trim_start();
+    if rest.starts_with(']') {
+        return Some(Err(anyhow::anyhow!(
+            "Messages to outside the diagram ('->]') are not supported"
+        )));
+    }
+    let (right, rest) = read_name(rest);
+    if left.is_empty() || right.is_empty() {
+        return None;
+    }
+
+    let mut markers = Vec::new();
+    for marker in rest.split_whitespace() {
+        let mut marker = marker;
+        while !marker.is_empty() {
+            let known = ["++", "--", "**", "!!"]
+                .into_iter()
+                .find(|m| marker.starts_with(m))?;
+            markers.push(known);
+            marker = &marker[2..];
+        }
+    }
+
+    let (from, to) = if arrow.reversed {
+        (right, left)
+    } else {
+        (left, right)
+    };
+    Some(Ok(PlantUmlMessage {
+        from,
+        to,
+        arrow: Arrow {
+            line: arrow.line,
+            head: arrow.head,
+        },
+        markers,
+        text,
+        both_ways: arrow.both_ways,
+        unknown_head: arrow.unknown_head,
+    }))
+}
+
+struct ArrowTokens {
+    line: LineStyle,
+    head: ArrowHead,
+    /// The arrow points from right to left, as in `A <- B`.
+    reversed: bool,
+    both_ways: bool,
+    unknown_head: bool,
+}
+
+/// Reads an arrow such as `->`, `-->>`, `<-`, `->x` or `-[#red]>`.
+fn read_arrow(s: &str) -> Option<(ArrowTokens, &str)> {
+    let mut rest = s;
+    let left = ["<<", "x<", "<", "\\\\", "\\", "//", "/", "x", "o"]
+        .into_iter()
+        .find(|head| rest.starts_with(head) && rest[head.len()..].starts_with('-'))
+        .unwrap_or("");
+    rest = &rest[left.len()..];
+
+    let mut dashes = 0;
+    loop {
+        if let Some(after) = rest.strip_prefix('-') {
+            dashes += 1;
+            rest = after;
+        } else if rest.starts_with('[') && dashes > 0 {
+            // Arrow styles such as `-[#red]>` only change how the arrow is drawn
+            rest = &rest[rest.find(']')? + 1..];
+        } else {
+            break;
+        }
+    }
+    if dashes == 0 {
+        return None;
+    }
+
+    // A head must end at whitespace, or `A ->x B` could not be told from `A -> xB`
+    let right = [">>", ">x", ">", "\\\\", "\\", "//", "/", "x", "o"]
+        .into_iter()
+        .find(|head| {
+            rest.strip_prefix(head).is_some_and(|after| {
+                after.is_empty()
+                    || after.starts_with(char::is_whitespace)
+                    || !head.ends_with(['x', 'o'])
+            })
+        })
+        .unwrap_or("");
+    rest = &rest[right.len()..];
+
+    let reversed = right.is_empty() && !left.is_empty();
+    let (head, tail) = if reversed {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    let (head, unknown_head) = match head {
+        ">" | "<" => (ArrowHead::Arrow, false),
+        ">>" | "<<" => (ArrowHead::Async, false),
+        ">x" | "x<" | "x" => (ArrowHead::Cross, false),
+        "" => (ArrowHead::Open, false),
+        _ => (ArrowHead::Arrow, true),
+    };
+    Some((
+        ArrowTokens {
+            line: if dashes > 1 {
+                LineStyle::Dotted
+            } else {
+                LineStyle::Solid
+            },
+            head,
+            reversed,
+            both_ways: !tail.is_empty(),
+            unknown_head,
+        },
+        rest,
+    ))
+}
+
+/// Reads a participant name, quoted or up to the next space, arrow or colon.
+fn read_name(s: &str) -> (String, &str) {
+    let s = s.trim_start();
+    if let Some(quoted) = s.strip_prefix('"') {
+        return match quoted.find('"') {
+            Some(end) => (quoted[..end].to_string(), &quoted[end + 1..]),
+            None => (quoted.to_string(), ""),
+        };
+    }
+    let end = s
+        .find(|c: char| c.is_whitespace() || matches!(c, '-' | '<' | '>' | ':' | ',' | '#'))
+        .unwrap_or(s.len());
+    (s[..end].to_string(), &s[end..])
+}
+
+fn split_outside_quotes(s: &str, separator: char) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (pos, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => return Some((&s[..pos], &s[pos + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn participant_kind(keyword: &str) -> Option<ParticipantKind> {
+    ParticipantKind::from_mermaid_str(keyword)
+}
+
+/// Reads a colour such as `Blue`, `transparent` or `AABBCC`, written after `#`.
+fn plantuml_color(color: &str) -> Option<BoxColor> {
+    BoxColor::from_mermaid_str(color)
+        .filter(|color| !matches!(color, BoxColor::Rgb(_)))
+        .or_else(|| RgbColor::from_mermaid_str(&format!("#{color}")).map(BoxColor::Rgb))
+}
+
+fn box_color(color: BoxColor) -> String {
+    match color {
+        BoxColor::Rgb(RgbColor { r, g, b, .. }) => format!("#{r:02X}{g:02X}{b:02X}"),
+        color => format!("#{}", color.as_mermaid_string()),
+    }
+}
+
+/// The declaration of a participant, such as `actor "Alice Smith" as Alice`.
+fn declaration(participant: &Participant) -> String {
+    let kind = participant.kind.as_mermaid_str();
+    let id = plantuml_name(&participant.id);
+    match &participant.label {
+        Some(label) => format!("{kind} \"{}\" as {id}", quoted(label)),
+        None => format!("{kind} {id}"),
+    }
+}
+
+/// A participant id, quoted unless it is a plain word.
+fn plantuml_name(id: &str) -> String {
+    if id.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        id.to_string()
+    } else {
+        format!("\"{}\"", quoted(id))
+    }
+}
+
+fn with_label(indent: &str, keyword: &str, label: &str) -> String {
+    if label.is_empty() {
+        format!("{indent}{keyword}")
+    } else {
+        format!("{indent}{keyword} {}", encode(label))
+    }
+}
+
+/// PlantUML strings can't contain double quotes.
+fn quoted(text: &str) -> String {
+    encode(text).replace('"', "'")
+}
+
+fn unquote(s: &str) -> String {
+    s.trim()
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s.trim())
+        .to_string()
+}
+
+/// Reads `\n` escapes as line breaks.
+fn decode(text: &str) -> String {
+    text.replace("\\n", "\n")
+}
+
+fn encode(text: &str) -> String {
+    text.replace('\n', "\\n")
+}
+
+/// The 1-based columns of `line` without surrounding whitespace.
+fn trimmed_columns(line: &str) -> Range<usize> {
+    let start = line.chars().take_while(|c| c.is_whitespace()).count() + 1;
+    start..start + line.trim().chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plantuml_import() {
+        let input = "@startuml
+title Checkout
+autonumber 10 5
+box \"Front\" #Aqua
+  actor \"Alice Smith\" as Alice
+  participant Web
+end box
+database DB #lightblue
+Alice -> Web ++ : order\\ntwice
+Web --> DB : query
+DB ->> Web
+Web <-- DB : late
+Web ->x Web
+alt cached
+  note over Web, DB : hit
+else miss
+  note left of DB
+    slow
+    path
+  end note
+end
+create participant Cache
+Web -> Cache
+Web -> DB
+destroy DB
+@enduml";
+        let (diagram, diagnostics) = SequenceDiagram::from_plantuml_tolerant(input);
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![(
+                8,
+                "'#lightblue' in participant declaration is not supported, ignored"
+            )]
+        );
+
+        assert_eq!(diagram.metadata.title.as_deref(), Some("Checkout"));
+        assert_eq!(diagram.autonumber, Some(Autonumber { start: 10, step: 5 }));
+        let participants: Vec<_> = diagram
+            .participants
+            .iter()
+            .map(|p| (p.id.as_str(), p.display_name(), p.kind))
+            .collect();
+        assert_eq!(
+            participants,
+            vec![
+                ("Alice", "Alice Smith", ParticipantKind::Actor),
+                ("Web", "Web", ParticipantKind::Participant),
+                ("DB", "DB", ParticipantKind::Database),
+                ("Cache", "Cache", ParticipantKind::Participant),
+            ]
+        );
+        assert_eq!(
+            diagram.box_span(&diagram.boxes[0]).unwrap(),
+            (0, 1),
+            "box covers the participants declared in it"
+        );
+        assert_eq!(diagram.boxes[0].color, BoxColor::Aqua);
+
+        let Event::Message {
+            text, activation, ..
+        } = &diagram.events[0]
+        else {
+            panic!("expected a message");
+        };
+        assert_eq!(
+            (text.as_str(), *activation),
+            ("order\ntwice", Activation::Activate)
+        );
+        let arrows: Vec<_> = diagram
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Message {
+                    from, to, arrow, ..
+                } => Some((
+                    diagram.position_of(*from).unwrap(),
+                    diagram.position_of(*to).unwrap(),
+                    arrow.line,
+                    arrow.head,
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            arrows[1..5],
+            [
+                (1, 2, LineStyle::Dotted, ArrowHead::Arrow),
+                (2, 1, LineStyle::Solid, ArrowHead::Async),
+                (2, 1, LineStyle::Dotted, ArrowHead::Arrow),
+                (1, 1, LineStyle::Solid, ArrowHead::Cross),
+            ]
+        );
+        assert!(matches!(
+            &diagram.events[8],
+            Event::Note { position: NotePosition::Left, text, .. } if text == "slow\npath"
+        ));
+        let lifecycles: Vec<_> = diagram
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Message { lifecycle, .. } if *lifecycle != Lifecycle::None => {
+                    Some(*lifecycle)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            lifecycles,
+            vec![Lifecycle::CreateTarget, Lifecycle::DestroyTarget]
+        );
+    }
+
+    #[test]
+    fn test_plantuml_roundtrip() {
+        let input = "sequenceDiagram
+    title Round trip
+    box rgb(10, 20, 30) Back end
+        participant A as Alice
+        actor B
+    end
+    A-)+B: hi<br/>there
+    B--x-A: bye
+    par one
+        Note right of A: n
+    and two
+        create participant C
+        A->>C: go
+    end
+    destroy C
+    A->>C: stop";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        let plantuml = diagram.to_plantuml();
+        let (parsed, diagnostics) = SequenceDiagram::from_plantuml_tolerant(&plantuml);
+
+        assert_eq!(diagnostics, vec![], "{plantuml}");
+        assert_eq!(parsed.participants, diagram.participants);
+        assert_eq!(parsed.events, diagram.events);
+        assert_eq!(parsed.metadata.title, diagram.metadata.title);
+        assert_eq!(parsed.boxes[0].color, diagram.boxes[0].color);
+        assert_eq!(parsed.boxes[0].label, "Back end");
+    }
+
+    #[test]
+    fn test_plantuml_diagnostics() {
+        let input = "participant A
+A -> B : hi
+[-> A : in
+== Phase 2 ==
+note left : attached
+loop
+A -> B
+@enduml";
+        let (diagram, diagnostics) = SequenceDiagram::from_plantuml_tolerant(input);
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Severity::Error, 1, "First line must be '@startuml'"),
+                (
+                    Severity::Error,
+                    3,
+                    "Messages from outside the diagram ('[->') are not supported"
+                ),
+                (Severity::Warning, 4, "Dividers are not supported, ignored"),
+                (
+                    Severity::Warning,
+                    5,
+                    "Notes attached to messages are not supported, ignored"
+                ),
+                (Severity::Error, 6, "Unclosed 'loop' block"),
+            ]
+        );
+        assert_eq!(diagram.participants.len(), 2);
+        assert_eq!(diagram.events.len(), 4);
+    }
+
+    #[test]
+    fn test_plantuml_non_ascii_names() {
+        let input = "@startuml\nA ->中 : hi\nA -中 B\n中 -> 文 : 你好\n@enduml";
+        let (diagram, _) = SequenceDiagram::from_plantuml_tolerant(input);
+        assert!(diagram.participants.iter().any(|p| p.id == "中"));
+        assert!(diagram.participants.iter().any(|p| p.id == "文"));
+        assert!(matches!(
+            diagram.events.get(diagram.events.len() - 1),
+            Some(Event::Message { text, .. }) if text == "你好"
+        ));
+    }
+}
@@ -1,11 +1,38 @@
-use super::models::{BoxColor, Event, NotePosition, ParticipantBox};
+use super::diagnostic::{ParseError, Severity};
+use super::front_matter::{FrontMatterEntry, FrontMatterReader, unquote, write_front_matter};
+use super::models::{
+    Activation, Arrow, Autonumber, BoxColor, Event, EventId, FragmentKind, Lifecycle, Metadata,
+    NotePosition, Participant, ParticipantBox, ParticipantId, ParticipantKind, RgbColor,
+};
+use super::source::{RawLine, SourceMap};
+use super::store::OrderedStore;
+use super::syntax::{decode_text, encode_text, split_message, split_statements};
 use anyhow::{Result, bail};
+use std::ops::Range;
+
+/// The event indices that make up a fragment or highlight block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockBounds {
+    pub start: usize,
+    pub sections: Vec<usize>,
+    /// Index of the end marker, or `None` if the block is never closed.
+    pub end: Option<usize>,
+    /// Nesting depth, `0` for top-level blocks.
+    pub depth: usize,
+}
 
 #[derive(Default, Clone)]
 pub struct SequenceDiagram {
-    pub participants: Vec<String>,
-    pub events: Vec<Event>,
+    /// Participants in display order. Events refer to them by id, so reordering or removing
+    /// participants never rewrites events.
+    pub participants: OrderedStore<ParticipantId, Participant>,
+    pub events: OrderedStore<EventId, Event>,
+    /// Boxes hold runs of neighbouring participants, from their first to their last member.
     pub boxes: Vec<ParticipantBox>,
+    pub autonumber: Option<Autonumber>,
+    pub metadata: Metadata,
+    /// The imported text, so that `to_mermaid` can keep what the model does not interpret.
+    pub source: Option<SourceMap>,
 }
 
 impl SequenceDiagram {
@@ -18,17 +45,25 @@ impl SequenceDiagram {
             return false;
         }
         // Reject if this range overlaps any existing box.
-        let overlaps = self.boxes.iter().any(|b| start <= b.end && b.start <= end);
+        let overlaps = self
+            .boxes
+            .iter()
+            .filter_map(|b| self.box_span(b))
+            .any(|(b_start, b_end)| start <= b_end && b_start <= end);
         if overlaps {
             return false;
         }
+        let (Some(first), Some(last)) = (self.participant_at(start), self.participant_at(end))
+        else {
+            return false;
+        };
         self.boxes.push(ParticipantBox {
             label,
             color,
-            start,
-            end,
+            first,
+            last,
         });
-        self.boxes.sort_by_key(|b| b.start);
+        self.sort_boxes();
         true
     }
 
@@ -38,28 +73,149 @@ impl SequenceDiagram {
         }
     }
 
+    /// The positions of the first and last participant in `b`.
+    pub fn box_span(&self, b: &ParticipantBox) -> Option<(usize, usize)> {
+        Some((self.position_of(b.first)?, self.position_of(b.last)?))
+    }
+
+    /// Keeps boxes in display order, as exports write them in the order they are stored.
+    fn sort_boxes(&mut self) {
+        let mut boxes = std::mem::take(&mut self.boxes);
+        boxes.sort_by_key(|b| self.box_span(b).map(|(start, _)| start));
+        self.boxes = boxes;
+    }
+
     #[allow(dead_code)]
     pub fn box_for_participant(&self, participant_idx: usize) -> Option<&ParticipantBox> {
-        self.boxes
+        self.box_index_at(participant_idx).map(|b| &self.boxes[b])
+    }
+
+    /// Returns the index of the box containing the participant at `participant_idx`.
+    pub fn box_index_at(&self, participant_idx: usize) -> Option<usize> {
+        self.boxes.iter().position(|b| {
+            self.box_span(b)
+                .is_some_and(|(start, end)| start <= participant_idx && participant_idx <= end)
+        })
+    }
+
+    /// Replaces the box at `box_idx`. The box is kept as it was if the new range is invalid
+    /// or overlaps another box.
+    pub fn update_box(
+        &mut self,
+        box_idx: usize,
+        label: String,
+        color: BoxColor,
+        start: usize,
+        end: usize,
+    ) -> bool {
+        if box_idx >= self.boxes.len() {
+            return false;
+        }
+        let old = self.boxes.remove(box_idx);
+        if self.add_box(label, color, start, end) {
+            true
+        } else {
+            self.boxes.insert(box_idx, old);
+            false
+        }
+    }
+
+    /// Moves the participant at `idx` out of its box, or into a box right next to it.
+    ///
+    /// The first member of a box leaves at the start, any other member moves past the last
+    /// one. Returns the new position of the participant, or `None` if there is no box to move
+    /// into or it is the only member of its box.
+    pub fn toggle_box_membership(&mut self, idx: usize) -> Option<usize> {
+        if let Some(b) = self.box_index_at(idx) {
+            let (start, end) = self.box_span(&self.boxes[b])?;
+            if start == end {
+                return None;
+            }
+            if idx == start {
+                self.boxes[b].first = self.participant_at(start + 1)?;
+                return Some(idx);
+            }
+            for i in idx..end {
+                self.participants.swap(i, i + 1);
+            }
+            self.boxes[b].last = self.participant_at(end - 1)?;
+            return Some(end);
+        }
+        let id = self.participant_at(idx)?;
+        let spans: Vec<_> = self.boxes.iter().map(|b| self.box_span(b)).collect();
+        if let Some(b) = spans
             .iter()
-            .find(|b| b.start <= participant_idx && participant_idx <= b.end)
+            .position(|s| s.is_some_and(|(_, end)| end + 1 == idx))
+        {
+            self.boxes[b].last = id;
+        } else if let Some(b) = spans
+            .iter()
+            .position(|s| s.is_some_and(|(start, _)| start == idx + 1))
+        {
+            self.boxes[b].first = id;
+        } else {
+            return None;
+        }
+        Some(idx)
+    }
+
+    pub fn add_participant(&mut self, participant: impl Into<Participant>) {
+        self.participants.push(participant.into());
+    }
+
+    /// Returns the index of the participant with the given id.
+    pub fn participant_index(&self, id: &str) -> Option<usize> {
+        self.participants.iter().position(|p| p.id == id)
+    }
+
+    /// The id of the participant at position `idx`.
+    pub fn participant_at(&self, idx: usize) -> Option<ParticipantId> {
+        self.participants.id(idx)
     }
 
-    pub fn add_participant(&mut self, name: String) {
-        self.participants.push(name);
+    /// The current position of a participant.
+    pub fn position_of(&self, id: ParticipantId) -> Option<usize> {
+        self.participants.index_of(id)
+    }
+
+    /// Builds a plain message between the participants at positions `from` and `to`.
+    pub fn message(&self, from: usize, to: usize, text: String) -> Option<Event> {
+        Some(Event::Message {
+            from: self.participant_at(from)?,
+            to: self.participant_at(to)?,
+            text,
+            arrow: Arrow::default(),
+            activation: Activation::default(),
+            lifecycle: Lifecycle::default(),
+        })
+    }
+
+    /// Builds a note over or beside the participants at positions `start` and `end`.
+    pub fn note(
+        &self,
+        position: NotePosition,
+        start: usize,
+        end: usize,
+        text: String,
+    ) -> Option<Event> {
+        Some(Event::Note {
+            position,
+            participant_start: self.participant_at(start)?,
+            participant_end: self.participant_at(end)?,
+            text,
+        })
     }
 
     pub fn add_message(&mut self, from: usize, to: usize, text: String) {
-        if from < self.participants.len() && to < self.participants.len() {
-            self.events.push(Event::Message { from, to, text });
+        if let Some(message) = self.message(from, to, text) {
+            self.events.push(message);
         }
     }
 
     pub fn insert_message(&mut self, after_index: usize, from: usize, to: usize, text: String) {
-        if from < self.participants.len() && to < self.participants.len() {
+        if let Some(message) = self.message(from, to, text) {
             let insert_at = (after_index + 1).min(self.events.len());
-            self.events
-                .insert(insert_at, Event::Message { from, to, text });
+            self.events.insert(insert_at, message);
         }
     }
 
@@ -70,14 +226,8 @@ impl SequenceDiagram {
         participant_end: usize,
         text: String,
     ) {
-        if participant_start < self.participants.len() && participant_end < self.participants.len()
-        {
-            self.events.push(Event::Note {
-                position,
-                participant_start,
-                participant_end,
-                text,
-            });
+        if let Some(note) = self.note(position, participant_start, participant_end, text) {
+            self.events.push(note);
         }
     }
 
@@ -89,18 +239,9 @@ impl SequenceDiagram {
         participant_end: usize,
         text: String,
     ) {
-        if participant_start < self.participants.len() && participant_end < self.participants.len()
-        {
+        if let Some(note) = self.note(position, participant_start, participant_end, text) {
             let insert_at = (after_index + 1).min(self.events.len());
-            self.events.insert(
-                insert_at,
-                Event::Note {
-                    position,
-                    participant_start,
-                    participant_end,
-                    text,
-                },
-            );
+            self.events.insert(insert_at, note);
         }
     }
 
@@ -112,159 +253,597 @@ impl SequenceDiagram {
         self.events.len()
     }
 
+    /// Removes the event at `idx`. Removing a block start or end unwraps the whole block.
     pub fn remove_event(&mut self, idx: usize) {
-        if idx < self.events.len() {
-            self.events.remove(idx);
+        match self.events.get(idx) {
+            Some(event) if event.is_block_start() || event.is_block_end() => {
+                self.unwrap_block(idx);
+            }
+            Some(_) => {
+                self.events.remove(idx);
+            }
+            None => {}
         }
     }
 
-    pub fn swap_participants(&mut self, a: usize, b: usize) {
-        if a >= self.participants.len() || b >= self.participants.len() {
-            return;
+    /// Swaps two adjacent events. Block markers cannot be swapped with each other, so
+    /// moving a marker only grows or shrinks its block.
+    pub fn move_event(&mut self, from: usize, to: usize) -> bool {
+        if from.abs_diff(to) != 1 || from >= self.events.len() || to >= self.events.len() {
+            return false;
         }
-        self.participants.swap(a, b);
-        for e in &mut self.events {
-            match e {
-                Event::Message { from, to, .. } => {
-                    if *from == a {
-                        *from = b;
-                    } else if *from == b {
-                        *from = a;
-                    }
-                    if *to == a {
-                        *to = b;
-                    } else if *to == b {
-                        *to = a;
-                    }
-                }
-                Event::Note {
-                    participant_start,
-                    participant_end,
-                    ..
-                } => {
-                    if *participant_start == a {
-                        *participant_start = b;
-                    } else if *participant_start == b {
-                        *participant_start = a;
-                    }
-                    if *participant_end == a {
-                        *participant_end = b;
-                    } else if *participant_end == b {
-                        *participant_end = a;
+        if self.events[from].is_block_marker() && self.events[to].is_block_marker() {
+            return false;
+        }
+        self.events.swap(from, to);
+        true
+    }
+
+    /// Moves the participant at `idx` one place to the left or right, returning its new
+    /// position.
+    ///
+    /// Boxes stay whole: a participant at the edge of its box takes the box along, and a
+    /// participant next to a box moves past all of it.
+    pub fn move_participant(&mut self, idx: usize, right: bool) -> Option<usize> {
+        let neighbor = if right { idx + 1 } else { idx.checked_sub(1)? };
+        let id = self.participant_at(idx)?;
+        let other_id = self.participant_at(neighbor)?;
+
+        let group = |pos: usize| {
+            self.box_index_at(pos)
+                .and_then(|b| self.box_span(&self.boxes[b]))
+                .unwrap_or((pos, pos))
+        };
+        let (own, other) = (group(idx), group(neighbor));
+        if own == other {
+            self.participants.swap(idx, neighbor);
+            // Swapping inside a box may change which members are at its edges.
+            for b in &mut self.boxes {
+                for edge in [&mut b.first, &mut b.last] {
+                    if *edge == id {
+                        *edge = other_id;
+                    } else if *edge == other_id {
+                        *edge = id;
                     }
                 }
             }
+        } else {
+            let (left, right) = if right { (own, other) } else { (other, own) };
+            self.participants
+                .rotate_left(left.0..right.1 + 1, left.1 - left.0 + 1);
+            self.sort_boxes();
         }
+        self.position_of(id)
     }
 
-    pub fn point_event_left(&mut self, idx: usize) {
-        if let Some(Event::Message { from, to, .. }) = self.events.get_mut(idx)
-            && *from < *to
+    /// Turns the message at `idx` to point left, returning whether it did not already.
+    pub fn point_event_left(&mut self, idx: usize) -> bool {
+        if let Some(&Event::Message { from, to, .. }) = self.events.get(idx)
+            && self.position_of(from) < self.position_of(to)
+            && let Some(Event::Message { from, to, .. }) = self.events.get_mut(idx)
         {
             std::mem::swap(from, to);
+            return true;
         }
+        false
     }
 
-    pub fn point_event_right(&mut self, idx: usize) {
-        if let Some(Event::Message { from, to, .. }) = self.events.get_mut(idx)
-            && *from > *to
+    /// Turns the message at `idx` to point right, returning whether it did not already.
+    pub fn point_event_right(&mut self, idx: usize) -> bool {
+        if let Some(&Event::Message { from, to, .. }) = self.events.get(idx)
+            && self.position_of(from) > self.position_of(to)
+            && let Some(Event::Message { from, to, .. }) = self.events.get_mut(idx)
         {
             std::mem::swap(from, to);
+            return true;
         }
+        false
     }
 
-    pub fn remove_participant(&mut self, idx: usize) {
-        if idx >= self.participants.len() {
-            return;
-        }
-        self.participants.remove(idx);
-        self.events.retain(|e| match e {
-            Event::Message { from, to, .. } => *from != idx && *to != idx,
-            Event::Note {
-                participant_start,
-                participant_end,
-                ..
-            } => *participant_start != idx && *participant_end != idx,
-        });
-        for e in &mut self.events {
-            match e {
-                Event::Message { from, to, .. } => {
-                    if *from > idx {
-                        *from -= 1;
+    /// Changes the arrow head of the message at `idx`, returning whether there is one.
+    pub fn cycle_arrow_head(&mut self, idx: usize) -> bool {
+        let Some(Event::Message { arrow, .. }) = self.events.get_mut(idx) else {
+            return false;
+        };
+        arrow.head = arrow.head.next();
+        true
+    }
+
+    /// Switches the message at `idx` between solid and dotted, returning whether there is one.
+    pub fn toggle_arrow_line(&mut self, idx: usize) -> bool {
+        let Some(Event::Message { arrow, .. }) = self.events.get_mut(idx) else {
+            return false;
+        };
+        arrow.line = arrow.line.toggle();
+        true
+    }
+
+    /// Changes the activation of the message at `idx`, returning whether there is one.
+    pub fn cycle_activation(&mut self, idx: usize) -> bool {
+        let Some(Event::Message { activation, .. }) = self.events.get_mut(idx) else {
+            return false;
+        };
+        *activation = activation.next();
+        true
+    }
+
+    /// Returns the activation depth of every participant after each event.
+    ///
+    /// Unbalanced deactivations are ignored rather than going below zero.
+    pub fn activation_depths(&self) -> Vec<Vec<usize>> {
+        let mut depths = vec![0usize; self.participants.len()];
+        let mut result = Vec::with_capacity(self.events.len());
+
+        for event in &self.events {
+            match event {
+                Event::Message {
+                    from,
+                    to,
+                    activation,
+                    ..
+                } => match activation {
+                    Activation::None => {}
+                    Activation::Activate => {
+                        if let Some(d) = self.position_of(*to).and_then(|p| depths.get_mut(p)) {
+                            *d += 1;
+                        }
                     }
-                    if *to > idx {
-                        *to -= 1;
+                    Activation::Deactivate => {
+                        if let Some(d) = self.position_of(*from).and_then(|p| depths.get_mut(p)) {
+                            *d = d.saturating_sub(1);
+                        }
                     }
-                }
-                Event::Note {
-                    participant_start,
-                    participant_end,
-                    ..
+                },
+                Event::Activation {
+                    participant,
+                    active,
                 } => {
-                    if *participant_start > idx {
-                        *participant_start -= 1;
-                    }
-                    if *participant_end > idx {
-                        *participant_end -= 1;
+                    if let Some(d) = self
+                        .position_of(*participant)
+                        .and_then(|p| depths.get_mut(p))
+                    {
+                        *d = if *active { *d + 1 } else { d.saturating_sub(1) };
                     }
                 }
+                Event::Note { .. }
+                | Event::FragmentStart { .. }
+                | Event::FragmentSection { .. }
+                | Event::FragmentEnd
+                | Event::HighlightStart { .. }
+                | Event::HighlightEnd => {}
             }
+            result.push(depths.clone());
         }
 
-        let mut new_boxes: Vec<ParticipantBox> = Vec::new();
-        for mut b in self.boxes.drain(..) {
-            if b.start == idx && b.end == idx {
-                continue;
+        result
+    }
+
+    /// Returns the index of the message that creates and the message that destroys each
+    /// participant, if any.
+    pub fn lifespans(&self) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut result = vec![(None, None); self.participants.len()];
+        for (i, event) in self.events.iter().enumerate() {
+            if let Event::Message {
+                from,
+                to,
+                lifecycle,
+                ..
+            } = event
+            {
+                if let Some((created, _)) = lifecycle
+                    .created(*to)
+                    .and_then(|p| self.position_of(p))
+                    .and_then(|p| result.get_mut(p))
+                {
+                    created.get_or_insert(i);
+                }
+                if let Some((_, destroyed)) = lifecycle
+                    .destroyed(*from, *to)
+                    .and_then(|p| self.position_of(p))
+                    .and_then(|p| result.get_mut(p))
+                {
+                    destroyed.get_or_insert(i);
+                }
             }
-            if b.end >= idx {
-                b.end = b.end.saturating_sub(1);
+        }
+        result
+    }
+
+    /// Marks the message at `idx` as creating or destroying its target, or clears the mark if
+    /// it is already set. Other marks of the same kind for that participant are removed.
+    /// Returns whether there is a message at `idx`.
+    pub fn toggle_lifecycle(&mut self, idx: usize, lifecycle: Lifecycle) -> bool {
+        let Some(&Event::Message {
+            from,
+            to,
+            lifecycle: current,
+            ..
+        }) = self.events.get(idx)
+        else {
+            return false;
+        };
+        let target = if lifecycle == Lifecycle::DestroySender {
+            from
+        } else {
+            to
+        };
+        let is_create = lifecycle == Lifecycle::CreateTarget;
+        for event in &mut self.events {
+            if let Event::Message {
+                from,
+                to,
+                lifecycle: other,
+                ..
+            } = event
+            {
+                let same = if is_create {
+                    other.created(*to) == Some(target)
+                } else {
+                    other.destroyed(*from, *to) == Some(target)
+                };
+                if same {
+                    *other = Lifecycle::None;
+                }
             }
-            if b.start > idx {
-                b.start -= 1;
+        }
+        if current != lifecycle
+            && let Some(Event::Message { lifecycle: l, .. }) = self.events.get_mut(idx)
+        {
+            *l = lifecycle;
+        }
+        true
+    }
+
+    /// Returns the autonumber of every event, or `None` for events that are not messages.
+    ///
+    /// Without autonumbering every entry is `None`.
+    pub fn message_numbers(&self) -> Vec<Option<u32>> {
+        let Some(autonumber) = self.autonumber else {
+            return vec![None; self.events.len()];
+        };
+        let mut count = 0;
+        self.events
+            .iter()
+            .map(|event| {
+                matches!(event, Event::Message { .. }).then(|| {
+                    count += 1;
+                    autonumber.number(count - 1)
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the index of the message with the given number. Without autonumbering,
+    /// messages are counted from 1.
+    pub fn find_message_number(&self, number: u32) -> Option<usize> {
+        let autonumber = self.autonumber.unwrap_or_default();
+        self.events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| matches!(event, Event::Message { .. }))
+            .zip(0..)
+            .find(|(_, n)| autonumber.number(*n) == number)
+            .map(|((idx, _), _)| idx)
+    }
+
+    /// Returns all fragment and highlight blocks in the order their start markers appear.
+    pub fn blocks(&self) -> Vec<BlockBounds> {
+        let mut result: Vec<BlockBounds> = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+
+        for (i, event) in self.events.iter().enumerate() {
+            if event.is_block_start() {
+                result.push(BlockBounds {
+                    start: i,
+                    sections: Vec::new(),
+                    end: None,
+                    depth: open.len(),
+                });
+                open.push(result.len() - 1);
+            } else if event.is_block_end() {
+                if let Some(b) = open.pop() {
+                    result[b].end = Some(i);
+                }
+            } else if let Event::FragmentSection { .. } = event
+                && let Some(&b) = open.last()
+            {
+                result[b].sections.push(i);
             }
-            if b.start <= b.end {
-                new_boxes.push(b);
+        }
+
+        result
+    }
+
+    /// Returns the innermost block containing the event at `idx`, including its own markers.
+    pub fn block_at(&self, idx: usize) -> Option<BlockBounds> {
+        self.blocks()
+            .into_iter()
+            .filter(|b| b.start <= idx && idx <= b.end.unwrap_or(usize::MAX))
+            .max_by_key(|b| b.depth)
+    }
+
+    /// Returns the leftmost and rightmost participant referenced by the events in `start..=end`.
+    pub fn participant_span(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        if start > end {
+            return None;
+        }
+        self.events
+            .iter()
+            .skip(start)
+            .take(end - start + 1)
+            .flat_map(Event::participants)
+            .filter_map(|p| self.position_of(p))
+            .fold(None, |span, p| match span {
+                None => Some((p, p)),
+                Some((lo, hi)) => Some((lo.min(p), hi.max(p))),
+            })
+    }
+
+    /// Returns `true` if `start..=end` can be wrapped in a block without cutting through another.
+    fn can_wrap(&self, start: usize, end: usize) -> bool {
+        if start > end || end >= self.events.len() {
+            return false;
+        }
+        let mut depth = 0usize;
+        for event in self.events.iter().skip(start).take(end - start + 1) {
+            if event.is_block_start() {
+                depth += 1;
+            } else if event.is_block_marker() {
+                if depth == 0 {
+                    return false;
+                }
+                if event.is_block_end() {
+                    depth -= 1;
+                }
             }
         }
+        depth == 0
+    }
+
+    /// Wraps the events in `start..=end` in a new fragment.
+    ///
+    /// Returns `false` if the range would cut through an existing block.
+    pub fn wrap_in_fragment(
+        &mut self,
+        start: usize,
+        end: usize,
+        kind: FragmentKind,
+        label: String,
+    ) -> bool {
+        if !self.can_wrap(start, end) {
+            return false;
+        }
+        self.events.insert(end + 1, Event::FragmentEnd);
+        self.events
+            .insert(start, Event::FragmentStart { kind, label });
+        true
+    }
 
-        self.boxes = new_boxes;
+    /// Wraps the events in `start..=end` in a highlight region.
+    ///
+    /// Returns `false` if the range would cut through an existing block.
+    pub fn wrap_in_highlight(&mut self, start: usize, end: usize, color: RgbColor) -> bool {
+        if !self.can_wrap(start, end) {
+            return false;
+        }
+        self.events.insert(end + 1, Event::HighlightEnd);
+        self.events.insert(start, Event::HighlightStart { color });
+        true
+    }
+
+    /// Inserts an `else`/`and`/`option` section after the event at `after_index`.
+    ///
+    /// Returns `false` if the position is not directly inside a fragment that supports sections.
+    pub fn add_fragment_section(&mut self, after_index: usize, label: String) -> bool {
+        let at = after_index + 1;
+        let block = self
+            .blocks()
+            .into_iter()
+            .filter(|b| b.start < at && at <= b.end.unwrap_or(self.events.len()))
+            .max_by_key(|b| b.depth);
+        let Some(block) = block else {
+            return false;
+        };
+        let supports_sections = matches!(
+            self.events.get(block.start),
+            Some(Event::FragmentStart { kind, .. }) if kind.section_keyword().is_some()
+        );
+        if !supports_sections {
+            return false;
+        }
+        self.events.insert(at, Event::FragmentSection { label });
+        true
+    }
+
+    /// Removes the markers of the innermost block containing `idx`, keeping its events.
+    pub fn unwrap_block(&mut self, idx: usize) -> bool {
+        let Some(block) = self.block_at(idx) else {
+            return false;
+        };
+        let mut markers = block.sections;
+        markers.push(block.start);
+        markers.extend(block.end);
+        markers.sort_unstable();
+        for i in markers.into_iter().rev() {
+            self.events.remove(i);
+        }
+        true
+    }
+
+    pub fn remove_participant(&mut self, idx: usize) {
+        let Some(id) = self.participant_at(idx) else {
+            return;
+        };
+        self.participants.remove(idx);
+        self.events.retain(|e| !e.participants().contains(&id));
+
+        self.boxes.retain(|b| b.first != id || b.last != id);
+        for b in &mut self.boxes {
+            // The next member takes over the edge of the box.
+            if b.first == id {
+                b.first = self.participants.id(idx).unwrap_or(id);
+            }
+            if b.last == id {
+                b.last = self.participants.id(idx - 1).unwrap_or(id);
+            }
+        }
+    }
+
+    /// Points import problems at the nearest event by its id, so that they still find it after
+    /// edits, and sorts them by line. Each comes with the index of the event it was found at
+    /// while parsing, which may be one past the last event.
+    pub(super) fn locate_events(&self, diagnostics: Vec<(ParseError, usize)>) -> Vec<ParseError> {
+        let last_event = self.events.len().checked_sub(1);
+        let mut diagnostics: Vec<ParseError> = diagnostics
+            .into_iter()
+            .map(|(mut diagnostic, event)| {
+                diagnostic.event = last_event.and_then(|last| self.events.id(event.min(last)));
+                diagnostic
+            })
+            .collect();
+        diagnostics.sort_by_key(|d| d.line);
+        diagnostics
+    }
+
+    /// The Mermaid id of a participant.
+    pub(super) fn mermaid_id(&self, id: ParticipantId) -> Option<&str> {
+        self.participants.by_id(id).map(|p| p.id.as_str())
+    }
+
+    /// Returns the participant with the given Mermaid id, adding it if it does not exist.
+    pub(super) fn ensure_participant(&mut self, id: &str) -> ParticipantId {
+        match self
+            .participant_index(id)
+            .and_then(|idx| self.participant_at(idx))
+        {
+            Some(existing) => existing,
+            None => self.participants.push(Participant::new(id)),
+        }
     }
 
     pub fn to_mermaid(&self) -> String {
-        let mut lines = vec!["sequenceDiagram".to_string()];
+        let lines = self.canonical_lines();
+        match &self.source {
+            Some(source) => source.render(&lines),
+            None => lines.join("\n") + "\n",
+        }
+    }
+
+    fn canonical_lines(&self) -> Vec<String> {
+        let mut lines = write_front_matter(&self.metadata);
+        lines.push("sequenceDiagram".to_string());
+        if let Some(title) = self
+            .metadata
+            .title
+            .as_ref()
+            .filter(|_| !self.metadata.title_in_front_matter)
+        {
+            lines.push(format!("    title {}", encode_text(title)));
+        }
+        if let Some(acc_title) = &self.metadata.acc_title {
+            lines.push(format!("    accTitle: {}", encode_text(acc_title)));
+        }
+        match &self.metadata.acc_descr {
+            Some(acc_descr) if acc_descr.contains('\n') => {
+                lines.push("    accDescr {".to_string());
+                for line in acc_descr.split('\n') {
+                    let line = encode_text(line).replace('}', "#125;");
+                    lines.push(format!("        {line}").trim_end().to_string());
+                }
+                lines.push("    }".to_string());
+            }
+            Some(acc_descr) => lines.push(format!("    accDescr: {}", encode_text(acc_descr))),
+            None => {}
+        }
+        if let Some(autonumber) = self.autonumber {
+            lines.push(format!("    {}", autonumber.as_mermaid_string()));
+        }
+
+        let created: Vec<usize> = self
+            .lifespans()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, (created, _))| created.map(|_| idx))
+            .collect();
 
         let mut i = 0;
         while i < self.participants.len() {
-            if let Some(b) = self.boxes.iter().find(|b| b.start == i) {
-                let color = b.color.as_mermaid_str();
+            if let Some((b, (_, box_end))) = self
+                .boxes
+                .iter()
+                .filter_map(|b| Some((b, self.box_span(b)?)))
+                .find(|(_, (start, _))| *start == i)
+            {
+                let color = b.color.as_mermaid_string();
                 if b.label.is_empty() {
                     lines.push(format!("    box {color}"));
                 } else {
-                    lines.push(format!("    box {color} {}", b.label));
+                    lines.push(format!("    box {color} {}", encode_text(&b.label)));
                 }
-                let box_end = b.end;
                 for j in i..=box_end {
-                    if let Some(name) = self.participants.get(j) {
-                        lines.push(format!("        participant {name}"));
+                    if let Some(participant) = self.participants.get(j) {
+                        lines.push(format!("        {}", participant.as_mermaid_string()));
                     }
                 }
                 lines.push("    end".to_string());
                 i = box_end + 1;
             } else {
-                if let Some(name) = self.participants.get(i) {
-                    lines.push(format!("    participant {name}"));
+                // Created participants are declared by their `create` statement instead.
+                if let Some(participant) = self.participants.get(i)
+                    && !created.contains(&i)
+                {
+                    lines.push(format!("    {}", participant.as_mermaid_string()));
                 }
                 i += 1;
             }
         }
 
+        // Open blocks, innermost last; `None` is a highlight region.
+        let mut open_blocks: Vec<Option<FragmentKind>> = Vec::new();
         for event in &self.events {
+            let indent = "    ".repeat(open_blocks.len() + 1);
             match event {
-                Event::Message { from, to, text } => {
+                Event::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    activation,
+                    lifecycle,
+                } => {
+                    if let Some(participant) = lifecycle
+                        .created(*to)
+                        .and_then(|p| self.participants.by_id(p))
+                    {
+                        lines.push(format!(
+                            "{indent}create {}",
+                            participant.as_mermaid_string()
+                        ));
+                    }
+                    if let Some(name) = lifecycle
+                        .destroyed(*from, *to)
+                        .and_then(|p| self.mermaid_id(p))
+                    {
+                        lines.push(format!("{indent}destroy {name}"));
+                    }
                     if let (Some(from_name), Some(to_name)) =
-                        (self.participants.get(*from), self.participants.get(*to))
+                        (self.mermaid_id(*from), self.mermaid_id(*to))
                     {
-                        lines.push(format!("    {from_name}->>{to_name}: {text}"));
+                        let arrow = arrow.as_mermaid_str();
+                        let activation = activation.as_mermaid_str();
+                        let text = encode_text(text);
+                        lines.push(format!(
+                            "{indent}{from_name}{arrow}{activation}{to_name}: {text}"
+                        ));
+                    }
+                }
+                Event::Activation {
+                    participant,
+                    active,
+                } => {
+                    if let Some(name) = self.mermaid_id(*participant) {
+                        let keyword = if *active { "activate" } else { "deactivate" };
+                        lines.push(format!("{indent}{keyword} {name}"));
                     }
                 }
                 Event::Note {
@@ -274,221 +853,649 @@ impl SequenceDiagram {
                     text,
                 } => {
                     let pos_str = position.as_str();
+                    let text = encode_text(text);
                     if *position == NotePosition::Over && participant_start != participant_end {
                         if let (Some(start_name), Some(end_name)) = (
-                            self.participants.get(*participant_start),
-                            self.participants.get(*participant_end),
+                            self.mermaid_id(*participant_start),
+                            self.mermaid_id(*participant_end),
                         ) {
                             lines.push(format!(
-                                "    Note {pos_str} {start_name},{end_name}: {text}"
+                                "{indent}Note {pos_str} {start_name},{end_name}: {text}"
                             ));
                         }
-                    } else if let Some(name) = self.participants.get(*participant_start) {
-                        lines.push(format!("    Note {pos_str} {name}: {text}"));
+                    } else if let Some(name) = self.mermaid_id(*participant_start) {
+                        lines.push(format!("{indent}Note {pos_str} {name}: {text}"));
                     }
                 }
+                Event::FragmentStart { kind, label } => {
+                    lines.push(with_label(&indent, kind.as_mermaid_str(), label));
+                    open_blocks.push(Some(*kind));
+                }
+                Event::FragmentSection { label } => {
+                    let keyword = open_blocks
+                        .last()
+                        .copied()
+                        .flatten()
+                        .and_then(FragmentKind::section_keyword)
+                        .unwrap_or("else");
+                    let indent = "    ".repeat(open_blocks.len().max(1));
+                    lines.push(with_label(&indent, keyword, label));
+                }
+                Event::HighlightStart { color } => {
+                    lines.push(format!("{indent}rect {}", color.as_mermaid_string()));
+                    open_blocks.push(None);
+                }
+                Event::FragmentEnd | Event::HighlightEnd => {
+                    open_blocks.pop();
+                    let indent = "    ".repeat(open_blocks.len() + 1);
+                    lines.push(format!("{indent}end"));
+                }
             }
         }
 
-        lines.join("\n") + "\n"
+        lines
     }
 
-    pub fn from_mermaid(input: &str) -> Result<Self> {
-        let mut diagram = SequenceDiagram::new();
-        let mut lines = input.lines();
-
-        let first_line = lines.next().map_or("", str::trim);
-        if first_line != "sequenceDiagram" {
-            bail!("First line must be 'sequenceDiagram'");
+    /// Parses a Mermaid sequence diagram, failing on the first statement it cannot import.
+    ///
+    /// Unsupported statements are kept as is and don't fail the import.
+    pub fn from_mermaid(input: &str) -> Result<Self, ParseError> {
+        let (diagram, diagnostics) = Self::from_mermaid_tolerant(input);
+        match diagnostics
+            .into_iter()
+            .find(|d| d.severity == Severity::Error)
+        {
+            Some(error) => Err(error),
+            None => Ok(diagram),
         }
+    }
+
+    /// The Mermaid text the diagram was imported from, if any.
+    pub fn source_text(&self) -> Option<&str> {
+        self.source.as_ref().map(SourceMap::text)
+    }
+
+    /// Keeps the lines of `text` that the model does not interpret when exporting, as if the
+    /// diagram had been imported from it.
+    pub fn attach_source(&mut self, text: &str) {
+        self.source = Self::from_mermaid_tolerant(text).0.source;
+    }
 
-        let mut current_box: Option<(BoxColor, String, usize)> = None;
+    /// Parses everything it understands and reports the rest.
+    pub fn from_mermaid_tolerant(input: &str) -> (Self, Vec<ParseError>) {
+        // Editors on Windows may save a byte order mark before the header
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+        let mut parser = MermaidParser::default();
+        let mut raw = Vec::new();
+        let mut has_header = false;
 
-        for line in lines {
+        let mut front_matter_allowed = true;
+
+        for (number, line) in input.split('\n').enumerate() {
             let trimmed = line.trim();
+            parser.line = number + 1;
+            parser.columns = trimmed_columns(line);
 
-            if trimmed.is_empty() {
-                bail!("Empty lines are not supported");
+            if parser.acc_descr_block.is_some() {
+                parser.acc_descr_line(trimmed);
+                raw.push(RawLine::Statement(line));
+                continue;
             }
-            if trimmed.starts_with("%%") {
-                bail!("Comments are not supported");
+            if parser.front_matter.is_some() {
+                raw.push(if parser.front_matter_line(line) {
+                    RawLine::Statement(line)
+                } else {
+                    RawLine::Trivia(line)
+                });
+                continue;
             }
+            if is_trivia(trimmed) {
+                raw.push(RawLine::Trivia(line));
+                continue;
+            }
+            // YAML front matter may only precede the header
+            if front_matter_allowed && !has_header && trimmed == "---" {
+                front_matter_allowed = false;
+                parser.front_matter = Some((FrontMatterReader::default(), parser.location()));
+                raw.push(RawLine::Statement(line));
+                continue;
+            }
+            front_matter_allowed = false;
+
+            // A line holds one or more `;`-separated statements; it is kept as is unless at
+            // least one of them is understood
+            let mut understood = false;
+            for (offset, statement) in split_statements(line) {
+                let statement = statement.trim();
+                if statement.is_empty() {
+                    continue;
+                }
+                parser.columns = columns(line, offset);
+
+                // Comments and directives may precede the header
+                if !has_header {
+                    has_header = true;
+                    if statement == "sequenceDiagram" {
+                        understood = true;
+                        continue;
+                    }
+                    parser.report(Severity::Error, "First line must be 'sequenceDiagram'");
+                }
 
-            // Parse participant
-            if let Some(rest) = trimmed.strip_prefix("participant ") {
-                let name = rest.trim().to_string();
-                if name.is_empty() {
-                    bail!("Invalid participant declaration: {line}");
+                if parser.pending_lifecycle.is_some()
+                    && split_message(statement).is_none()
+                    && let Some((keyword, _, at)) = parser.pending_lifecycle.take()
+                {
+                    parser.report_at(
+                        at,
+                        Severity::Error,
+                        format!("'{keyword}' must be followed by a message"),
+                    );
                 }
-                if !diagram.participants.contains(&name) {
-                    diagram.participants.push(name);
+
+                match parser.statement(statement) {
+                    Ok(true) => understood = true,
+                    // Keep statements we don't understand so they survive a round-trip
+                    Ok(false) => {
+                        parser.report(Severity::Warning, "Unsupported statement, kept as is");
+                    }
+                    Err(error) => parser.report(Severity::Error, error.to_string()),
                 }
-                continue;
             }
+            raw.push(if understood {
+                RawLine::Statement(line)
+            } else {
+                RawLine::Trivia(line)
+            });
+        }
 
-            if let Some(rest) = trimmed.strip_prefix("Note ") {
-                let (position, after_pos) = if let Some(after) = rest.strip_prefix("right of ") {
-                    (NotePosition::Right, after)
-                } else if let Some(after) = rest.strip_prefix("left of ") {
-                    (NotePosition::Left, after)
-                } else if let Some(after) = rest.strip_prefix("over ") {
-                    (NotePosition::Over, after)
-                } else {
-                    bail!("Invalid note position: {line}");
-                };
+        if let Some((_, at)) = parser.front_matter.take() {
+            parser.report_at(at, Severity::Error, "Unclosed front matter");
+        }
+        if let Some((_, at)) = parser.acc_descr_block.take() {
+            parser.report_at(at, Severity::Error, "Unclosed 'accDescr' block");
+        }
+        if !has_header {
+            parser.line = 1;
+            parser.columns = 1..1;
+            parser.report(Severity::Error, "First line must be 'sequenceDiagram'");
+        }
+        if let Some((keyword, _, at)) = parser.pending_lifecycle.take() {
+            parser.report_at(
+                at,
+                Severity::Error,
+                format!("'{keyword}' must be followed by a message"),
+            );
+        }
+        // Close unterminated blocks so that everything after them is still usable
+        while let Some((block, at)) = parser.open_blocks.pop() {
+            let keyword = block.map_or("rect", FragmentKind::as_mermaid_str);
+            parser.report_at(at, Severity::Error, format!("Unclosed '{keyword}' block"));
+            parser.diagram.events.push(if block.is_some() {
+                Event::FragmentEnd
+            } else {
+                Event::HighlightEnd
+            });
+        }
 
-                let Some(colon_pos) = after_pos.find(':') else {
-                    bail!("Invalid note syntax (missing ':'): {line}");
-                };
+        let MermaidParser {
+            mut diagram,
+            diagnostics,
+            ..
+        } = parser;
+        let diagnostics = diagram.locate_events(diagnostics);
+        diagram.source = Some(SourceMap::new(input, &raw, diagram.canonical_lines()));
+        (diagram, diagnostics)
+    }
+}
 
-                let participants_str = after_pos[..colon_pos].trim();
-                let text = after_pos[colon_pos + 1..].trim().to_string();
+/// State of an import in progress.
+#[derive(Default)]
+struct MermaidParser {
+    diagram: SequenceDiagram,
+    /// Problems found so far, with the index of the event they are nearest to.
+    diagnostics: Vec<(ParseError, usize)>,
+    /// The current line number and the columns of its statement.
+    line: usize,
+    columns: Range<usize>,
+    /// The open box, with its first participant and the number of blocks open around it.
+    current_box: Option<(BoxColor, String, usize, usize)>,
+    /// Open blocks, innermost last, with the line they start on; `None` is a highlight region.
+    open_blocks: Vec<(Option<FragmentKind>, Location)>,
+    /// A `create` or `destroy` statement waiting for its message.
+    pending_lifecycle: Option<(&'static str, ParticipantId, Location)>,
+    /// The YAML front matter being read, with the line it starts on.
+    front_matter: Option<(FrontMatterReader, Location)>,
+    /// A multi-line `accDescr { ... }` being read, with the line it starts on.
+    acc_descr_block: Option<(Vec<String>, Location)>,
+}
 
-                if position == NotePosition::Over && participants_str.contains(',') {
-                    let parts: Vec<&str> = participants_str.split(',').map(str::trim).collect();
-                    if parts.len() != 2 {
-                        bail!("Note over must have exactly 2 participants: {line}");
-                    }
-                    let start_name = parts[0];
-                    let end_name = parts[1];
+/// A line number, the columns of its statement and the number of events before it.
+type Location = (usize, Range<usize>, usize);
 
-                    if !diagram.participants.contains(&start_name.to_string()) {
-                        diagram.participants.push(start_name.to_string());
-                    }
-                    if !diagram.participants.contains(&end_name.to_string()) {
-                        diagram.participants.push(end_name.to_string());
-                    }
+impl MermaidParser {
+    fn location(&self) -> Location {
+        (self.line, self.columns.clone(), self.diagram.events.len())
+    }
+
+    fn report(&mut self, severity: Severity, message: impl Into<String>) {
+        self.report_at(self.location(), severity, message);
+    }
 
-                    let start_idx = diagram
-                        .participants
-                        .iter()
-                        .position(|p| p == start_name)
-                        .unwrap();
-                    let end_idx = diagram
-                        .participants
-                        .iter()
-                        .position(|p| p == end_name)
-                        .unwrap();
-
-                    diagram.events.push(Event::Note {
-                        position,
-                        participant_start: start_idx,
-                        participant_end: end_idx,
-                        text,
-                    });
+    fn report_at(
+        &mut self,
+        (line, columns, event): Location,
+        severity: Severity,
+        message: impl Into<String>,
+    ) {
+        let diagnostic = ParseError {
+            severity,
+            line,
+            columns,
+            message: message.into(),
+            event: None,
+        };
+        self.diagnostics.push((diagnostic, event));
+    }
+
+    /// Reads a line of the front matter, returning whether it is supported.
+    fn front_matter_line(&mut self, line: &str) -> bool {
+        let trimmed = line.trim();
+        if trimmed == "---" {
+            self.front_matter = None;
+            return true;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return false;
+        }
+        let Some((reader, _)) = &mut self.front_matter else {
+            return false;
+        };
+        let metadata = &mut self.diagram.metadata;
+        match reader.read(line) {
+            Ok(FrontMatterEntry::Map) => {}
+            Ok(FrontMatterEntry::Value(key, value)) => {
+                if key == "title" {
+                    metadata.title = Some(unquote(&value));
+                    metadata.title_in_front_matter = true;
+                } else if let Some(key) = key.strip_prefix("config.") {
+                    metadata.config.push((key.to_string(), value));
                 } else {
-                    let name = participants_str;
-                    if name.is_empty() {
-                        bail!("Invalid note syntax: {line}");
-                    }
+                    metadata.extra.push((key, value));
+                }
+            }
+            Err(error) => {
+                self.report(Severity::Warning, format!("{error}, kept as is"));
+                return false;
+            }
+        }
+        true
+    }
 
-                    if !diagram.participants.contains(&name.to_string()) {
-                        diagram.participants.push(name.to_string());
-                    }
+    /// Reads a line of a multi-line `accDescr`, which ends at the first `}`.
+    fn acc_descr_line(&mut self, trimmed: &str) {
+        let Some((lines, _)) = &mut self.acc_descr_block else {
+            return;
+        };
+        match trimmed.split_once('}') {
+            Some((text, _)) => {
+                lines.push(decode_text(text.trim()));
+                let (lines, _) = self.acc_descr_block.take().unwrap_or_default();
+                self.diagram.metadata.acc_descr = Some(join_block(&lines));
+            }
+            None => lines.push(decode_text(trimmed)),
+        }
+    }
 
-                    let idx = diagram.participants.iter().position(|p| p == name).unwrap();
+    /// Parses `title`, `accTitle` and `accDescr` statements, returning whether it was one.
+    fn metadata_statement(&mut self, trimmed: &str) -> bool {
+        let metadata = &mut self.diagram.metadata;
+        if let Some(title) = trimmed
+            .strip_prefix("title:")
+            .or_else(|| trimmed.strip_prefix("title "))
+        {
+            metadata.title = Some(decode_text(title.trim()));
+            metadata.title_in_front_matter = false;
+        } else if let Some(acc_title) = trimmed.strip_prefix("accTitle:") {
+            metadata.acc_title = Some(decode_text(acc_title.trim()));
+        } else if let Some(acc_descr) = trimmed.strip_prefix("accDescr:") {
+            metadata.acc_descr = Some(decode_text(acc_descr.trim()));
+        } else if let Some(rest) = trimmed
+            .strip_prefix("accDescr")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('{'))
+        {
+            self.acc_descr_block = Some((Vec::new(), self.location()));
+            let rest = rest.trim();
+            if !rest.is_empty() {
+                self.acc_descr_line(rest);
+            }
+        } else {
+            return false;
+        }
+        true
+    }
 
-                    diagram.events.push(Event::Note {
-                        position,
-                        participant_start: idx,
-                        participant_end: idx,
-                        text,
-                    });
+    /// Parses a statement, returning whether it is supported.
+    fn statement(&mut self, trimmed: &str) -> Result<bool> {
+        if self.metadata_statement(trimmed) {
+            return Ok(true);
+        }
+
+        // Parse participant, optionally created by the next message
+        let (create, declaration_line) = match trimmed.strip_prefix("create ") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, trimmed),
+        };
+        let declaration = declaration_line
+            .strip_prefix("participant ")
+            .map(|rest| (ParticipantKind::Participant, rest))
+            .or_else(|| {
+                declaration_line
+                    .strip_prefix("actor ")
+                    .map(|rest| (ParticipantKind::Actor, rest))
+            });
+        if let Some((mut kind, rest)) = declaration {
+            let mut rest = rest.to_string();
+            // Parse `@{ "type": "..." }` metadata
+            if let Some(open) = rest.find("@{") {
+                let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+                    bail!("Unclosed participant metadata");
+                };
+                let Some(metadata_kind) = ParticipantKind::from_metadata(&rest[open + 1..=close])
+                else {
+                    bail!("Unsupported participant type");
+                };
+                kind = metadata_kind;
+                rest.replace_range(open..=close, "");
+            }
+            let participant = Participant::from_mermaid_str(&rest);
+            if participant.id.is_empty() {
+                bail!("Invalid participant declaration");
+            }
+            let id = self.diagram.ensure_participant(&participant.id);
+            if let Some(existing) = self.diagram.participants.by_id_mut(id) {
+                if let Some(label) = participant.label {
+                    existing.label = Some(decode_text(&label));
                 }
-                continue;
+                existing.kind = kind;
+            }
+            if create {
+                self.pending_lifecycle = Some(("create", id, self.location()));
+            }
+            return Ok(true);
+        }
+        if create {
+            bail!("Invalid create declaration");
+        }
+
+        // Parse destroy
+        if let Some(name) = trimmed.strip_prefix("destroy ") {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("Invalid destroy syntax");
             }
+            let id = self.diagram.ensure_participant(name);
+            self.pending_lifecycle = Some(("destroy", id, self.location()));
+            return Ok(true);
+        }
 
-            // Parse message
-            if let Some(arrow_pos) = trimmed.find("->>") {
-                let from_name = trimmed[..arrow_pos].trim();
-                let rest = &trimmed[arrow_pos + 3..];
+        if let Some(rest) = trimmed.strip_prefix("Note ") {
+            let (position, after_pos) = if let Some(after) = rest.strip_prefix("right of ") {
+                (NotePosition::Right, after)
+            } else if let Some(after) = rest.strip_prefix("left of ") {
+                (NotePosition::Left, after)
+            } else if let Some(after) = rest.strip_prefix("over ") {
+                (NotePosition::Over, after)
+            } else {
+                bail!("Invalid note position");
+            };
 
-                let (to_name, message) = if let Some(colon_pos) = rest.find(':') {
-                    let to = rest[..colon_pos].trim();
-                    let msg = rest[colon_pos + 1..].trim();
-                    (to, msg.to_string())
-                } else {
-                    bail!("Invalid message syntax (missing ':'): {line}");
-                };
+            let Some(colon_pos) = after_pos.find(':') else {
+                bail!("Invalid note syntax (missing ':')");
+            };
+
+            let participants_str = after_pos[..colon_pos].trim();
+            let text = decode_text(after_pos[colon_pos + 1..].trim());
+
+            if position == NotePosition::Over && participants_str.contains(',') {
+                let parts: Vec<&str> = participants_str.split(',').map(str::trim).collect();
+                if parts.len() != 2 {
+                    bail!("Note over must have exactly 2 participants");
+                }
+                let start_idx = self.diagram.ensure_participant(parts[0]);
+                let end_idx = self.diagram.ensure_participant(parts[1]);
+
+                self.diagram.events.push(Event::Note {
+                    position,
+                    participant_start: start_idx,
+                    participant_end: end_idx,
+                    text,
+                });
+            } else {
+                let name = participants_str;
+                if name.is_empty() {
+                    bail!("Invalid note syntax");
+                }
+
+                let idx = self.diagram.ensure_participant(name);
+
+                self.diagram.events.push(Event::Note {
+                    position,
+                    participant_start: idx,
+                    participant_end: idx,
+                    text,
+                });
+            }
+            return Ok(true);
+        }
+
+        // Parse autonumber
+        if let Some(args) = trimmed.strip_prefix("autonumber")
+            && (args.is_empty() || args.starts_with(char::is_whitespace))
+        {
+            if args.trim() == "off" {
+                self.diagram.autonumber = None;
+            } else if let Some(autonumber) = Autonumber::from_mermaid_args(args) {
+                self.diagram.autonumber = Some(autonumber);
+            } else {
+                bail!("Invalid autonumber");
+            }
+            return Ok(true);
+        }
+
+        // Parse fragment blocks
+        let (keyword, label) = trimmed
+            .split_once(char::is_whitespace)
+            .map_or((trimmed, ""), |(k, l)| (k, l.trim()));
+        if let Some(kind) = FragmentKind::from_mermaid_str(keyword) {
+            self.open_blocks.push((Some(kind), self.location()));
+            self.diagram.events.push(Event::FragmentStart {
+                kind,
+                label: decode_text(label),
+            });
+            return Ok(true);
+        }
+        if keyword == "rect" {
+            let Some(color) = RgbColor::from_mermaid_str(label) else {
+                bail!("Invalid rect color");
+            };
+            self.open_blocks.push((None, self.location()));
+            self.diagram.events.push(Event::HighlightStart { color });
+            return Ok(true);
+        }
+        if matches!(keyword, "else" | "and" | "option") {
+            let expected = self
+                .open_blocks
+                .last()
+                .and_then(|(block, _)| *block)
+                .and_then(FragmentKind::section_keyword);
+            if expected != Some(keyword) {
+                bail!("Unexpected '{keyword}'");
+            }
+            self.diagram.events.push(Event::FragmentSection {
+                label: decode_text(label),
+            });
+            return Ok(true);
+        }
+
+        // Parse activation
+        let activation = if let Some(rest) = trimmed.strip_prefix("activate ") {
+            Some((rest, true))
+        } else {
+            trimmed
+                .strip_prefix("deactivate ")
+                .map(|rest| (rest, false))
+        };
+        if let Some((name, active)) = activation {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("Invalid activation syntax");
+            }
+            let participant = self.diagram.ensure_participant(name);
+            self.diagram.events.push(Event::Activation {
+                participant,
+                active,
+            });
+            return Ok(true);
+        }
+
+        // Parse message
+        if let Some(tokens) = split_message(trimmed) {
+            let Some(text) = tokens.text else {
+                bail!("Invalid message syntax (missing ':')");
+            };
+            if tokens.from.is_empty() || tokens.to.is_empty() {
+                bail!("Invalid message syntax");
+            }
 
-                if from_name.is_empty() || to_name.is_empty() {
-                    bail!("Invalid message syntax: {line}");
-                }
+            let from_idx = self.diagram.ensure_participant(tokens.from);
+            let to_idx = self.diagram.ensure_participant(tokens.to);
+
+            let lifecycle = match self.pending_lifecycle.take() {
+                None => Lifecycle::None,
+                Some(("create", p, _)) if p == to_idx => Lifecycle::CreateTarget,
+                Some(("destroy", p, _)) if p == to_idx => Lifecycle::DestroyTarget,
+                Some(("destroy", p, _)) if p == from_idx => Lifecycle::DestroySender,
+                Some((keyword, p, _)) => bail!(
+                    "'{keyword} {}' must be followed by a message involving it",
+                    self.diagram.mermaid_id(p).unwrap_or_default()
+                ),
+            };
+
+            self.diagram.events.push(Event::Message {
+                from: from_idx,
+                to: to_idx,
+                text: decode_text(text),
+                arrow: tokens.arrow,
+                activation: tokens.activation,
+                lifecycle,
+            });
+            return Ok(true);
+        }
 
-                if !diagram.participants.contains(&from_name.to_string()) {
-                    diagram.participants.push(from_name.to_string());
-                }
-                if !diagram.participants.contains(&to_name.to_string()) {
-                    diagram.participants.push(to_name.to_string());
+        // Parse box block header
+        if trimmed == "box" || trimmed.starts_with("box ") {
+            let rest = trimmed.strip_prefix("box").unwrap().trim();
+            let (color, label) = if rest.is_empty() {
+                (BoxColor::default(), String::new())
+            } else {
+                match BoxColor::split_prefix(rest) {
+                    Some((c, remainder)) => (c, decode_text(remainder)),
+                    None => (BoxColor::default(), decode_text(rest)),
                 }
+            };
+            self.current_box = Some((
+                color,
+                label,
+                self.diagram.participants.len(),
+                self.open_blocks.len(),
+            ));
+            return Ok(true);
+        }
 
-                let from_idx = diagram
-                    .participants
-                    .iter()
-                    .position(|p| p == from_name)
-                    .unwrap();
-
-                let to_idx = diagram
-                    .participants
-                    .iter()
-                    .position(|p| p == to_name)
-                    .unwrap();
-
-                diagram.events.push(Event::Message {
-                    from: from_idx,
-                    to: to_idx,
-                    text: message,
+        // Parse box, fragment or rect block end
+        if trimmed == "end" {
+            // Close whichever block was opened last
+            let box_is_innermost = self
+                .current_box
+                .as_ref()
+                .is_some_and(|&(.., depth)| depth == self.open_blocks.len());
+            if !box_is_innermost && let Some((block, _)) = self.open_blocks.pop() {
+                self.diagram.events.push(if block.is_some() {
+                    Event::FragmentEnd
+                } else {
+                    Event::HighlightEnd
                 });
-                continue;
+            } else if let Some((color, label, start, _)) = self.current_box.take() {
+                let end = self.diagram.participants.len().saturating_sub(1);
+                if end >= start {
+                    self.diagram.add_box(label, color, start, end);
+                }
+            } else {
+                bail!("Unexpected 'end'");
             }
+            return Ok(true);
+        }
 
-            // Parse box block header
-            if trimmed == "box" || trimmed.starts_with("box ") {
-                let rest = trimmed.strip_prefix("box").unwrap().trim();
-                let (color, label) = if rest.is_empty() {
-                    (BoxColor::default(), String::new())
-                } else {
-                    let (first_word, remainder) = rest
-                        .split_once(|c: char| c.is_whitespace())
-                        .map_or((rest, ""), |(f, r)| (f, r.trim()));
-                    if let Some(c) = BoxColor::from_mermaid_str(first_word) {
-                        (c, remainder.to_string())
-                    } else {
-                        (BoxColor::default(), rest.to_string())
-                    }
-                };
-                current_box = Some((color, label, diagram.participants.len()));
-                continue;
-            }
+        Ok(false)
+    }
+}
 
-            // Parse box block end
-            if trimmed == "end" {
-                if let Some((color, label, start)) = current_box.take() {
-                    let end = diagram.participants.len().saturating_sub(1);
-                    if end >= start {
-                        diagram.boxes.push(ParticipantBox {
-                            label,
-                            color,
-                            start,
-                            end,
-                        });
-                    }
-                } else {
-                    bail!("Unexpected 'end'");
-                }
-                continue;
-            }
+/// The 1-based columns of the statement at byte `offset` of `line`, without surrounding
+/// whitespace.
+fn columns(line: &str, offset: usize) -> Range<usize> {
+    let statement = split_statements(&line[offset..])[0].1;
+    let start = line[..offset].chars().count()
+        + statement.chars().take_while(|c| c.is_whitespace()).count()
+        + 1;
+    start..start + statement.trim().chars().count()
+}
 
-            bail!("Unsupported mermaid feature: {trimmed}");
-        }
+/// The 1-based columns of `line` without surrounding whitespace.
+fn trimmed_columns(line: &str) -> Range<usize> {
+    let start = line.chars().take_while(|c| c.is_whitespace()).count() + 1;
+    start..start + line.trim().chars().count()
+}
+
+/// Joins the lines of a multi-line `accDescr`, dropping blank lines at either end.
+fn join_block(lines: &[String]) -> String {
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+/// Blank lines, comments and `%%{init}%%` directives.
+fn is_trivia(line: &str) -> bool {
+    line.is_empty() || line.starts_with("%%")
+}
 
-        Ok(diagram)
+fn with_label(indent: &str, keyword: &str, label: &str) -> String {
+    if label.is_empty() {
+        format!("{indent}{keyword}")
+    } else {
+        format!("{indent}{keyword} {}", encode_text(label))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::{
+        ArrowHead, Autonumber, FragmentKind, Lifecycle, LineStyle, ParticipantKind, RgbColor,
+    };
+    use crate::core::{is_valid_id, wrap_text};
+
+    /// The blocks of `diagram` that are fragments rather than highlights.
+    fn fragments(diagram: &SequenceDiagram) -> Vec<BlockBounds> {
+        diagram
+            .blocks()
+            .into_iter()
+            .filter(|b| matches!(diagram.events[b.start], Event::FragmentStart { .. }))
+            .collect()
+    }
+
+    fn span(diagram: &SequenceDiagram, b: usize) -> (usize, usize) {
+        diagram.box_span(&diagram.boxes[b]).unwrap()
+    }
 
     #[test]
     fn test_box_roundtrip() {
@@ -506,8 +1513,53 @@ mod tests {
         assert_eq!(parsed.boxes.len(), 1);
         assert_eq!(parsed.boxes[0].label, "Frontend");
         assert_eq!(parsed.boxes[0].color, BoxColor::Blue);
-        assert_eq!(parsed.boxes[0].start, 0);
-        assert_eq!(parsed.boxes[0].end, 1);
+        assert_eq!(span(&parsed, 0), (0, 1));
+    }
+
+    #[test]
+    fn test_box_custom_colors() {
+        let input = "sequenceDiagram
+    box rgb(33, 66,99) Backend
+        participant A
+    end
+    box transparent Aqua
+        participant B
+    end
+    box #f80 Hex
+        participant C
+    end
+    box rgba(0, 0, 0, 0.5)
+        participant D
+    end";
+        let parsed = SequenceDiagram::from_mermaid(input).unwrap();
+        let colors: Vec<(BoxColor, &str)> = parsed
+            .boxes
+            .iter()
+            .map(|b| (b.color, b.label.as_str()))
+            .collect();
+        assert_eq!(
+            colors,
+            vec![
+                (BoxColor::Rgb(RgbColor::new(33, 66, 99)), "Backend"),
+                (BoxColor::Transparent, "Aqua"),
+                (BoxColor::Rgb(RgbColor::new(255, 136, 0)), "Hex"),
+                (
+                    BoxColor::Rgb(RgbColor {
+                        alpha: Some(128),
+                        ..RgbColor::default()
+                    }),
+                    ""
+                ),
+            ]
+        );
+
+        let canonical = parsed.canonical_lines();
+        assert!(canonical.contains(&"    box rgb(33, 66, 99) Backend".to_string()));
+        assert!(canonical.contains(&"    box transparent Aqua".to_string()));
+        assert!(canonical.contains(&"    box rgba(0, 0, 0, 0.5)".to_string()));
+        let reparsed = SequenceDiagram::from_mermaid(&canonical.join("\n")).unwrap();
+        let reparsed: Vec<BoxColor> = reparsed.boxes.iter().map(|b| b.color).collect();
+        assert_eq!(reparsed, colors.iter().map(|(c, _)| *c).collect::<Vec<_>>());
     }
 
     #[test]
@@ -520,13 +1572,11 @@ mod tests {
 
         diagram.remove_participant(0); // remove Alice
         assert_eq!(diagram.boxes.len(), 1);
-        assert_eq!(diagram.boxes[0].start, 0);
-        assert_eq!(diagram.boxes[0].end, 1);
+        assert_eq!(span(&diagram, 0), (0, 1));
 
         diagram.remove_participant(1); // remove Carol (now at index 1)
         assert_eq!(diagram.boxes.len(), 1);
-        assert_eq!(diagram.boxes[0].start, 0);
-        assert_eq!(diagram.boxes[0].end, 0);
+        assert_eq!(span(&diagram, 0), (0, 0));
     }
 
     #[test]
@@ -544,6 +1594,28 @@ mod tests {
         assert_eq!(parsed.boxes[0].label, "");
     }
 
+    #[test]
+    fn test_box_nested_blocks() {
+        let input = "sequenceDiagram\nbox Aqua Team\nparticipant A\nloop Retry\nA->>A: ping\nend\nend\nA->>A: done";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.boxes.len(), 1);
+        assert_eq!(diagram.boxes[0].label, "Team");
+        assert_eq!(fragments(&diagram).len(), 1);
+        assert_eq!(diagram.event_count(), 4);
+
+        let input = "sequenceDiagram\nloop Retry\nbox Team\nparticipant A\nend\nA->>A: ping\nend";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.boxes.len(), 1);
+        assert_eq!(fragments(&diagram).len(), 1);
+    }
+
+    #[test]
+    fn test_from_mermaid_byte_order_mark() {
+        let diagram = SequenceDiagram::from_mermaid("\u{feff}sequenceDiagram\nA->>B: hi").unwrap();
+        assert_eq!(diagram.participants.len(), 2);
+        assert_eq!(diagram.event_count(), 1);
+    }
+
     #[test]
     fn test_box_remove_single_participant() {
         let mut diagram = SequenceDiagram::new();
@@ -559,6 +1631,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_box_move_participant() {
+        let mut diagram = SequenceDiagram::new();
+        for name in ["A", "B", "C", "D"] {
+            diagram.add_participant(name.to_string());
+        }
+        diagram.add_box("Middle".to_string(), BoxColor::Blue, 1, 2);
+
+        // The first member takes its box along across the edge
+        assert_eq!(diagram.move_participant(1, false), Some(0));
+        assert_eq!(diagram.participants, vec!["B", "C", "A", "D"]);
+        assert_eq!(span(&diagram, 0), (0, 1));
+
+        // A neighbour moves past the whole box
+        assert_eq!(diagram.move_participant(2, false), Some(0));
+        assert_eq!(diagram.participants, vec!["A", "B", "C", "D"]);
+        assert_eq!(span(&diagram, 0), (1, 2));
+        assert_eq!(diagram.move_participant(3, false), Some(1));
+        assert_eq!(diagram.participants, vec!["A", "D", "B", "C"]);
+        assert_eq!(span(&diagram, 0), (2, 3));
+
+        // Inside the box members swap places
+        assert_eq!(diagram.move_participant(2, true), Some(3));
+        assert_eq!(diagram.participants, vec!["A", "D", "C", "B"]);
+        assert_eq!(span(&diagram, 0), (2, 3));
+        assert_eq!(diagram.move_participant(3, true), None);
+
+        diagram.source = None;
+        assert!(diagram.to_mermaid().contains(
+            "    participant D\n    box Blue Middle\n        participant C\n        participant B\n    end"
+        ));
+    }
+
+    #[test]
+    fn test_box_editing() {
+        let mut diagram = SequenceDiagram::new();
+        for name in ["A", "B", "C", "D", "E"] {
+            diagram.add_participant(name.to_string());
+        }
+        diagram.add_box("Front".to_string(), BoxColor::Blue, 0, 1);
+        diagram.add_box("Back".to_string(), BoxColor::Green, 3, 4);
+
+        assert!(!diagram.update_box(0, "Front".to_string(), BoxColor::Red, 0, 3));
+        assert_eq!(
+            (span(&diagram, 0), diagram.boxes[0].color),
+            ((0, 1), BoxColor::Blue)
+        );
+        assert!(diagram.update_box(0, "Web".to_string(), BoxColor::Red, 0, 2));
+        assert_eq!(diagram.boxes[0].label, "Web");
+        assert_eq!(span(&diagram, 0), (0, 2));
+
+        // Leaving from the middle moves the participant past the end of its box
+        assert_eq!(diagram.toggle_box_membership(1), Some(2));
+        assert_eq!(diagram.participants, vec!["A", "C", "B", "D", "E"]);
+        assert_eq!(span(&diagram, 0), (0, 1));
+
+        // The first member leaves at the start, and joins again from there
+        assert_eq!(diagram.toggle_box_membership(3), Some(3));
+        assert_eq!(span(&diagram, 1), (4, 4));
+        assert_eq!(diagram.toggle_box_membership(3), Some(3));
+        assert_eq!(span(&diagram, 1), (3, 4));
+
+        // Between two boxes, a participant joins the one before it
+        assert_eq!(diagram.toggle_box_membership(2), Some(2));
+        assert_eq!((span(&diagram, 0).1, span(&diagram, 1).0), (2, 3));
+
+        assert!(diagram.update_box(1, "Back".to_string(), BoxColor::Green, 4, 4));
+        assert_eq!(diagram.toggle_box_membership(4), None);
+    }
+
     #[test]
     fn test_box_for_participant() {
         let mut diagram = SequenceDiagram::new();
@@ -599,6 +1741,387 @@ mod tests {
         assert_eq!(diagram.events.len(), 2);
     }
 
+    #[test]
+    fn test_arrow_styles_roundtrip() {
+        let input = "sequenceDiagram
+    participant Alice
+    participant Bob
+    Alice->>Bob: solid arrow
+    Bob-->>Alice: dotted arrow
+    Alice->Bob: solid open
+    Bob-->Alice: dotted open
+    Alice-xBob: solid cross
+    Bob--xAlice: dotted cross
+    Alice-)Bob: solid async
+    Bob--)Alice: dotted async
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.participants, vec!["Alice", "Bob"]);
+        assert_eq!(diagram.events.len(), 8);
+        if let Event::Message { arrow, text, .. } = &diagram.events[1] {
+            assert_eq!(arrow.line, LineStyle::Dotted);
+            assert_eq!(arrow.head, ArrowHead::Arrow);
+            assert_eq!(text, "dotted arrow");
+        } else {
+            panic!("Expected Message event");
+        }
+        assert_eq!(diagram.to_mermaid(), input);
+    }
+
+    #[test]
+    fn test_arrow_with_dashed_participant_name() {
+        let input = "sequenceDiagram
+    api-gateway-->>auth-service: Token";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.participants, vec!["api-gateway", "auth-service"]);
+    }
+
+    #[test]
+    fn test_participant_alias_roundtrip() {
+        let input = "sequenceDiagram
+    participant API as Public API Gateway
+    box Green Backend
+        participant DB as Main Database
+    end
+    participant Client
+    Client->>API: Request
+    API->>DB: Query
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.participants, vec!["API", "DB", "Client"]);
+        assert_eq!(diagram.participants[0].display_name(), "Public API Gateway");
+        assert_eq!(diagram.participants[2].display_name(), "Client");
+        assert_eq!(diagram.to_mermaid(), input);
+
+        // An alias declared after first use labels the existing participant.
+        let late = "sequenceDiagram
+    A->>B: Hi
+    participant B as Bob";
+        let diagram = SequenceDiagram::from_mermaid(late).unwrap();
+        assert_eq!(diagram.participants.len(), 2);
+        assert_eq!(diagram.participants[1].label.as_deref(), Some("Bob"));
+    }
+
+    #[test]
+    fn test_participant_kind_roundtrip() {
+        let input = "sequenceDiagram
+    actor U as User
+    participant API@{ \"type\": \"boundary\" }
+    participant Svc@{ \"type\": \"control\" }
+    participant Order@{ \"type\": \"entity\" }
+    participant DB@{ \"type\": \"database\" } as Main DB
+    participant Files@{ \"type\": \"collections\" }
+    participant Jobs@{ \"type\": \"queue\" }
+    U->>API: Request
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        let kinds: Vec<ParticipantKind> = diagram.participants.iter().map(|p| p.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ParticipantKind::Actor,
+                ParticipantKind::Boundary,
+                ParticipantKind::Control,
+                ParticipantKind::Entity,
+                ParticipantKind::Database,
+                ParticipantKind::Collections,
+                ParticipantKind::Queue,
+            ]
+        );
+        assert_eq!(diagram.participants[4].display_name(), "Main DB");
+        assert_eq!(diagram.to_mermaid(), input);
+
+        let compact = "sequenceDiagram
+    participant DB@{\"type\":\"database\"}";
+        let diagram = SequenceDiagram::from_mermaid(compact).unwrap();
+        assert_eq!(diagram.participants[0].kind, ParticipantKind::Database);
+
+        let unknown = "sequenceDiagram
+    participant X@{ \"type\": \"spaceship\" }";
+        assert!(SequenceDiagram::from_mermaid(unknown).is_err());
+    }
+
+    #[test]
+    fn test_autonumber() {
+        let input = "sequenceDiagram
+    autonumber 10 5
+    participant Alice
+    participant Bob
+    Alice->>Bob: One
+    Note right of Bob: Thinking
+    Bob-->>Alice: Two
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.autonumber, Some(Autonumber { start: 10, step: 5 }));
+        assert_eq!(diagram.message_numbers(), vec![Some(10), None, Some(15)]);
+        assert_eq!(diagram.find_message_number(15), Some(2));
+        assert_eq!(diagram.find_message_number(12), None);
+        assert_eq!(diagram.to_mermaid(), input);
+
+        let mut plain = SequenceDiagram::from_mermaid("sequenceDiagram\n    autonumber").unwrap();
+        assert_eq!(plain.autonumber, Some(Autonumber::default()));
+        plain.source = None;
+        assert!(plain.to_mermaid().contains("    autonumber\n"));
+
+        let off = SequenceDiagram::from_mermaid("sequenceDiagram\n    A->>B: Hi").unwrap();
+        assert_eq!(off.message_numbers(), vec![None]);
+        assert_eq!(off.find_message_number(1), Some(0));
+
+        assert!(SequenceDiagram::from_mermaid("sequenceDiagram\n    autonumber x").is_err());
+    }
+
+    #[test]
+    fn test_create_destroy_roundtrip() {
+        let input = "sequenceDiagram
+    participant Alice
+    participant Bob
+    Alice->>Bob: Hello
+    create participant Carl
+    Alice->>Carl: Hi Carl
+    create actor D as Donald
+    Carl->>D: Hi
+    destroy Carl
+    Alice-xCarl: We are too many
+    destroy Bob
+    Bob->>Alice: I agree
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.participants, vec!["Alice", "Bob", "Carl", "D"]);
+        assert_eq!(diagram.participants[3].kind, ParticipantKind::Actor);
+        assert_eq!(
+            diagram.lifespans(),
+            vec![
+                (None, None),
+                (None, Some(4)),
+                (Some(1), Some(3)),
+                (Some(2), None)
+            ]
+        );
+        assert_eq!(diagram.to_mermaid(), input);
+
+        let dangling = "sequenceDiagram
+    create participant Carl
+    Note over Carl: Hi";
+        assert!(SequenceDiagram::from_mermaid(dangling).is_err());
+
+        let unrelated = "sequenceDiagram
+    destroy Carl
+    Alice->>Bob: Hi";
+        assert!(SequenceDiagram::from_mermaid(unrelated).is_err());
+    }
+
+    #[test]
+    fn test_toggle_lifecycle() {
+        let mut diagram = SequenceDiagram::new();
+        diagram.add_participant("Alice".to_string());
+        diagram.add_participant("Bob".to_string());
+        diagram.add_message(0, 1, "One".to_string());
+        diagram.add_message(0, 1, "Two".to_string());
+
+        diagram.toggle_lifecycle(0, Lifecycle::CreateTarget);
+        assert_eq!(diagram.lifespans()[1], (Some(0), None));
+
+        // Only one message can create a participant.
+        diagram.toggle_lifecycle(1, Lifecycle::CreateTarget);
+        assert_eq!(diagram.lifespans()[1], (Some(1), None));
+
+        diagram.toggle_lifecycle(1, Lifecycle::CreateTarget);
+        assert_eq!(diagram.lifespans()[1], (None, None));
+    }
+
+    #[test]
+    fn test_activation_roundtrip() {
+        let input = "sequenceDiagram
+    participant Alice
+    participant Bob
+    Alice->>+Bob: Request
+    activate Bob
+    Bob->>Bob: Work
+    deactivate Bob
+    Bob-->>-Alice: Response
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.events.len(), 5);
+        if let Event::Message { activation, .. } = &diagram.events[0] {
+            assert_eq!(*activation, Activation::Activate);
+        } else {
+            panic!("Expected Message event");
+        }
+        assert_eq!(
+            diagram.events[1],
+            Event::Activation {
+                participant: diagram.participant_at(1).unwrap(),
+                active: true
+            }
+        );
+        assert_eq!(diagram.to_mermaid(), input);
+    }
+
+    #[test]
+    fn test_activation_depths() {
+        let input = "sequenceDiagram
+    Alice->>+Bob: Request
+    activate Bob
+    deactivate Bob
+    Bob-->>-Alice: Response
+    deactivate Alice";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        let depths = diagram.activation_depths();
+        assert_eq!(
+            depths,
+            vec![vec![0, 1], vec![0, 2], vec![0, 1], vec![0, 0], vec![0, 0],]
+        );
+    }
+
+    #[test]
+    fn test_fragment_roundtrip() {
+        let input = "sequenceDiagram
+    participant Alice
+    participant Bob
+    loop Every minute
+        Alice->>Bob: Ping
+        alt is healthy
+            Bob->>Alice: Pong
+        else is sick
+            Bob-xAlice: Error
+        end
+    end
+    par
+        Alice->>Bob: A
+    and Second branch
+        Alice->>Bob: B
+    end
+    critical Connect
+        Alice->>Bob: Open
+    option Timeout
+        Alice->>Alice: Retry
+    end
+    opt Maybe
+        Alice->>Bob: Hello
+    end
+    break Failed
+        Bob->>Alice: Abort
+    end
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        let fragments = fragments(&diagram);
+        assert_eq!(fragments.len(), 6);
+        assert_eq!(fragments[0].start, 0);
+        assert_eq!(fragments[0].end, Some(7));
+        assert_eq!(fragments[1].depth, 1);
+        assert_eq!(fragments[1].sections, vec![4]);
+        assert_eq!(diagram.to_mermaid(), input);
+    }
+
+    #[test]
+    fn test_fragment_invalid() {
+        let unclosed = "sequenceDiagram
+    loop Forever
+    Alice->>Bob: Hi";
+        assert!(SequenceDiagram::from_mermaid(unclosed).is_err());
+
+        let misplaced_else = "sequenceDiagram
+    loop Forever
+    Alice->>Bob: Hi
+    else Never
+    end";
+        assert!(SequenceDiagram::from_mermaid(misplaced_else).is_err());
+    }
+
+    #[test]
+    fn test_wrap_and_unwrap_fragment() {
+        let mut diagram = SequenceDiagram::new();
+        diagram.add_participant("Alice".to_string());
+        diagram.add_participant("Bob".to_string());
+        diagram.add_message(0, 1, "One".to_string());
+        diagram.add_message(1, 0, "Two".to_string());
+        diagram.add_message(0, 1, "Three".to_string());
+
+        assert!(diagram.wrap_in_fragment(0, 1, FragmentKind::Alt, "ok".to_string()));
+        assert_eq!(diagram.event_count(), 5);
+        assert!(diagram.add_fragment_section(1, "fail".to_string()));
+        assert_eq!(fragments(&diagram)[0].sections, vec![2]);
+        assert_eq!(diagram.participant_span(0, 4), Some((0, 1)));
+
+        // A range that cuts through the alt fragment is rejected.
+        assert!(!diagram.wrap_in_fragment(3, 5, FragmentKind::Loop, String::new()));
+        assert!(diagram.wrap_in_fragment(0, 5, FragmentKind::Loop, String::new()));
+        assert_eq!(fragments(&diagram)[1].depth, 1);
+
+        // Fragment markers cannot pass each other.
+        assert!(!diagram.move_event(0, 1));
+
+        assert!(diagram.unwrap_block(3));
+        assert_eq!(fragments(&diagram).len(), 1);
+        diagram.remove_event(0);
+        assert!(fragments(&diagram).is_empty());
+        assert_eq!(diagram.event_count(), 3);
+    }
+
+    #[test]
+    fn test_highlight_roundtrip() {
+        let input = "sequenceDiagram
+    participant Alice
+    participant Bob
+    rect rgb(191, 223, 255)
+        Alice->>Bob: Hello
+        rect rgba(0, 0, 255, 0.1)
+            loop Retry
+                Bob->>Alice: Hi
+            end
+        end
+    end
+";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert!(matches!(
+            diagram.events[0],
+            Event::HighlightStart {
+                color: RgbColor {
+                    r: 191,
+                    g: 223,
+                    b: 255,
+                    alpha: None
+                }
+            }
+        ));
+        assert_eq!(diagram.blocks().len(), 3);
+        assert_eq!(fragments(&diagram)[0].depth, 2);
+        assert_eq!(diagram.to_mermaid(), input);
+
+        let invalid = "sequenceDiagram
+    rect blue
+    Alice->>Bob: Hi
+    end";
+        assert!(SequenceDiagram::from_mermaid(invalid).is_err());
+    }
+
+    #[test]
+    fn test_wrap_and_unwrap_highlight() {
+        let mut diagram = SequenceDiagram::new();
+        diagram.add_participant("Alice".to_string());
+        diagram.add_participant("Bob".to_string());
+        diagram.add_message(0, 1, "One".to_string());
+        diagram.add_message(1, 0, "Two".to_string());
+        assert!(diagram.wrap_in_fragment(0, 1, FragmentKind::Loop, String::new()));
+
+        // Highlights cannot cut through a fragment, but may surround it.
+        assert!(!diagram.wrap_in_highlight(1, 3, RgbColor::new(200, 200, 200)));
+        assert!(diagram.wrap_in_highlight(0, 3, RgbColor::new(200, 200, 200)));
+        assert_eq!(diagram.blocks()[1].depth, 1);
+        assert!(
+            diagram
+                .to_mermaid()
+                .contains("    rect rgb(200, 200, 200)\n        loop\n")
+        );
+
+        // Sections cannot be added directly inside a highlight.
+        assert!(!diagram.add_fragment_section(0, String::new()));
+
+        diagram.remove_event(5);
+        assert_eq!(diagram.blocks().len(), 1);
+        assert!(matches!(diagram.events[0], Event::FragmentStart { .. }));
+    }
+
     #[test]
     fn test_from_mermaid_auto_participants() {
         let input = "sequenceDiagram
@@ -629,7 +2152,7 @@ mod tests {
         } = &diagram.events[0]
         {
             assert_eq!(*position, NotePosition::Right);
-            assert_eq!(*participant_start, 0);
+            assert_eq!(diagram.position_of(*participant_start), Some(0));
             assert_eq!(text, "This is a note");
         } else {
             panic!("Expected Note event");
@@ -652,8 +2175,8 @@ mod tests {
         } = &diagram.events[0]
         {
             assert_eq!(*position, NotePosition::Over);
-            assert_eq!(*participant_start, 0);
-            assert_eq!(*participant_end, 1);
+            assert_eq!(diagram.position_of(*participant_start), Some(0));
+            assert_eq!(diagram.position_of(*participant_end), Some(1));
             assert_eq!(text, "Spanning note");
         } else {
             panic!("Expected Note event");
@@ -675,4 +2198,321 @@ mod tests {
         assert_eq!(parsed.participants, diagram.participants);
         assert_eq!(parsed.events.len(), 3);
     }
+
+    #[test]
+    fn test_lossless_roundtrip() {
+        let input = "%%{init: {\"theme\": \"dark\"}}%%
+sequenceDiagram
+  %% Actors
+  participant Alice
+  participant Bob
+
+  Alice->>Bob:Hello   
+  links Bob: {\"Docs\": \"https://example.com\"}
+    %% Reply
+  Bob-->>Alice: Hi
+  %% The end";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.events.len(), 2);
+        assert_eq!(diagram.to_mermaid(), input);
+
+        let crlf = "sequenceDiagram\r\n    A->>B: Hi\r\n";
+        assert_eq!(
+            SequenceDiagram::from_mermaid(crlf).unwrap().to_mermaid(),
+            crlf
+        );
+    }
+
+    #[test]
+    fn test_trivia_follows_edits() {
+        let input = "sequenceDiagram
+  participant Alice
+  participant Bob
+  %% Greeting
+  Alice->>Bob: Hello
+  %% Reply
+  Bob-->>Alice: Hi
+";
+        let mut diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        diagram.insert_message(0, 1, 0, "Who?".to_string());
+        assert_eq!(
+            diagram.to_mermaid(),
+            "sequenceDiagram
+  participant Alice
+  participant Bob
+  %% Greeting
+  Alice->>Bob: Hello
+    Bob->>Alice: Who?
+  %% Reply
+  Bob-->>Alice: Hi
+"
+        );
+
+        diagram.remove_event(0);
+        assert_eq!(
+            diagram.to_mermaid(),
+            "sequenceDiagram
+  participant Alice
+  participant Bob
+  %% Greeting
+    Bob->>Alice: Who?
+  %% Reply
+  Bob-->>Alice: Hi
+"
+        );
+    }
+
+    #[test]
+    fn test_tolerant_import() {
+        let input = "sequenceDiagram
+    Alice->>Bob: Hello
+    Note sideways of Bob: Huh
+    links Bob: {}
+    Bob-->>Alice: Hi
+    loop Forever
+    Alice->>Bob: Again";
+        let (diagram, diagnostics) = SequenceDiagram::from_mermaid_tolerant(input);
+        assert_eq!(diagram.events.len(), 5);
+        assert!(matches!(diagram.events[4], Event::FragmentEnd));
+
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                let event = d.event.and_then(|id| diagram.events.index_of(id));
+                (d.severity, d.line, d.columns.clone(), event)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Severity::Error, 3, 5..30, Some(1)),
+                (Severity::Warning, 4, 5..18, Some(1)),
+                (Severity::Error, 6, 5..17, Some(2)),
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "Invalid note position");
+
+        // Problems keep pointing at their event after edits above it.
+        let mut edited = diagram.clone();
+        edited.insert_message(0, 0, 1, "Inserted".to_string());
+        let event = diagnostics[2]
+            .event
+            .and_then(|id| edited.events.index_of(id));
+        assert_eq!(event, Some(3));
+
+        let error = SequenceDiagram::from_mermaid(input).err().unwrap();
+        assert_eq!(error.to_string(), "3:5: Invalid note position");
+        assert_eq!(
+            error.to_report("a.mmd", input),
+            "error: Invalid note position
+ --> a.mmd:3:5
+  |
+3 |     Note sideways of Bob: Huh
+  |     ^^^^^^^^^^^^^^^^^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_adversarial_roundtrip() {
+        let names = [
+            "Web: API",
+            "a->>b",
+            "x;y",
+            "#59;",
+            "\"Quoted\"",
+            "Alice as Bob",
+            "-dash",
+        ];
+        let texts = [
+            "time: 10:00",
+            "a ->> b --x c -) d",
+            "semi; colon",
+            "#59; is a semicolon, #35; a hash",
+            "issue #5 and #quot;",
+            "\"quoted\" 'text'",
+            "%% not a comment",
+            "end",
+            "ünïcødé ✓",
+            "trailing #",
+        ];
+
+        let mut diagram = SequenceDiagram::new();
+        for name in names {
+            diagram.add_participant(Participant::from_mermaid_str(name));
+        }
+        assert!(diagram.participants.iter().all(|p| is_valid_id(&p.id)));
+        assert_eq!(diagram.participants[0].display_name(), "Web: API");
+        assert_eq!(diagram.participants[5].id, "Alice");
+        assert_eq!(diagram.participants[5].display_name(), "Bob");
+
+        for (i, text) in texts.iter().enumerate() {
+            let from = i % names.len();
+            diagram.add_message(from, (from + 1) % names.len(), (*text).to_string());
+            diagram.add_note(NotePosition::Over, from, from, (*text).to_string());
+        }
+        diagram.wrap_in_fragment(0, 3, FragmentKind::Alt, "x; y #59;".to_string());
+        diagram.add_fragment_section(2, "a: b -> c".to_string());
+        diagram.add_box("Box; #1".to_string(), BoxColor::Blue, 0, 1);
+
+        let mermaid = diagram.to_mermaid();
+        let parsed = SequenceDiagram::from_mermaid(&mermaid).unwrap();
+        assert_eq!(parsed.participants, diagram.participants);
+        assert_eq!(parsed.events, diagram.events);
+        assert_eq!(parsed.boxes[0].label, "Box; #1");
+        assert_eq!(parsed.to_mermaid(), mermaid);
+    }
+
+    #[test]
+    fn test_statements_and_entities() {
+        let input = "sequenceDiagram
+    participant A as Alice#59; Bob
+    A->>B: Hi #quot;there#quot;; B-->>A: Yo #9829;
+    Note over A: #unknown; stays";
+        let diagram = SequenceDiagram::from_mermaid(input).unwrap();
+        assert_eq!(diagram.participants[0].display_name(), "Alice; Bob");
+        let texts: Vec<&str> = diagram
+            .events
+            .iter()
+            .map(|e| match e {
+                Event::Message { text, .. } | Event::Note { text, .. } => text.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(texts, vec!["Hi \"there\"", "Yo ♥", "#unknown; stays"]);
+    }
+
+    #[test]
+    fn test_multiline_text() {
+        let mut diagram = SequenceDiagram::from_mermaid(
+            "sequenceDiagram
+    A->>B: Hello<br/>there<BR />you
+    Note over A: One<br>Two",
+        )
+        .unwrap();
+        let Event::Message { text, .. } = &diagram.events[0] else {
+            panic!("Expected Message event");
+        };
+        assert_eq!(text, "Hello\nthere\nyou");
+        assert_eq!(diagram.events[0].height(0), 5);
+        assert_eq!(diagram.events[1].height(0), 3);
+        assert_eq!(diagram.events[0].height(3), 7);
+
+        diagram.add_message(1, 0, "literal <br> tag".to_string());
+        diagram.source = None;
+        let mermaid = diagram.to_mermaid();
+        assert!(mermaid.contains("A->>B: Hello<br/>there<br/>you\n"));
+        assert!(mermaid.contains("B->>A: literal #lt;br> tag\n"));
+        let parsed = SequenceDiagram::from_mermaid(&mermaid).unwrap();
+        assert_eq!(parsed.events, diagram.events);
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("aaa bbb ccc", 7), vec!["aaa bbb", "ccc"]);
+        assert_eq!(wrap_text("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap_text("a\n\nb", 0), vec!["a", "", "b"]);
+        assert_eq!(wrap_text("", 5), vec![""]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let input = "---
+title: \"Login: happy path\"
+displayMode: compact
+config:
+  sequence:
+    mirrorActors: false
+  theme: dark
+---
+sequenceDiagram
+    accTitle: Login
+    accDescr {
+        Alice logs in
+        and gets a token
+    }
+    participant A
+    participant B
+    A->>B: Hi
+";
+        let (mut diagram, diagnostics) = SequenceDiagram::from_mermaid_tolerant(input);
+        assert!(diagnostics.is_empty());
+        let metadata = &diagram.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("Login: happy path"));
+        assert!(metadata.title_in_front_matter);
+        assert_eq!(metadata.acc_title.as_deref(), Some("Login"));
+        assert_eq!(
+            metadata.acc_descr.as_deref(),
+            Some("Alice logs in\nand gets a token")
+        );
+        assert_eq!(
+            metadata.config,
+            vec![
+                ("sequence.mirrorActors".to_string(), "false".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+            ]
+        );
+        assert_eq!(diagram.to_mermaid(), input);
+
+        diagram.source = None;
+        assert_eq!(diagram.to_mermaid(), input);
+
+        diagram.metadata.title_in_front_matter = false;
+        diagram.metadata.acc_descr = Some("One line; short".to_string());
+        let mermaid = diagram.to_mermaid();
+        assert!(mermaid.contains("sequenceDiagram\n    title Login: happy path\n"));
+        assert!(mermaid.contains("    accDescr: One line#59; short\n"));
+        let parsed = SequenceDiagram::from_mermaid(&mermaid).unwrap();
+        assert_eq!(parsed.metadata, diagram.metadata);
+
+        let unclosed = "---\ntitle: x\nsequenceDiagram\n    A->>B: Hi";
+        let error = SequenceDiagram::from_mermaid(unclosed).err().unwrap();
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (1, "Unclosed front matter")
+        );
+    }
+
+    #[test]
+    fn test_event_edits_report_changes() {
+        let mut diagram =
+            SequenceDiagram::from_mermaid("sequenceDiagram\n    A->>B: hi\n    Note over A: n")
+                .unwrap();
+        assert!(!diagram.point_event_right(0), "already points right");
+        assert!(diagram.point_event_left(0));
+        for idx in [1, 2] {
+            assert!(!diagram.point_event_left(idx));
+            assert!(!diagram.cycle_arrow_head(idx));
+            assert!(!diagram.toggle_arrow_line(idx));
+            assert!(!diagram.cycle_activation(idx));
+            assert!(!diagram.toggle_lifecycle(idx, Lifecycle::CreateTarget));
+        }
+        assert!(diagram.cycle_arrow_head(0));
+        assert!(diagram.toggle_lifecycle(0, Lifecycle::DestroyTarget));
+    }
+
+    #[test]
+    fn test_stable_ids() {
+        let mut diagram =
+            SequenceDiagram::from_mermaid("sequenceDiagram\n    A->>B: one\n    B->>C: two")
+                .unwrap();
+        let first = diagram.events.id(0).unwrap();
+        let b = diagram.participant_at(1).unwrap();
+
+        diagram.participants.swap(0, 2);
+        assert_eq!(diagram.position_of(b), Some(1));
+        assert!(diagram.move_event(0, 1));
+        assert_eq!(diagram.events.index_of(first), Some(1));
+
+        diagram.remove_participant(0);
+        assert_eq!(diagram.events.len(), 1);
+        assert_eq!(diagram.events.index_of(first), Some(0));
+        assert_eq!(diagram.position_of(b), Some(0));
+
+        diagram.source = None;
+        assert_eq!(
+            diagram.to_mermaid(),
+            "sequenceDiagram\n    participant B\n    participant A\n    A->>B: one\n"
+        );
+    }
 }
@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+
+/// The original text of an imported diagram.
+///
+/// Lines the model does not interpret (comments, blank lines, `%%{init}%%` directives and
+/// unknown statements) are kept as trivia in front of the statement that follows them. On
+/// export, statements whose canonical form is unchanged are written back with their original
+/// text, so an untouched file round-trips byte for byte.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// The imported text, returned as is while the diagram is unchanged.
+    text: String,
+    /// The canonical lines of the diagram as it was imported.
+    canonical: Vec<String>,
+    /// The original text and leading trivia of each canonical line, if it has a source line.
+    lines: Vec<SourceLine>,
+    /// Trivia after the last statement.
+    trailing: Vec<String>,
+    /// The text last rendered, as the diagram is exported on every save and autosave.
+    cache: RenderCache,
+}
+
+/// The canonical lines last passed to [`SourceMap::render`] and the text it returned.
+#[derive(Clone, Debug, Default)]
+struct RenderCache(RefCell<Option<(Vec<String>, String)>>);
+
+/// A cache never makes two source maps different.
+impl PartialEq for RenderCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RenderCache {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct SourceLine {
+    raw: Option<String>,
+    leading: Vec<String>,
+}
+
+/// A line of the imported text.
+pub(crate) enum RawLine<'a> {
+    Statement(&'a str),
+    Trivia(&'a str),
+}
+
+impl SourceMap {
+    /// Matches the statement lines of `text` against the canonical lines of the parsed diagram.
+    pub(crate) fn new(text: &str, raw: &[RawLine], canonical: Vec<String>) -> Self {
+        let statements: Vec<&str> = raw
+            .iter()
+            .filter_map(|line| match line {
+                RawLine::Statement(s) => Some(*s),
+                RawLine::Trivia(_) => None,
+            })
+            .collect();
+        let statements: Vec<String> = statements.into_iter().map(normalize).collect();
+        let normalized: Vec<String> = canonical.iter().map(|line| normalize(line)).collect();
+        let pairs = common_subsequence(&statements, &normalized, |a, b| a == b);
+
+        let mut lines = vec![SourceLine::default(); canonical.len()];
+        let mut pending = Vec::new();
+        let mut statement = 0;
+        let mut pair = pairs.iter().peekable();
+        for line in raw {
+            match line {
+                RawLine::Trivia(s) => pending.push((*s).to_string()),
+                RawLine::Statement(s) => {
+                    // Statements without a canonical counterpart (such as a participant declared
+                    // after its first message) are only kept while the diagram is unchanged.
+                    if let Some(&(_, target)) = pair.next_if(|(from, _)| *from == statement) {
+                        lines[target] = SourceLine {
+                            raw: Some((*s).to_string()),
+                            leading: std::mem::take(&mut pending),
+                        };
+                    }
+                    statement += 1;
+                }
+            }
+        }
+
+        Self {
+            text: text.to_string(),
+            canonical,
+            lines,
+            trailing: pending,
+            cache: RenderCache::default(),
+        }
+    }
+
+    /// The imported text.
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Writes `canonical` back using the original text wherever it is unchanged.
+    pub(crate) fn render(&self, canonical: &[String]) -> String {
+        if canonical == self.canonical.as_slice() {
+            return self.text.clone();
+        }
+        if let Some((lines, text)) = &*self.cache.0.borrow()
+            && lines.as_slice() == canonical
+        {
+            return text.clone();
+        }
+
+        let pairs = common_subsequence(&self.canonical, canonical, |a, b| a == b);
+        let mut out: Vec<&str> = Vec::new();
+        let mut old = 0;
+        let mut new = 0;
+        for &(old_match, new_match) in pairs
+            .iter()
+            .chain(std::iter::once(&(self.canonical.len(), canonical.len())))
+        {
+            // Removed statements leave their trivia behind.
+            for line in &self.lines[old..old_match] {
+                out.extend(line.leading.iter().map(String::as_str));
+            }
+            out.extend(canonical[new..new_match].iter().map(String::as_str));
+            if let Some(line) = self.lines.get(old_match) {
+                out.extend(line.leading.iter().map(String::as_str));
+                out.push(line.raw.as_deref().unwrap_or(&canonical[new_match]));
+            }
+            old = old_match + 1;
+            new = new_match + 1;
+        }
+        out.extend(self.trailing.iter().map(String::as_str));
+
+        let mut text = out.join("\n");
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        *self.cache.0.borrow_mut() = Some((canonical.to_vec(), text.clone()));
+        text
+    }
+}
+
+/// Compares statements regardless of whitespace, so `A->>B:Hi` matches `A->>B: Hi`.
+fn normalize(line: &str) -> String {
+    line.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Index pairs of a longest common subsequence of `a` and `b`.
+///
+/// Edits usually touch a few lines in the middle, so the common prefix and suffix are matched
+/// directly and only the lines between them go through the quadratic table.
+fn common_subsequence<A, B>(a: &[A], b: &[B], eq: impl Fn(&A, &B) -> bool) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| eq(x, y)).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| eq(x, y))
+        .count();
+    let middle = middle_subsequence(
+        &a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix],
+        &eq,
+    );
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(middle.into_iter().map(|(i, j)| (prefix + i, prefix + j)));
+    pairs.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+    pairs
+}
+
+fn middle_subsequence<A, B>(a: &[A], b: &[B], eq: &impl Fn(&A, &B) -> bool) -> Vec<(usize, usize)> {
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i * width + j] = if eq(&a[i], &b[j]) {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if eq(&a[i], &b[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_subsequence() {
+        let a = ["a", "b", "c", "d", "e", "f"];
+        let b = ["a", "b", "x", "d", "c", "f"];
+        let pairs = common_subsequence(&a, &b, |x, y| x == y);
+        assert_eq!(pairs, [(0, 0), (1, 1), (3, 3), (5, 5)]);
+        assert_eq!(
+            pairs,
+            middle_subsequence(&a, &b, &|x: &&str, y: &&str| x == y)
+        );
+
+        let same = common_subsequence(&a, &a, |x, y| x == y);
+        assert_eq!(same, (0..a.len()).map(|i| (i, i)).collect::<Vec<_>>());
+        assert!(common_subsequence(&a, &[] as &[&str], |x, y| x == y).is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+/// A handle that stays valid while items are reordered, inserted or removed.
+pub trait StableId: Copy + Eq {
+    fn from_raw(raw: u32) -> Self;
+}
+
+/// Items addressed by stable ids, kept in an order of their own.
+///
+/// Indices are a view of the current order; use ids to refer to an item across edits.
+/// Lookups by id scan the order, which is fine for diagram-sized collections.
+#[derive(Clone)]
+pub struct OrderedStore<I, T> {
+    entries: Vec<(I, T)>,
+    next_id: u32,
+}
+
+impl<I, T> Default for OrderedStore<I, T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<I: StableId, T> OrderedStore<I, T> {
+    fn allocate(&mut self) -> I {
+        let id = I::from_raw(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends an item, returning its new id.
+    pub fn push(&mut self, item: T) -> I {
+        let id = self.allocate();
+        self.entries.push((id, item));
+        id
+    }
+
+    /// Inserts an item at `index`, returning its new id.
+    pub fn insert(&mut self, index: usize, item: T) -> I {
+        let id = self.allocate();
+        self.entries.insert(index, (id, item));
+        id
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.entries.pop().map(|(_, item)| item)
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        self.entries.remove(index).1
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+    }
+
+    /// Rotates the items in `range` left by `mid` places.
+    pub fn rotate_left(&mut self, range: std::ops::Range<usize>, mid: usize) {
+        self.entries[range].rotate_left(mid);
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.entries.retain(|(_, item)| keep(item));
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.entries.get(index).map(|(_, item)| item)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.entries.get_mut(index).map(|(_, item)| item)
+    }
+
+    /// The id of the item at `index`.
+    pub fn id(&self, index: usize) -> Option<I> {
+        self.entries.get(index).map(|(id, _)| *id)
+    }
+
+    /// The current index of the item with `id`.
+    pub fn index_of(&self, id: I) -> Option<usize> {
+        self.entries.iter().position(|(i, _)| *i == id)
+    }
+
+    pub fn by_id(&self, id: I) -> Option<&T> {
+        self.entries
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, item)| item)
+    }
+
+    pub fn by_id_mut(&mut self, id: I) -> Option<&mut T> {
+        self.entries
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, item)| item)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + Clone {
+        self.entries.iter().map(|(_, item)| item)
+    }
+}
+
+impl<I: StableId, T> Index<usize> for OrderedStore<I, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.entries[index].1
+    }
+}
+
+impl<I: StableId, T> IndexMut<usize> for OrderedStore<I, T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.entries[index].1
+    }
+}
+
+impl<'a, I, T> IntoIterator for &'a OrderedStore<I, T> {
+    type Item = &'a T;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, (I, T)>, fn(&'a (I, T)) -> &'a T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().map(|(_, item)| item)
+    }
+}
+
+impl<'a, I, T> IntoIterator for &'a mut OrderedStore<I, T> {
+    type Item = &'a mut T;
+    type IntoIter =
+        std::iter::Map<std::slice::IterMut<'a, (I, T)>, fn(&'a mut (I, T)) -> &'a mut T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter_mut().map(|(_, item)| item)
+    }
+}
+
+/// Compares the items in order, regardless of their ids.
+impl<I, T: PartialEq> PartialEq for OrderedStore<I, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .all(|((_, a), (_, b))| a == b)
+    }
+}
+
+impl<I, T: PartialEq<U>, U> PartialEq<Vec<U>> for OrderedStore<I, T> {
+    fn eq(&self, other: &Vec<U>) -> bool {
+        self.entries.len() == other.len()
+            && self.entries.iter().zip(other).all(|((_, a), b)| a == b)
+    }
+}
+
+impl<I: fmt::Debug, T: fmt::Debug> fmt::Debug for OrderedStore<I, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|(id, item)| (id, item)))
+            .finish()
+    }
+}
@@ -0,0 +1,179 @@
+use super::models::{Activation, Arrow};
+
+/// Named entity codes understood on import, e.g. `#quot;`.
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("quot", '"'),
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("semi", ';'),
+    ("colon", ':'),
+    ("comma", ','),
+    ("num", '#'),
+];
+
+/// Splits a line into `;`-separated statements with their byte offsets, leaving the `;` of
+/// entity codes such as `#59;` alone.
+pub(crate) fn split_statements(line: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    for (pos, c) in line.char_indices() {
+        if c == ';' && !ends_with_entity_name(&line[start..pos]) {
+            statements.push((start, &line[start..pos]));
+            start = pos + 1;
+        }
+    }
+    statements.push((start, &line[start..]));
+    statements
+}
+
+/// Whether `s` ends in the start of an entity code, such as `#59`.
+fn ends_with_entity_name(s: &str) -> bool {
+    let name_start = s
+        .rfind(|c: char| !is_entity_char(c))
+        .map_or(0, |pos| pos + 1);
+    name_start < s.len() && s[..name_start].ends_with('#')
+}
+
+fn is_entity_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Replaces entity codes like `#59;` or `#quot;` with the characters they stand for, and
+/// `<br/>` tags with line breaks.
+pub(crate) fn decode_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find(['#', '<']) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with('<') {
+            let len = line_break_len(rest);
+            if len > 0 {
+                out.push('\n');
+            } else {
+                out.push('<');
+            }
+            rest = &rest[len.max(1)..];
+            continue;
+        }
+        let decoded = rest[1..].split_once(';').and_then(|(name, _)| {
+            let c = if let Ok(code) = name.parse::<u32>() {
+                char::from_u32(code)?
+            } else {
+                NAMED_ENTITIES.iter().find(|(n, _)| *n == name)?.1
+            };
+            Some((c, name.len() + 2))
+        });
+        if let Some((c, len)) = decoded {
+            out.push(c);
+            rest = &rest[len..];
+        } else {
+            out.push('#');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escapes text so it can't end the statement or be read as an entity code or line break.
+pub(crate) fn encode_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (pos, c) in text.char_indices() {
+        match c {
+            ';' => out.push_str("#59;"),
+            '#' if starts_with_entity_name(&text[pos + 1..]) => out.push_str("#35;"),
+            '<' if line_break_len(&text[pos..]) > 0 => out.push_str("#lt;"),
+            '\n' => out.push_str("<br/>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The length of the `<br>`, `<br/>` or `<br />` tag at the start of `s`, or `0`.
+fn line_break_len(s: &str) -> usize {
+    let Some(tag) = s.get(..3).filter(|tag| tag.eq_ignore_ascii_case("<br")) else {
+        return 0;
+    };
+    let rest = s[tag.len()..].trim_start_matches(' ');
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    if rest.starts_with('>') {
+        s.len() - rest.len() + 1
+    } else {
+        0
+    }
+}
+
+fn starts_with_entity_name(s: &str) -> bool {
+    let name_len = s.find(|c: char| !is_entity_char(c)).unwrap_or(s.len());
+    name_len > 0 && s[name_len..].starts_with(';')
+}
+
+/// Whether `id` can be written as a participant id without changing how the diagram is read.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.trim() == id
+        && !id.contains([':', ';', ',', '#', '@', '<', '=', '>', '\n'])
+        && !id.contains(" as ")
+        && !id.starts_with(['+', '-'])
+        && Arrow::find_in(id).is_none()
+}
+
+/// Replaces whatever keeps `name` from being a valid participant id.
+pub(crate) fn sanitize_id(name: &str) -> String {
+    let mut id: String = name
+        .trim()
+        .replace(" as ", "_as_")
+        .chars()
+        .map(|c| if ":;,#@<=>\n".contains(c) { '_' } else { c })
+        .collect();
+    while let Some((pos, _)) = Arrow::find_in(&id) {
+        id.replace_range(pos..=pos, "_");
+    }
+    if id.starts_with(['+', '-']) {
+        id.replace_range(..1, "_");
+    }
+    if id.is_empty() {
+        id.push('_');
+    }
+    id
+}
+
+/// The parts of a message statement such as `A->>+B: Hello`.
+pub(crate) struct MessageTokens<'a> {
+    pub from: &'a str,
+    pub arrow: Arrow,
+    pub activation: Activation,
+    pub to: &'a str,
+    /// The raw text after the `:`, if there is one.
+    pub text: Option<&'a str>,
+}
+
+/// Splits a message statement. Participant ids can't contain `:`, so only the part before the
+/// first `:` is searched for the arrow, and the text may contain anything.
+pub(crate) fn split_message(statement: &str) -> Option<MessageTokens<'_>> {
+    let (head, text) = match statement.split_once(':') {
+        Some((head, text)) => (head, Some(text.trim())),
+        None => (statement, None),
+    };
+    let (arrow_pos, arrow) = Arrow::find_in(head)?;
+    let rest = &head[arrow_pos + arrow.as_mermaid_str().len()..];
+    let (activation, to) = if let Some(to) = rest.strip_prefix('+') {
+        (Activation::Activate, to)
+    } else if let Some(to) = rest.strip_prefix('-') {
+        (Activation::Deactivate, to)
+    } else {
+        (Activation::None, rest)
+    };
+    Some(MessageTokens {
+        from: head[..arrow_pos].trim(),
+        arrow,
+        activation,
+        to: to.trim(),
+        text,
+    })
+}
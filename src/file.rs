@@ -0,0 +1,256 @@
+use anyhow::{Result, bail};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::core::{MarkdownFile, SequenceDiagram, ViewState, is_json, is_markdown, is_plantuml};
+
+/// How a diagram is written back to its file.
+#[derive(Clone)]
+pub enum FileFormat {
+    Mermaid,
+    PlantUml,
+    Json,
+    /// A sequence diagram in a fence of a Markdown file; `block` is `None` until one is picked.
+    Markdown {
+        file: MarkdownFile,
+        block: Option<usize>,
+    },
+}
+
+/// The file a diagram was opened from or saved to.
+#[derive(Clone)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    pub format: FileFormat,
+}
+
+impl OpenFile {
+    pub fn new(path: PathBuf, format: FileFormat) -> Self {
+        Self { path, format }
+    }
+
+    /// The file name, for the status bar.
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    }
+
+    /// Writes `diagram` to the file in its format, replacing it atomically.
+    pub fn save(&mut self, diagram: &SequenceDiagram, view: ViewState) -> Result<()> {
+        let contents = match &mut self.format {
+            FileFormat::Mermaid => diagram.to_mermaid(),
+            FileFormat::PlantUml => diagram.to_plantuml(),
+            FileFormat::Json => diagram.to_json(view),
+            FileFormat::Markdown {
+                file,
+                block: Some(idx),
+            } => {
+                file.replace_block(*idx, &diagram.to_mermaid());
+                file.content().to_string()
+            }
+            FileFormat::Markdown { block: None, .. } => bail!("No diagram picked to save"),
+        };
+        write_atomic(&self.path, &contents)?;
+        Ok(())
+    }
+
+    /// Reads the diagram from the file again, after another program changed it.
+    pub fn reload(&mut self) -> Result<SequenceDiagram> {
+        let content = fs::read_to_string(&self.path)?;
+        let diagram = match &mut self.format {
+            FileFormat::Mermaid => SequenceDiagram::from_mermaid(&content)?,
+            FileFormat::PlantUml => SequenceDiagram::from_plantuml(&content)?,
+            FileFormat::Json => SequenceDiagram::from_json(&content)?.0,
+            FileFormat::Markdown {
+                file,
+                block: Some(idx),
+            } => {
+                let reloaded = MarkdownFile::parse(content);
+                let Some(block) = reloaded.blocks.get(*idx) else {
+                    bail!("Sequence diagram {} is no longer in the file", *idx + 1);
+                };
+                let diagram = SequenceDiagram::from_mermaid(&reloaded.block_text(*idx))
+                    .map_err(|e| block.locate(e))?;
+                *file = reloaded;
+                diagram
+            }
+            FileFormat::Markdown { block: None, .. } => bail!("No diagram picked to reload"),
+        };
+        Ok(diagram)
+    }
+
+    /// Returns this file moved to `path`, in the format its extension asks for.
+    ///
+    /// A Markdown file can only be saved as another Markdown file, which keeps the text
+    /// around the diagram.
+    pub fn save_as(&self, path: PathBuf) -> Result<Self> {
+        match &self.format {
+            markdown @ FileFormat::Markdown { .. } if is_markdown(&path) => {
+                Ok(Self::new(path, markdown.clone()))
+            }
+            _ => Self::create(path),
+        }
+    }
+
+    /// A new file at `path` for a diagram that has no file yet, in the format its extension
+    /// asks for.
+    pub fn create(path: PathBuf) -> Result<Self> {
+        if is_markdown(&path) {
+            bail!("Only diagrams opened from Markdown can be saved to Markdown");
+        }
+        let format = FileFormat::from_path(&path);
+        Ok(Self::new(path, format))
+    }
+}
+
+impl FileFormat {
+    /// The format to save a new file in, by its extension; Mermaid unless it says otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        if is_json(path, "") {
+            Self::Json
+        } else if is_plantuml(path, "") {
+            Self::PlantUml
+        } else {
+            Self::Mermaid
+        }
+    }
+}
+
+/// How often the opened file is checked for changes made by other programs.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when another program changes the opened file, by its modification time.
+pub struct FileWatch {
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl Default for FileWatch {
+    fn default() -> Self {
+        Self {
+            modified: None,
+            last_check: Instant::now(),
+        }
+    }
+}
+
+impl FileWatch {
+    /// Takes the file as it is now as unchanged, after reading or writing it.
+    pub fn reset(&mut self, path: &Path) {
+        self.modified = modified(path);
+    }
+
+    /// Whether the file changed since the last [`reset`](Self::reset), checked at most every
+    /// [`WATCH_INTERVAL`].
+    pub fn changed(&mut self, path: &Path) -> bool {
+        if self.last_check.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let modified = modified(path);
+        modified.is_some() && modified != self.modified
+    }
+}
+
+/// The modification time of `path`, or `None` if it cannot be read.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it over `path`, so that
+/// `path` is never left half written.
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dir.join(format!(".{name}.{}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ParseError;
+
+    #[test]
+    fn test_save_as_picks_format() {
+        let file = OpenFile::new("a.mmd".into(), FileFormat::Mermaid);
+        let json = file.save_as("b.json".into()).unwrap();
+        assert!(matches!(json.format, FileFormat::Json));
+        assert!(matches!(
+            file.save_as("c.puml".into()).unwrap().format,
+            FileFormat::PlantUml
+        ));
+        assert!(file.save_as("d.md".into()).is_err());
+
+        // A diagram without a file follows the same rules.
+        assert!(matches!(
+            OpenFile::create("e.json".into()).unwrap().format,
+            FileFormat::Json
+        ));
+        assert!(OpenFile::create("f.md".into()).is_err());
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("tuigram-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.md");
+        let markdown = "# Notes\n\n```mermaid\nsequenceDiagram\n    A->>B: Hi\n```\n";
+        fs::write(&path, markdown).unwrap();
+        let mut file = OpenFile::new(
+            path.clone(),
+            FileFormat::Markdown {
+                file: MarkdownFile::parse(markdown.to_string()),
+                block: Some(0),
+            },
+        );
+
+        fs::write(&path, markdown.replace("Hi", "Bye")).unwrap();
+        assert!(file.reload().unwrap().to_mermaid().contains("A->>B: Bye"));
+
+        // Errors point at the line in the Markdown file.
+        fs::write(&path, markdown.replace("A->>B: Hi", "A->>B Hi")).unwrap();
+        let Err(error) = file.reload() else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(error.downcast::<ParseError>().unwrap().line, 5);
+
+        fs::write(&path, "# Notes\n").unwrap();
+        assert!(file.reload().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("tuigram-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("diagram.mmd");
+
+        write_atomic(&path, "sequenceDiagram\n").unwrap();
+        write_atomic(&path, "sequenceDiagram\n    A->>B: Hi\n").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "sequenceDiagram\n    A->>B: Hi\n"
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,53 +7,410 @@
 
 mod app;
 mod core;
+mod file;
+mod recovery;
 mod render;
 mod theme;
 mod ui;
 
-use clap::Parser;
+use anyhow::{Context, bail};
+use clap::{Parser, Subcommand, ValueEnum};
 use ratatui::crossterm::{
     event::{self, Event as CEvent},
     execute,
 };
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use tui_world::prelude::*;
 
-use crate::core::SequenceDiagram;
+use crate::core::{BlockSelector, MarkdownFile, ParseError, SequenceDiagram, Severity, ViewState};
+use crate::file::{FileFormat, OpenFile};
 
 /// A TUI sequence diagram editor
 #[derive(Parser, Debug)]
 #[command(name = "tuigram")]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Import a Mermaid (.mmd) sequence diagram file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Import a Mermaid (.mmd), PlantUML (.puml), tuigram JSON (.json) or Markdown (.md) file
     #[arg(short, long, value_name = "FILE")]
     import: Option<PathBuf>,
+
+    /// The sequence diagram to edit in a Markdown file, by number or by the heading above it
+    #[arg(long, requires = "import", value_name = "NUMBER|HEADING")]
+    block: Option<String>,
+}
+
+/// Commands that run without the editor, for scripts, CI and pre-commit hooks.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a diagram to Mermaid, PlantUML or JSON
+    Convert {
+        #[command(flatten)]
+        input: Input,
+
+        /// The format to convert to
+        #[arg(long, value_enum)]
+        to: OutputFormat,
+
+        /// Write to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Render a diagram as text or SVG
+    Render {
+        #[command(flatten)]
+        input: Input,
+
+        /// The format to render to
+        #[arg(long, value_enum, default_value = "txt")]
+        format: RenderFormat,
+
+        /// Use only ASCII characters in text output
+        #[arg(long)]
+        ascii: bool,
+
+        /// Write to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Check that diagrams import without errors, exiting non-zero if any do not
+    Check {
+        /// The files to check, or `-` for stdin; every diagram in a Markdown file is checked
+        #[arg(value_name = "FILE", default_value = "-")]
+        files: Vec<PathBuf>,
+
+        /// The format of the input, instead of guessing it from the file
+        #[arg(long, value_enum)]
+        from: Option<InputFormat>,
+    },
+}
+
+/// The diagram a command reads.
+#[derive(clap::Args, Debug)]
+struct Input {
+    /// The file to read, or `-` for stdin
+    #[arg(value_name = "FILE", default_value = "-")]
+    file: PathBuf,
+
+    /// The format of the input, instead of guessing it from the file
+    #[arg(long, value_enum)]
+    from: Option<InputFormat>,
+
+    /// The sequence diagram to read from a Markdown file, by number or by the heading above it
+    #[arg(long, value_name = "NUMBER|HEADING")]
+    block: Option<String>,
+
+    /// Write the output even if the input has errors, leaving out what could not be read
+    #[arg(long)]
+    partial: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InputFormat {
+    Mermaid,
+    #[value(name = "plantuml")]
+    PlantUml,
+    Json,
+    Markdown,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Mermaid,
+    #[value(name = "plantuml")]
+    PlantUml,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RenderFormat {
+    Txt,
+    Svg,
+}
+
+/// A diagram read from a file, with what the editor needs besides the diagram.
+#[derive(Default)]
+struct Import {
+    diagram: SequenceDiagram,
+    diagnostics: Vec<ParseError>,
+    view: ViewState,
+    file: Option<OpenFile>,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let diagram = if let Some(path) = args.import {
-        let content = std::fs::read_to_string(&path)
-            .map_err(|e| anyhow::anyhow!("Failed to read file '{}': {}", path.display(), e))?;
+    let Some(command) = args.command else {
+        let import = match args.import {
+            Some(path) => import(path, args.block.as_deref(), None, false)?,
+            None => Import::default(),
+        };
+        return run(import);
+    };
+
+    match command {
+        Command::Convert { input, to, output } => {
+            let import = import(input.file, input.block.as_deref(), input.from, true)?;
+            fail_on_errors(&import.diagnostics, input.partial)?;
+            let diagram = &import.diagram;
+            let converted = match to {
+                OutputFormat::Mermaid => diagram.to_mermaid(),
+                OutputFormat::PlantUml => diagram.to_plantuml(),
+                OutputFormat::Json => diagram.to_json(import.view),
+            };
+            write_output(output.as_deref(), &converted)
+        }
+        Command::Render {
+            input,
+            format,
+            ascii,
+            output,
+        } => {
+            let import = import(input.file, input.block.as_deref(), input.from, true)?;
+            fail_on_errors(&import.diagnostics, input.partial)?;
+            let rendered = match format {
+                RenderFormat::Svg => render::to_svg(&import.diagram),
+                RenderFormat::Txt if ascii => {
+                    render::to_text(&import.diagram, render::Charset::Ascii)?
+                }
+                RenderFormat::Txt => render::to_text(&import.diagram, render::Charset::Unicode)?,
+            };
+            write_output(output.as_deref(), &rendered)
+        }
+        Command::Check { files, from } => check(&files, from),
+    }
+}
+
+/// The path to read stdin from, or write stdout to.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn display_name(path: &Path) -> String {
+    if is_stdio(path) {
+        "<stdin>".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+fn read_input(path: &Path) -> anyhow::Result<String> {
+    if is_stdio(path) {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("Failed to read stdin")?;
+        return Ok(content);
+    }
+    std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read file '{}': {}", path.display(), e))
+}
 
-        SequenceDiagram::from_mermaid(&content).map_err(|e| {
-            anyhow::anyhow!("Failed to parse mermaid file '{}': {}", path.display(), e)
-        })?
+fn write_output(path: Option<&Path>, contents: &str) -> anyhow::Result<()> {
+    match path {
+        Some(path) if !is_stdio(path) => file::write_atomic(path, contents)
+            .with_context(|| format!("Failed to write '{}'", path.display())),
+        _ => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(contents.as_bytes())?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// The format of `content`, by the extension of `path` or, for stdin, by how it starts.
+fn detect_format(path: &Path, content: &str) -> InputFormat {
+    if core::is_markdown(path) {
+        InputFormat::Markdown
+    } else if core::is_json(path, content) {
+        InputFormat::Json
+    } else if core::is_plantuml(path, content) {
+        InputFormat::PlantUml
     } else {
-        SequenceDiagram::new()
+        InputFormat::Mermaid
+    }
+}
+
+fn report(diagnostics: &[ParseError], name: &str, source: &str) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.to_report(name, source));
+    }
+}
+
+/// Reads a Mermaid, PlantUML, JSON or Markdown file, or stdin for `-`. A Markdown file with
+/// several diagrams needs a `block` selector unless the editor will ask for one.
+///
+/// Commands that print the diagram report problems on stderr; the editor lists them itself,
+/// as stderr is hidden behind its screen.
+fn import(
+    path: PathBuf,
+    block: Option<&str>,
+    from: Option<InputFormat>,
+    prints: bool,
+) -> anyhow::Result<Import> {
+    let content = read_input(&path)?;
+    let name = display_name(&path);
+    let format = from.unwrap_or_else(|| detect_format(&path, &content));
+    // Stdin cannot be saved back to.
+    let stdin = is_stdio(&path);
+    let open = |format| (!stdin).then(|| OpenFile::new(path, format));
+
+    if format == InputFormat::Markdown {
+        let file = MarkdownFile::parse(content);
+        let count = file.blocks.len();
+        let block = match block {
+            _ if count == 0 => bail!("No sequence diagrams in '{name}'"),
+            Some(selector) => Some(file.select(&BlockSelector::parse(selector))?),
+            None if count == 1 => Some(0),
+            None if prints => {
+                bail!("'{name}' has {count} sequence diagrams, choose one with --block")
+            }
+            None => None,
+        };
+        let (diagram, diagnostics) = match block {
+            Some(idx) => {
+                let (diagram, diagnostics) =
+                    SequenceDiagram::from_mermaid_tolerant(&file.block_text(idx));
+                let diagnostics: Vec<_> = diagnostics
+                    .into_iter()
+                    .map(|d| file.blocks[idx].locate(d))
+                    .collect();
+                if prints {
+                    report(&diagnostics, &name, file.content());
+                }
+                (diagram, diagnostics)
+            }
+            None => (SequenceDiagram::new(), Vec::new()),
+        };
+        return Ok(Import {
+            diagram,
+            diagnostics,
+            view: ViewState::default(),
+            file: open(FileFormat::Markdown { file, block }),
+        });
+    }
+    if block.is_some() {
+        bail!("--block only applies to Markdown files");
+    }
+
+    if format == InputFormat::Json {
+        let (diagram, view) = SequenceDiagram::from_json(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to read '{name}': {e:#}"))?;
+        return Ok(Import {
+            diagram,
+            view,
+            file: open(FileFormat::Json),
+            ..Import::default()
+        });
+    }
+
+    let (diagram, diagnostics, format) = if format == InputFormat::PlantUml {
+        let (diagram, diagnostics) = SequenceDiagram::from_plantuml_tolerant(&content);
+        (diagram, diagnostics, FileFormat::PlantUml)
+    } else {
+        let (diagram, diagnostics) = SequenceDiagram::from_mermaid_tolerant(&content);
+        (diagram, diagnostics, FileFormat::Mermaid)
     };
+    if prints {
+        report(&diagnostics, &name, &content);
+    }
+    Ok(Import {
+        diagram,
+        diagnostics,
+        file: open(format),
+        ..Import::default()
+    })
+}
+
+/// Fails if any of `diagnostics` is an error, unless `partial` output was asked for.
+fn fail_on_errors(diagnostics: &[ParseError], partial: bool) -> anyhow::Result<()> {
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    match errors {
+        _ if partial => Ok(()),
+        0 => Ok(()),
+        1 => bail!("1 error found, use --partial to write the output anyway"),
+        n => bail!("{n} errors found, use --partial to write the output anyway"),
+    }
+}
+
+/// Reports every problem in `files` and fails if any of them is an error. A file that
+/// cannot be read counts as one error, and the check goes on with the next file.
+fn check(files: &[PathBuf], from: Option<InputFormat>) -> anyhow::Result<()> {
+    let mut errors = 0;
+    for path in files {
+        match check_file(path, from) {
+            Ok(found) => errors += found,
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                errors += 1;
+            }
+        }
+    }
+    match errors {
+        0 => Ok(()),
+        1 => bail!("1 error found"),
+        n => bail!("{n} errors found"),
+    }
+}
 
-    run(diagram)
+/// Reports the problems in one file, returning how many of them are errors.
+fn check_file(path: &Path, from: Option<InputFormat>) -> anyhow::Result<usize> {
+    let mut errors = 0;
+    let content = read_input(path)?;
+    let name = display_name(path);
+    let diagnostics = match from.unwrap_or_else(|| detect_format(path, &content)) {
+        InputFormat::Markdown => {
+            let file = MarkdownFile::parse(content.clone());
+            (0..file.blocks.len())
+                .flat_map(|idx| {
+                    let (_, diagnostics) =
+                        SequenceDiagram::from_mermaid_tolerant(&file.block_text(idx));
+                    diagnostics
+                        .into_iter()
+                        .map(|d| file.blocks[idx].locate(d))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        }
+        InputFormat::Json => {
+            if let Err(e) = SequenceDiagram::from_json(&content) {
+                eprintln!("error: {name}: {e:#}");
+                errors += 1;
+            }
+            Vec::new()
+        }
+        InputFormat::PlantUml => SequenceDiagram::from_plantuml_tolerant(&content).1,
+        InputFormat::Mermaid => SequenceDiagram::from_mermaid_tolerant(&content).1,
+    };
+    report(&diagnostics, &name, &content);
+    errors += diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    Ok(errors)
 }
 
-fn run(diagram: SequenceDiagram) -> anyhow::Result<()> {
+fn run(import: Import) -> anyhow::Result<()> {
     let mut terminal = ratatui::init();
     execute!(std::io::stdout())?;
 
     let mut world = World::default();
-    app::setup_world(&mut world, diagram);
+    app::setup_world(&mut world, import.diagram);
+    world.get_mut::<app::AppState>().diagnostics = import.diagnostics;
+    app::restore_view(&mut world, import.view);
+    if let Some(file) = import.file {
+        app::open_file(&mut world, file);
+    }
+    app::offer_recovery(&mut world);
+    app::show_diagnostics(&mut world);
 
     loop {
         terminal.draw(|frame| app::render(frame, &mut world))?;
@@ -66,13 +423,90 @@ fn run(diagram: SequenceDiagram) -> anyhow::Result<()> {
             }
         }
 
+        app::tick(&mut world);
+
         if world.get::<app::AppState>().should_quit {
             break;
         }
     }
+    // Quitting saves or throws away the changes, so the recovery file is no longer needed.
+    world.get_mut::<app::AppState>().journal.discard();
 
     execute!(std::io::stdout())?;
     ratatui::restore();
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+
+        let args = Args::parse_from(["tuigram", "render", "--format", "svg"]);
+        let Some(Command::Render { input, format, .. }) = args.command else {
+            panic!("expected the render command");
+        };
+        assert!(is_stdio(&input.file));
+        assert!(matches!(format, RenderFormat::Svg));
+
+        assert!(Args::try_parse_from(["tuigram", "--import", "a.mmd", "check"]).is_err());
+        assert!(Args::try_parse_from(["tuigram", "convert", "a.mmd"]).is_err());
+    }
+
+    #[test]
+    fn test_errors_fail_conversion() {
+        let dir = std::env::temp_dir().join(format!("tuigram-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stray.mmd");
+        std::fs::write(&path, "sequenceDiagram\n    A->>B: Hi\n    end\n").unwrap();
+
+        let import = import(path, None, None, true).unwrap();
+        let error = fail_on_errors(&import.diagnostics, false).unwrap_err();
+        assert!(error.to_string().starts_with("1 error found"), "{error}");
+        assert!(fail_on_errors(&import.diagnostics, true).is_ok());
+        assert!(fail_on_errors(&[], false).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_continues_past_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("tuigram-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.mmd");
+        let bad = dir.join("bad.json");
+        let missing = dir.join("missing.mmd");
+        std::fs::write(&good, "sequenceDiagram\n    A->>B: Hi\n").unwrap();
+        std::fs::write(&bad, "{ not json").unwrap();
+
+        assert_eq!(check_file(&good, None).unwrap(), 0);
+        assert_eq!(check_file(&bad, None).unwrap(), 1);
+        assert!(check_file(&missing, None).is_err());
+
+        // The missing file is counted, and the files after it are still checked.
+        let files = [good.clone(), missing, bad, good];
+        let error = check(&files, None).unwrap_err();
+        assert_eq!(error.to_string(), "2 errors found");
+        assert!(check(&files[..1], None).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let stdin = Path::new("-");
+        assert_eq!(
+            detect_format(stdin, "sequenceDiagram\n"),
+            InputFormat::Mermaid
+        );
+        assert_eq!(detect_format(stdin, "@startuml\n"), InputFormat::PlantUml);
+        assert_eq!(detect_format(stdin, "{\"version\": 1}"), InputFormat::Json);
+        assert_eq!(
+            detect_format(Path::new("README.md"), ""),
+            InputFormat::Markdown
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{SequenceDiagram, ViewState};
+use crate::file::{FileFormat, OpenFile, modified, write_atomic};
+
+/// How often unsaved changes are written to the recovery file.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many earlier versions of a file are kept when it is saved.
+pub const BACKUP_COUNT: usize = 5;
+
+/// Autosaved copies of unsaved diagrams, and backups of the files they are saved to.
+///
+/// Both live under the state directory rather than next to the document, so they never
+/// show up in the document's repository.
+pub struct Journal {
+    dir: PathBuf,
+    last_autosave: Instant,
+    /// The recovery file last written and the diagram it holds, in Mermaid.
+    written: Option<(PathBuf, String)>,
+}
+
+/// The contents of a recovery file.
+#[derive(Serialize, Deserialize)]
+struct Autosave {
+    /// The diagram and view, as in a JSON document.
+    diagram: serde_json::Value,
+    /// The text the diagram was imported from, so that comments and other lines the model
+    /// does not interpret survive a restore.
+    #[serde(default)]
+    source: Option<String>,
+}
+
+/// A recovery file found on startup.
+pub struct Recovery {
+    pub diagram: SequenceDiagram,
+    pub view: ViewState,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new(state_dir())
+    }
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            last_autosave: Instant::now(),
+            written: None,
+        }
+    }
+
+    /// Whether [`AUTOSAVE_INTERVAL`] has passed since the last autosave, restarting the timer.
+    pub fn autosave_due(&mut self) -> bool {
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL {
+            return false;
+        }
+        self.last_autosave = Instant::now();
+        true
+    }
+
+    /// Writes `diagram` to the recovery file of `file`, unless it is already there.
+    pub fn autosave(
+        &mut self,
+        file: Option<&OpenFile>,
+        diagram: &SequenceDiagram,
+        view: ViewState,
+    ) -> io::Result<()> {
+        let path = self.recovery_path(file);
+        let mermaid = diagram.to_mermaid();
+        if self
+            .written
+            .as_ref()
+            .is_some_and(|written| *written == (path.clone(), mermaid.clone()))
+        {
+            return Ok(());
+        }
+        // The diagram was saved under a new name since the last autosave.
+        self.discard();
+        fs::create_dir_all(self.dir.join("recovery"))?;
+        let autosave = Autosave {
+            diagram: serde_json::from_str(&diagram.to_json(view))?,
+            source: diagram.source_text().map(str::to_string),
+        };
+        write_atomic(&path, &serde_json::to_string(&autosave)?)?;
+        self.written = Some((path, mermaid));
+        Ok(())
+    }
+
+    /// Removes the recovery file, once its changes are saved or thrown away.
+    pub fn discard(&mut self) {
+        if let Some((path, _)) = self.written.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Finds the recovery file of `file` if it was written after the file was last changed
+    /// and holds a different diagram than `diagram`.
+    pub fn find(&mut self, file: Option<&OpenFile>, diagram: &SequenceDiagram) -> Option<Recovery> {
+        let path = self.recovery_path(file);
+        let recovered = modified(&path)?;
+        if let Some(source) = file.and_then(|file| modified(&file.path))
+            && source >= recovered
+        {
+            return None;
+        }
+        let content = fs::read_to_string(&path).ok()?;
+        let autosave: Autosave = serde_json::from_str(&content).ok()?;
+        let (mut recovered, view) =
+            SequenceDiagram::from_json(&autosave.diagram.to_string()).ok()?;
+        if let Some(source) = &autosave.source {
+            recovered.attach_source(source);
+        }
+        // Writing it again would leave it as is.
+        self.written = Some((path, recovered.to_mermaid()));
+        if recovered.to_mermaid() == diagram.to_mermaid() {
+            self.discard();
+            return None;
+        }
+        Some(Recovery {
+            diagram: recovered,
+            view,
+        })
+    }
+
+    /// Copies `path` into the backups before it is overwritten, keeping the last
+    /// [`BACKUP_COUNT`] copies.
+    pub fn backup(&self, path: &Path) -> io::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let dir = self.dir.join("backups");
+        fs::create_dir_all(&dir)?;
+        let key = key(path);
+        let backup = |n: usize| dir.join(format!("{key}.{n}"));
+        for n in (1..BACKUP_COUNT).rev() {
+            if backup(n).exists() {
+                fs::rename(backup(n), backup(n + 1))?;
+            }
+        }
+        fs::copy(path, backup(1))?;
+        Ok(())
+    }
+
+    fn recovery_path(&self, file: Option<&OpenFile>) -> PathBuf {
+        let name = match file {
+            None => "untitled".to_string(),
+            Some(OpenFile {
+                path,
+                format:
+                    FileFormat::Markdown {
+                        block: Some(idx), ..
+                    },
+            }) => format!("{}-{}", key(path), idx + 1),
+            Some(file) => key(&file.path),
+        };
+        self.dir.join("recovery").join(format!("{name}.json"))
+    }
+}
+
+/// `$XDG_STATE_HOME/tuigram`, falling back to `~/.local/state/tuigram`.
+fn state_dir() -> PathBuf {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .unwrap_or_else(std::env::temp_dir);
+    state_home.join("tuigram")
+}
+
+/// A file name unique to `path`: its own name and a hash of its absolute path.
+fn key(path: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    // FNV-1a, which unlike `DefaultHasher` stays the same across Rust versions.
+    let hash = absolute
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    format!("{name}-{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tuigram-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_recovery() {
+        let dir = temp_dir("recovery");
+        let source = dir.join("diagram.mmd");
+        fs::write(&source, "sequenceDiagram\n").unwrap();
+        let file = OpenFile::new(source.clone(), FileFormat::Mermaid);
+        let saved = SequenceDiagram::new();
+        let mut edited = SequenceDiagram::new();
+        edited.add_participant("Alice".to_string());
+
+        let mut journal = Journal::new(dir.join("state"));
+        journal
+            .autosave(Some(&file), &edited, ViewState::default())
+            .unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_mins(1))
+            .unwrap();
+
+        let mut journal = Journal::new(dir.join("state"));
+        let recovery = journal.find(Some(&file), &saved).unwrap();
+        assert_eq!(recovery.diagram.to_mermaid(), edited.to_mermaid());
+        assert!(journal.find(None, &saved).is_none());
+
+        // Changes to the file after the autosave win over the recovery file.
+        fs::write(&source, "sequenceDiagram\n").unwrap();
+        assert!(journal.find(Some(&file), &saved).is_none());
+
+        journal.discard();
+        let recovery_dir = dir.join("state").join("recovery");
+        assert_eq!(fs::read_dir(recovery_dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_keeps_comments() {
+        let dir = temp_dir("recovery-comments");
+        let mut edited =
+            SequenceDiagram::from_mermaid("sequenceDiagram\n    %% keep me\n    A->>B: Hi\n")
+                .unwrap();
+        let message = edited.message(1, 0, "Hello".to_string()).unwrap();
+        edited.events.push(message);
+
+        let mut journal = Journal::new(dir.join("state"));
+        journal
+            .autosave(None, &edited, ViewState::default())
+            .unwrap();
+
+        let mut journal = Journal::new(dir.join("state"));
+        let recovery = journal.find(None, &SequenceDiagram::new()).unwrap();
+        let mermaid = recovery.diagram.to_mermaid();
+        assert!(mermaid.contains("    %% keep me\n    A->>B: Hi\n    B->>A: Hello\n"));
+        assert_eq!(mermaid, edited.to_mermaid());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backups() {
+        let dir = temp_dir("backups");
+        let source = dir.join("diagram.mmd");
+        let journal = Journal::new(dir.join("state"));
+
+        journal.backup(&source).unwrap();
+        for n in 0..=BACKUP_COUNT {
+            fs::write(&source, format!("version {n}")).unwrap();
+            journal.backup(&source).unwrap();
+        }
+
+        let backups = dir.join("state").join("backups");
+        assert_eq!(fs::read_dir(&backups).unwrap().count(), BACKUP_COUNT);
+        let key = key(&source);
+        let read = |n: usize| fs::read_to_string(backups.join(format!("{key}.{n}"))).unwrap();
+        assert_eq!(read(1), format!("version {BACKUP_COUNT}"));
+        assert_eq!(read(BACKUP_COUNT), "version 1");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
 mod sequence;
+mod svg;
+mod text;
 
 pub use sequence::render_sequence;
+pub use svg::to_svg;
+pub use text::{Charset, to_text};
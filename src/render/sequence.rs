@@ -3,21 +3,30 @@ use ratatui::{
     layout::{Alignment, Margin, Rect},
     style::{Color, Style},
     text::Line,
-    widgets::{Block, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation},
 };
 use tui_world::World;
 
 use crate::{
-    core::{BoxColor, Event, NotePosition, SequenceDiagram},
+    core::{
+        Arrow, ArrowHead, BlockBounds, BoxColor, Event, LineStyle, NotePosition, Participant,
+        ParticipantKind, RgbColor, SequenceDiagram, wrap_text,
+    },
     theme::Theme,
     ui::{EditorState, FIRST_MESSAGE_OFFSET, HEADER_HEIGHT, Selection, scroll::ScrollState},
 };
 
+/// Message and note text is never wrapped narrower than this.
+const MIN_TEXT_WIDTH: u16 = 10;
+
 pub fn render_sequence(f: &mut Frame, outer_area: Rect, world: &mut World) {
     let selection = world.get::<EditorState>().selection;
     let area = outer_area.inner(Margin::new(0, 1));
 
-    world.get_mut::<ScrollState>().set_viewport(area.height);
+    let width = text_width(area, world.get::<SequenceDiagram>());
+    world
+        .get_mut::<ScrollState>()
+        .set_viewport(area.height, width);
 
     if let Selection::Event(idx) = selection {
         let diagram = world.get::<SequenceDiagram>().clone();
@@ -26,15 +35,40 @@ pub fn render_sequence(f: &mut Frame, outer_area: Rect, world: &mut World) {
 
     let theme = world.get::<Theme>();
 
-    let block = Block::default()
+    let mut block = Block::default()
         .borders(Borders::ALL)
         .border_style(theme.border);
+    if let Some(title) = &world.get::<SequenceDiagram>().metadata.title {
+        block = block
+            .title(Line::styled(format!(" {title} "), theme.text))
+            .title_alignment(Alignment::Center);
+    }
     f.render_widget(block, outer_area);
 
+    render_diagram(f, area, world, true);
+}
+
+/// Draws the participants and events into `area`, from the current scroll offset on. The
+/// scroll viewport must already be set for `area`.
+///
+/// Highlight regions are only told apart by their background, so their `rect` and `end`
+/// labels are left out where `highlight_labels` is false, as in plain text.
+pub(super) fn render_diagram(f: &mut Frame, area: Rect, world: &World, highlight_labels: bool) {
     let participants = render_participants(f, area, world);
     let lifeline_start = area.y + HEADER_HEIGHT;
+    render_highlights(f, area, world, &participants, lifeline_start);
     render_lifelines(f, area, world, &participants, lifeline_start);
+    render_activations(f, area, world, &participants, lifeline_start);
+    render_fragments(
+        f,
+        area,
+        world,
+        &participants,
+        lifeline_start,
+        highlight_labels,
+    );
     render_events(f, world, &participants, lifeline_start);
+    render_created_participants(f, area, world, &participants);
     render_scrollbar(f, area, world);
     // Render box labels last so they sit on top of lifelines and events
     render_box_labels(f, area, world, &participants);
@@ -74,54 +108,192 @@ fn render_participants(f: &mut Frame, area: Rect, world: &World) -> Vec<u16> {
     // Render box backgrounds FIRST (so participants appear on top)
     render_participant_box_backgrounds(f, area, world, &positions);
 
-    // Then render individual participant boxes
-    for (i, name) in diagram.participants.iter().enumerate() {
+    // Then render individual participant boxes. Participants created by a message that is
+    // still visible or below are drawn by `render_created_participants` instead.
+    let first_visible = world.get::<ScrollState>().offset;
+    let lifespans = diagram.lifespans();
+    for (i, participant) in diagram.participants.iter().enumerate() {
+        if lifespans[i]
+            .0
+            .is_some_and(|created| created >= first_visible)
+        {
+            continue;
+        }
         let style = if selection == Selection::Participant(i) {
             theme.selected
         } else {
             theme.text
         };
+        render_participant_header(f, area, participant, positions[i], area.y, style);
+    }
+
+    positions
+}
 
-        let width = name.len() as u16 + 4;
-        let x = positions[i]
+/// Draws the headers of created participants at the row of their creating message.
+fn render_created_participants(f: &mut Frame, area: Rect, world: &World, participants: &[u16]) {
+    let diagram = world.get::<SequenceDiagram>();
+    let selection = world.get::<EditorState>().selection;
+    let theme = world.get::<Theme>();
+    let scroll = world.get::<ScrollState>();
+    let visible_range = scroll.visible_range(diagram);
+
+    for (i, (created, _)) in diagram.lifespans().into_iter().enumerate() {
+        let Some(created) = created.filter(|c| visible_range.contains(c)) else {
+            continue;
+        };
+        let style = if selection == Selection::Participant(i) {
+            theme.selected
+        } else {
+            theme.text
+        };
+        let participant = &diagram.participants[i];
+        let y = message_row(diagram, scroll, area, created).saturating_sub(1);
+        let width = header_width(participant);
+        let x = participants[i]
             .saturating_sub(width / 2)
             .min(area.width.saturating_sub(width));
-
+        // Clear the message arrow underneath the header.
         f.render_widget(
-            Paragraph::new(name.as_str())
-                .alignment(Alignment::Center)
-                .style(style)
-                .block(Block::default().borders(Borders::ALL).border_style(style)),
+            Clear,
             Rect {
                 x,
-                y: area.y,
+                y,
                 width,
                 height: 3,
-            },
+            }
+            .intersection(area),
         );
+        render_participant_header(f, area, participant, participants[i], y, style);
     }
+}
 
-    positions
+/// Returns the row of the arrow of the visible event at `idx`.
+fn message_row(diagram: &SequenceDiagram, scroll: &ScrollState, area: Rect, idx: usize) -> u16 {
+    let above: u16 = diagram
+        .events
+        .iter()
+        .take(idx)
+        .skip(scroll.offset)
+        .map(|e| scroll.event_height(e))
+        .sum();
+    let label_rows = label_rows(&diagram.events[idx], scroll.text_width());
+    area.y + HEADER_HEIGHT + FIRST_MESSAGE_OFFSET + above + label_rows.saturating_sub(1)
+}
+
+/// Returns the width message and note text is wrapped to, about the space between lifelines.
+/// Room is left for the autonumber in front of the first line of a message.
+pub(super) fn text_width(area: Rect, diagram: &SequenceDiagram) -> u16 {
+    let spacing = area.width / (diagram.participants.len().max(1) as u16 + 1);
+    spacing
+        .saturating_sub(2)
+        .saturating_sub(number_width(diagram))
+        .max(MIN_TEXT_WIDTH)
+}
+
+/// The width of the widest autonumber prefix such as `12. `, or 0 without autonumbering.
+pub(super) fn number_width(diagram: &SequenceDiagram) -> u16 {
+    diagram
+        .message_numbers()
+        .into_iter()
+        .flatten()
+        .map(|n| format!("{n}. ").len() as u16)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the number of text rows above a message arrow.
+fn label_rows(event: &Event, text_width: u16) -> u16 {
+    match event {
+        Event::Message { text, .. } => wrap_text(text, text_width).len() as u16,
+        _ => 1,
+    }
+}
+
+fn render_participant_header(
+    f: &mut Frame,
+    area: Rect,
+    participant: &Participant,
+    center: u16,
+    y: u16,
+    style: ratatui::style::Style,
+) {
+    let width = header_width(participant);
+    let x = center
+        .saturating_sub(width / 2)
+        .min(area.width.saturating_sub(width));
+    let rect = Rect {
+        x,
+        y,
+        width,
+        height: 3,
+    }
+    .intersection(area);
+
+    let header = match participant.kind {
+        // Actors are drawn as a stick figure with the name below.
+        ParticipantKind::Actor => Paragraph::new(vec![
+            Line::from("☺"),
+            Line::from("/|\\"),
+            Line::from(participant.display_name()),
+        ]),
+        kind => {
+            let border_type = match kind {
+                ParticipantKind::Database => BorderType::Rounded,
+                ParticipantKind::Collections => BorderType::Double,
+                ParticipantKind::Queue => BorderType::Thick,
+                _ => BorderType::Plain,
+            };
+            Paragraph::new(header_text(participant)).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(border_type)
+                    .border_style(style),
+            )
+        }
+    };
+    f.render_widget(header.alignment(Alignment::Center).style(style), rect);
+}
+
+/// The header text of a participant: its display name, prefixed by a glyph for some kinds.
+fn header_text(participant: &Participant) -> String {
+    let glyph = match participant.kind {
+        ParticipantKind::Boundary => "⊢○ ",
+        ParticipantKind::Control => "↻ ",
+        ParticipantKind::Entity => "○ ",
+        ParticipantKind::Queue => "⇉ ",
+        ParticipantKind::Participant
+        | ParticipantKind::Actor
+        | ParticipantKind::Database
+        | ParticipantKind::Collections => "",
+    };
+    format!("{glyph}{}", participant.display_name())
+}
+
+pub(super) fn header_width(participant: &Participant) -> u16 {
+    u16::try_from(Line::from(header_text(participant)).width())
+        .unwrap_or(u16::MAX)
+        .saturating_add(4)
 }
 
 fn render_participant_box_backgrounds(f: &mut Frame, area: Rect, world: &World, positions: &[u16]) {
     let diagram = world.get::<SequenceDiagram>();
 
     for b in &diagram.boxes {
-        if b.start >= positions.len() || b.end >= positions.len() {
+        let Some((start, end)) = diagram.box_span(b) else {
+            continue;
+        };
+        if end >= positions.len() {
             continue;
         }
 
-        let start_name = &diagram.participants[b.start];
-        let end_name = &diagram.participants[b.end];
-
-        let start_w = (start_name.len() as u16 + 4).min(area.width);
-        let end_w = (end_name.len() as u16 + 4).min(area.width);
+        let start_w = header_width(&diagram.participants[start]).min(area.width);
+        let end_w = header_width(&diagram.participants[end]).min(area.width);
 
-        let start_x = positions[b.start]
+        let start_x = positions[start]
             .saturating_sub(start_w / 2)
             .min(area.width.saturating_sub(start_w));
-        let end_x = positions[b.end]
+        let end_x = positions[end]
             .saturating_sub(end_w / 2)
             .min(area.width.saturating_sub(end_w));
 
@@ -154,20 +326,20 @@ fn render_box_labels(f: &mut Frame, area: Rect, world: &World, positions: &[u16]
     let diagram = world.get::<SequenceDiagram>();
 
     for b in &diagram.boxes {
-        if b.label.is_empty() || b.start >= positions.len() || b.end >= positions.len() {
+        let Some((start, end)) = diagram.box_span(b) else {
+            continue;
+        };
+        if b.label.is_empty() || end >= positions.len() {
             continue;
         }
 
-        let start_name = &diagram.participants[b.start];
-        let end_name = &diagram.participants[b.end];
-
-        let start_w = (start_name.len() as u16 + 4).min(area.width);
-        let end_w = (end_name.len() as u16 + 4).min(area.width);
+        let start_w = header_width(&diagram.participants[start]).min(area.width);
+        let end_w = header_width(&diagram.participants[end]).min(area.width);
 
-        let start_x = positions[b.start]
+        let start_x = positions[start]
             .saturating_sub(start_w / 2)
             .min(area.width.saturating_sub(start_w));
-        let end_x = positions[b.end]
+        let end_x = positions[end]
             .saturating_sub(end_w / 2)
             .min(area.width.saturating_sub(end_w));
 
@@ -195,6 +367,9 @@ fn render_box_labels(f: &mut Frame, area: Rect, world: &World, positions: &[u16]
     }
 }
 
+/// Background and label colours for a box. Named colours use a hand-picked pair; custom
+/// colours are darkened for the background and lightened for the label, so any value stays
+/// readable on a dark terminal.
 fn box_display_colors(color: BoxColor) -> (Color, Color) {
     match color {
         BoxColor::Blue => (Color::Rgb(20, 50, 100), Color::Rgb(100, 150, 220)),
@@ -205,9 +380,38 @@ fn box_display_colors(color: BoxColor) -> (Color, Color) {
         BoxColor::Purple => (Color::Rgb(70, 20, 90), Color::Rgb(170, 80, 200)),
         BoxColor::Aqua => (Color::Rgb(10, 70, 80), Color::Rgb(60, 190, 200)),
         BoxColor::Gray => (Color::Rgb(45, 45, 45), Color::Rgb(150, 150, 150)),
+        BoxColor::Rgb(color) if color.alpha != Some(0) => {
+            (box_background(color), box_label_color(color))
+        }
+        BoxColor::Rgb(_) | BoxColor::Transparent => (Color::Reset, Color::Rgb(150, 150, 150)),
     }
 }
 
+fn luminance(color: RgbColor) -> f32 {
+    (0.299 * f32::from(color.r) + 0.587 * f32::from(color.g) + 0.114 * f32::from(color.b)) / 255.0
+}
+
+/// Darkens the colour to at most a fixed luminance, fading translucent colours further.
+fn box_background(color: RgbColor) -> Color {
+    let opacity = f32::from(color.alpha.unwrap_or(u8::MAX)) / 255.0;
+    let factor = (0.12 / luminance(color).max(0.01)).min(0.4) * opacity;
+    let scale = |c: u8| (f32::from(c) * factor).round() as u8;
+    Color::Rgb(scale(color.r), scale(color.g), scale(color.b))
+}
+
+/// Mixes the colour with white until it is light enough to read on its background.
+fn box_label_color(color: RgbColor) -> Color {
+    let target = 0.55;
+    let lum = luminance(color);
+    let mix = if lum < target {
+        (target - lum) / (1.0 - lum)
+    } else {
+        0.0
+    };
+    let lighten = |c: u8| (f32::from(c) + (255.0 - f32::from(c)) * mix).round() as u8;
+    Color::Rgb(lighten(color.r), lighten(color.g), lighten(color.b))
+}
+
 fn render_lifelines(
     f: &mut Frame,
     area: Rect,
@@ -224,9 +428,25 @@ fn render_lifelines(
     let has_above = visible_range.start > 0;
     let has_below = visible_range.end < event_count;
 
-    for &x in participants {
+    let bottom = area.y + area.height;
+    let lifespans = diagram.lifespans();
+
+    for (p, &x) in participants.iter().enumerate() {
         let xi = x.min(area.width - 1);
-        for y in lifeline_start..area.y + area.height {
+        let (created, destroyed) = lifespans[p];
+        // Created participants start below their header, destroyed ones end in a cross.
+        let start = match created {
+            Some(c) if c >= visible_range.end => continue,
+            Some(c) if c >= visible_range.start => message_row(diagram, scroll, area, c) + 2,
+            _ => lifeline_start,
+        };
+        let end = match destroyed {
+            Some(d) if d < visible_range.start => continue,
+            Some(d) if d < visible_range.end => Some(message_row(diagram, scroll, area, d) + 1),
+            _ => None,
+        };
+
+        for y in start..end.unwrap_or(bottom).min(bottom) {
             f.render_widget(
                 Paragraph::new("│").style(theme.text),
                 Rect {
@@ -238,8 +458,22 @@ fn render_lifelines(
             );
         }
 
+        if let Some(end) = end
+            && end < bottom
+        {
+            f.render_widget(
+                Paragraph::new("✕").style(theme.text),
+                Rect {
+                    x: xi,
+                    y: end,
+                    width: 1,
+                    height: 1,
+                },
+            );
+        }
+
         // Show scroll indicators on lifelines
-        if has_above {
+        if has_above && start == lifeline_start {
             f.render_widget(
                 Paragraph::new("⋮").style(theme.muted),
                 Rect {
@@ -251,7 +485,7 @@ fn render_lifelines(
             );
         }
 
-        if has_below {
+        if has_below && end.is_none() {
             f.render_widget(
                 Paragraph::new("⋮").style(theme.muted),
                 Rect {
@@ -265,12 +499,321 @@ fn render_lifelines(
     }
 }
 
+/// Draws activation bars on top of the lifelines, offsetting nested activations to the right.
+fn render_activations(
+    f: &mut Frame,
+    area: Rect,
+    world: &World,
+    participants: &[u16],
+    lifeline_start: u16,
+) {
+    let diagram = world.get::<SequenceDiagram>();
+    let theme = world.get::<Theme>();
+    let scroll = world.get::<ScrollState>();
+    let visible_range = scroll.visible_range(diagram);
+    let depths = diagram.activation_depths();
+    let depth_after = |i: usize, p: usize| depths.get(i).map_or(0, |d| d[p]);
+    let depth_before = |i: usize, p: usize| i.checked_sub(1).map_or(0, |i| depth_after(i, p));
+
+    let bottom = area.y + area.height;
+    let mut draw = |row: u16, x: u16, depth: usize| {
+        if row >= bottom {
+            return;
+        }
+        for level in 0..depth as u16 {
+            let x = x + level;
+            if x >= area.x + area.width {
+                break;
+            }
+            f.render_widget(
+                Paragraph::new("┃").style(theme.text),
+                Rect {
+                    x,
+                    y: row,
+                    width: 1,
+                    height: 1,
+                },
+            );
+        }
+    };
+
+    let mut y = lifeline_start + FIRST_MESSAGE_OFFSET;
+    for i in visible_range.clone() {
+        let event = &diagram.events[i];
+        let top = y - 1;
+        // The row at which the activation changes: the arrow for messages, the first row otherwise.
+        let anchor = if matches!(event, Event::Message { .. }) {
+            top + label_rows(event, scroll.text_width())
+        } else {
+            top
+        };
+
+        for (p, &x) in participants.iter().enumerate() {
+            let (before, after) = (depth_before(i, p), depth_after(i, p));
+            for row in top..top + scroll.event_height(event) {
+                let depth = match row.cmp(&anchor) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => before.max(after),
+                    std::cmp::Ordering::Greater => after,
+                };
+                draw(row, x, depth);
+            }
+        }
+
+        y += scroll.event_height(event);
+    }
+
+    // Keep the last row free for the scroll indicator if more events follow.
+    let fill_end = if visible_range.end < diagram.event_count() {
+        bottom.saturating_sub(1)
+    } else {
+        bottom
+    };
+    let last = visible_range.end.checked_sub(1);
+    for (p, &x) in participants.iter().enumerate() {
+        let depth = last.map_or(0, |i| depth_after(i, p));
+        for row in y - 1..fill_end {
+            draw(row, x, depth);
+        }
+    }
+}
+
+/// Returns the horizontal extent of each block. Nested blocks get a smaller margin so that
+/// each frame stays visible.
+fn block_extents(
+    diagram: &SequenceDiagram,
+    blocks: &[BlockBounds],
+    area: Rect,
+    participants: &[u16],
+) -> Vec<(u16, u16)> {
+    let min_x = area.x;
+    let max_x = area.x + area.width.saturating_sub(1);
+    blocks
+        .iter()
+        .map(|block| {
+            let end = block.end.unwrap_or(diagram.event_count());
+            let nested = blocks
+                .iter()
+                .filter(|other| other.start > block.start && other.start < end)
+                .map(|other| other.depth - block.depth)
+                .max()
+                .unwrap_or(0);
+            let margin = 3 + 2 * nested as u16;
+            let (lo, hi) = diagram
+                .participant_span(block.start, end)
+                .unwrap_or((0, participants.len() - 1));
+            let left = participants[lo].saturating_sub(margin).max(min_x);
+            let right = (participants[hi] + margin).min(max_x);
+            (left, right)
+        })
+        .collect()
+}
+
+/// Fills `rect` highlight regions with a dimmed version of their colour.
+fn render_highlights(
+    f: &mut Frame,
+    area: Rect,
+    world: &World,
+    participants: &[u16],
+    lifeline_start: u16,
+) {
+    let diagram = world.get::<SequenceDiagram>();
+    let scroll = world.get::<ScrollState>();
+    let visible_range = scroll.visible_range(diagram);
+    let blocks = diagram.blocks();
+
+    if participants.is_empty() || !blocks.iter().any(|b| is_highlight(diagram, b)) {
+        return;
+    }
+
+    let extents = block_extents(diagram, &blocks, area, participants);
+    let bottom = area.y + area.height;
+
+    let mut y = lifeline_start + FIRST_MESSAGE_OFFSET;
+    for i in visible_range {
+        let event = &diagram.events[i];
+        let top = y - 1;
+        let height = scroll.event_height(event).min(bottom.saturating_sub(top));
+
+        // Outer regions are drawn first so that nested ones paint over them.
+        for (block, &(left, right)) in blocks.iter().zip(&extents) {
+            let Event::HighlightStart { color } = diagram.events[block.start] else {
+                continue;
+            };
+            if i < block.start || block.end.is_some_and(|end| i > end) {
+                continue;
+            }
+            f.render_widget(
+                Block::default().style(Style::default().bg(highlight_display_color(color))),
+                Rect {
+                    x: left,
+                    y: top,
+                    width: right - left + 1,
+                    height,
+                },
+            );
+        }
+
+        y += scroll.event_height(event);
+    }
+}
+
+fn is_highlight(diagram: &SequenceDiagram, block: &BlockBounds) -> bool {
+    matches!(diagram.events[block.start], Event::HighlightStart { .. })
+}
+
+/// Mermaid highlight colours are usually light pastels, so they are darkened to keep text
+/// readable on a dark terminal. More opaque colours are drawn stronger.
+fn highlight_display_color(color: RgbColor) -> Color {
+    let opacity = f32::from(color.alpha.unwrap_or(u8::MAX)) / 255.0;
+    let factor = 0.1 + 0.2 * opacity;
+    let scale = |c: u8| (f32::from(c) * factor).round() as u8;
+    Color::Rgb(scale(color.r), scale(color.g), scale(color.b))
+}
+
+/// Draws control-flow fragments as labelled frames around the participants they cover, and
+/// labels the start and end of highlight regions.
+fn render_fragments(
+    f: &mut Frame,
+    area: Rect,
+    world: &World,
+    participants: &[u16],
+    lifeline_start: u16,
+    highlight_labels: bool,
+) {
+    let diagram = world.get::<SequenceDiagram>();
+    let range = world.get::<EditorState>().event_range();
+    let theme = world.get::<Theme>();
+    let scroll = world.get::<ScrollState>();
+    let visible_range = scroll.visible_range(diagram);
+    // All blocks are used for nesting so that frames and highlights do not overlap.
+    let fragments = diagram.blocks();
+
+    if fragments.is_empty() || participants.is_empty() {
+        return;
+    }
+
+    let extents = block_extents(diagram, &fragments, area, participants);
+
+    let mut y = lifeline_start + FIRST_MESSAGE_OFFSET;
+    for i in visible_range {
+        let event = &diagram.events[i];
+        let top = y - 1;
+        let is_selected = range.is_some_and(|(start, end)| (start..=end).contains(&i));
+        let style = if is_selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+
+        for (fragment, &(left, right)) in fragments.iter().zip(&extents) {
+            let inside = fragment.start < i && fragment.end.is_none_or(|end| i < end);
+            let is_fragment = matches!(diagram.events[fragment.start], Event::FragmentStart { .. });
+            if !inside || !is_fragment || fragment.sections.contains(&i) {
+                continue;
+            }
+            for row in top..top + scroll.event_height(event) {
+                for x in [left, right] {
+                    f.render_widget(
+                        Paragraph::new("│").style(theme.text),
+                        Rect {
+                            x,
+                            y: row,
+                            width: 1,
+                            height: 1,
+                        },
+                    );
+                }
+            }
+        }
+
+        let frame_line = match event {
+            Event::FragmentStart { kind, label } => fragments
+                .iter()
+                .position(|fragment| fragment.start == i)
+                .map(|idx| (idx, ('┌', '─', '┐'), kind.as_mermaid_str(), label.as_str())),
+            Event::FragmentSection { label } => fragments
+                .iter()
+                .position(|fragment| fragment.sections.contains(&i))
+                .map(|idx| {
+                    let keyword = match &diagram.events[fragments[idx].start] {
+                        Event::FragmentStart { kind, .. } => kind.section_keyword(),
+                        _ => None,
+                    };
+                    (
+                        idx,
+                        ('├', '╌', '┤'),
+                        keyword.unwrap_or("else"),
+                        label.as_str(),
+                    )
+                }),
+            Event::FragmentEnd => fragments
+                .iter()
+                .position(|fragment| fragment.end == Some(i))
+                .map(|idx| (idx, ('└', '─', '┘'), "", "")),
+            _ => None,
+        };
+
+        let highlight_label = match event {
+            _ if !highlight_labels => None,
+            Event::HighlightStart { color } => Some(format!("rect {}", color.as_mermaid_string())),
+            Event::HighlightEnd => Some("end".to_string()),
+            _ => None,
+        };
+        if let Some(text) = highlight_label
+            && let Some(idx) = fragments
+                .iter()
+                .position(|block| block.start == i || block.end == Some(i))
+        {
+            let (left, right) = extents[idx];
+            let width = (right - left).saturating_sub(1).min(text.len() as u16);
+            f.render_widget(
+                Paragraph::new(text).style(if is_selected { style } else { theme.muted }),
+                Rect {
+                    x: left + 1,
+                    y: top,
+                    width,
+                    height: 1,
+                },
+            );
+        }
+
+        if let Some((idx, (left_char, fill, right_char), keyword, label)) = frame_line {
+            let (left, right) = extents[idx];
+            let width = (right - left + 1) as usize;
+            let title = match (keyword.is_empty(), label.is_empty()) {
+                (true, _) => String::new(),
+                (false, true) => format!("[{keyword}]"),
+                (false, false) => format!("[{keyword}] {label}"),
+            };
+            let mut line = String::from(left_char);
+            line.extend(title.chars().take(width.saturating_sub(2)));
+            while line.chars().count() < width.saturating_sub(1) {
+                line.push(fill);
+            }
+            line.push(right_char);
+            f.render_widget(
+                Paragraph::new(line).style(style),
+                Rect {
+                    x: left,
+                    y: top,
+                    width: width as u16,
+                    height: 1,
+                },
+            );
+        }
+
+        y += scroll.event_height(event);
+    }
+}
+
 fn render_events(f: &mut Frame, world: &World, participants: &[u16], lifeline_start: u16) {
     let diagram = world.get::<SequenceDiagram>();
-    let selection = world.get::<EditorState>().selection;
+    let range = world.get::<EditorState>().event_range();
     let theme = world.get::<Theme>();
     let scroll = world.get::<ScrollState>();
     let visible_range = scroll.visible_range(diagram);
+    let numbers = diagram.message_numbers();
 
     let base_y = lifeline_start + FIRST_MESSAGE_OFFSET;
     let mut y = base_y;
@@ -280,15 +823,31 @@ fn render_events(f: &mut Frame, world: &World, participants: &[u16], lifeline_st
             break;
         }
 
-        let style = if selection == Selection::Event(i) {
+        let is_selected = range.is_some_and(|(start, end)| (start..=end).contains(&i));
+        let style = if is_selected {
             theme.selected
         } else {
             theme.text
         };
 
         match event {
-            Event::Message { from, to, text } => {
-                render_message(f, participants, *from, *to, text, y, style);
+            Event::Message {
+                from,
+                to,
+                text,
+                arrow,
+                ..
+            } => {
+                let mut lines = wrap_text(text, scroll.text_width());
+                if let Some(n) = numbers[i] {
+                    lines[0].insert_str(0, &format!("{n}. "));
+                }
+                let arrow_y = y + lines.len() as u16 - 1;
+                if let (Some(from), Some(to)) =
+                    (diagram.position_of(*from), diagram.position_of(*to))
+                {
+                    render_message(f, participants, from, to, &lines, *arrow, arrow_y, style);
+                }
             }
             Event::Note {
                 position,
@@ -296,34 +855,88 @@ fn render_events(f: &mut Frame, world: &World, participants: &[u16], lifeline_st
                 participant_end,
                 text,
             } => {
-                render_note(
-                    f,
-                    participants,
-                    *position,
-                    *participant_start,
-                    *participant_end,
-                    text,
-                    y,
-                    style,
+                if let (Some(start), Some(end)) = (
+                    diagram.position_of(*participant_start),
+                    diagram.position_of(*participant_end),
+                ) {
+                    render_note(
+                        f,
+                        participants,
+                        *position,
+                        start,
+                        end,
+                        &wrap_text(text, scroll.text_width()),
+                        y,
+                        style,
+                    );
+                }
+            }
+            Event::Activation {
+                participant,
+                active,
+            } => {
+                let keyword = if *active { "activate" } else { "deactivate" };
+                let x = diagram
+                    .position_of(*participant)
+                    .map_or(0, |p| participants[p].saturating_add(3));
+                let style = if is_selected {
+                    theme.selected
+                } else {
+                    theme.muted
+                };
+                f.render_widget(
+                    Paragraph::new(keyword).style(style),
+                    Rect {
+                        x,
+                        y: y.saturating_sub(1),
+                        width: keyword.len() as u16,
+                        height: 1,
+                    },
                 );
             }
+            // Fragment frames and highlight labels are drawn by `render_fragments`.
+            Event::FragmentStart { .. }
+            | Event::FragmentSection { .. }
+            | Event::FragmentEnd
+            | Event::HighlightStart { .. }
+            | Event::HighlightEnd => {}
         }
 
-        y += event.height();
+        y += scroll.event_height(event);
     }
 }
 
+/// Glyphs used to draw an arrow: horizontal line, vertical line, right head and left head.
+fn arrow_glyphs(arrow: Arrow) -> (char, char, char, char) {
+    let (line, vertical) = match arrow.line {
+        LineStyle::Solid => ('─', '│'),
+        LineStyle::Dotted => ('╌', '╎'),
+    };
+    let (right, left) = match arrow.head {
+        ArrowHead::Arrow => ('▶', '◀'),
+        ArrowHead::Open => (line, line),
+        ArrowHead::Cross => ('✕', '✕'),
+        ArrowHead::Async => ('▷', '◁'),
+    };
+    (line, vertical, right, left)
+}
+
+#[allow(clippy::too_many_arguments)]
+/// Draws a message with its arrow at row `y` and the lines of its label above it.
 fn render_message(
     f: &mut Frame,
     participants: &[u16],
     from: usize,
     to: usize,
-    text: &str,
+    lines: &[String],
+    arrow: Arrow,
     y: u16,
     style: ratatui::style::Style,
 ) {
+    let rows = lines.len() as u16;
     let from_x = participants[from];
     let to_x = participants[to];
+    let (line, vertical, right_head, left_head) = arrow_glyphs(arrow);
 
     if from == to {
         let loop_width: u16 = 4;
@@ -335,28 +948,42 @@ fn render_message(
             height: 1,
         };
 
-        f.render_widget(Paragraph::new("───┐").style(style), area);
+        f.render_widget(
+            Paragraph::new(format!("{line}{line}{line}┐")).style(style),
+            area,
+        );
 
         area.y = y;
-        f.render_widget(Paragraph::new("   │").style(style), area);
+        f.render_widget(Paragraph::new(format!("   {vertical}")).style(style), area);
 
         area.y = y + 1;
-        f.render_widget(Paragraph::new("◀──┘").style(style), area);
+        f.render_widget(
+            Paragraph::new(format!("{left_head}{line}{line}┘")).style(style),
+            area,
+        );
 
-        area.x = from_x + loop_width;
-        area.y = y;
-        area.width = text.len() as u16;
-        f.render_widget(Paragraph::new(text).style(style), area);
+        // Self-message labels end beside the loop
+        for (row, line) in (y + 1 - rows..).zip(lines) {
+            f.render_widget(
+                Paragraph::new(line.as_str()).style(style),
+                Rect {
+                    x: from_x + loop_width,
+                    y: row,
+                    width: line.chars().count() as u16,
+                    height: 1,
+                },
+            );
+        }
     } else {
         let start = from_x.min(to_x);
         let end = from_x.max(to_x);
         let len = end - start;
 
-        let mut arrow = "─".repeat(len as usize);
+        let mut arrow = line.to_string().repeat(len as usize);
         if from_x < to_x {
-            arrow.push('▶');
+            arrow.push(right_head);
         } else {
-            arrow.insert(0, '◀');
+            arrow.insert(0, left_head);
         }
 
         f.render_widget(
@@ -369,21 +996,21 @@ fn render_message(
             },
         );
 
-        let text_width = text.len() as u16;
         let text_x = start + len.div_ceil(2);
-        let text_start = text_x.saturating_sub(text_width / 2);
-
-        f.render_widget(
-            Paragraph::new(text)
-                .alignment(Alignment::Center)
-                .style(style),
-            Rect {
-                x: text_start,
-                y: y.saturating_sub(1),
-                width: text_width,
-                height: 1,
-            },
-        );
+        for (row, line) in (y.saturating_sub(rows)..).zip(lines) {
+            let text_width = line.chars().count() as u16;
+            f.render_widget(
+                Paragraph::new(line.as_str())
+                    .alignment(Alignment::Center)
+                    .style(style),
+                Rect {
+                    x: text_x.saturating_sub(text_width / 2),
+                    y: row,
+                    width: text_width,
+                    height: 1,
+                },
+            );
+        }
     }
 }
 
@@ -394,13 +1021,17 @@ fn render_note(
     position: NotePosition,
     participant_start: usize,
     participant_end: usize,
-    text: &str,
+    lines: &[String],
     y: u16,
     style: ratatui::style::Style,
 ) {
     let start_x = participants[participant_start];
     let end_x = participants[participant_end];
-    let text_width = text.len() as u16;
+    let text_width = lines
+        .iter()
+        .map(|line| line.chars().count() as u16)
+        .max()
+        .unwrap_or(0);
 
     let (x, width) = match position {
         NotePosition::Right => (start_x.saturating_add(2), text_width + 4),
@@ -426,14 +1057,42 @@ fn render_note(
         x,
         y: y.saturating_sub(1),
         width,
-        height: 1,
+        height: lines.len() as u16,
     };
 
     f.render_widget(Clear, area);
     f.render_widget(
-        Paragraph::new(text)
-            .alignment(Alignment::Center)
-            .style(style.reversed()),
+        Paragraph::new(
+            lines
+                .iter()
+                .map(|line| Line::from(line.as_str()))
+                .collect::<Vec<_>>(),
+        )
+        .alignment(Alignment::Center)
+        .style(style.reversed()),
         area,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Autonumber;
+
+    #[test]
+    fn test_text_width_leaves_room_for_numbers() {
+        let mut diagram = SequenceDiagram::from_mermaid("sequenceDiagram\n    A->>B: hi").unwrap();
+        let area = Rect::new(0, 0, 60, 20);
+        assert_eq!(text_width(area, &diagram), 18);
+
+        diagram.autonumber = Some(Autonumber::default());
+        for _ in 0..9 {
+            diagram.add_message(0, 1, "a long label that needs wrapping".to_string());
+        }
+        assert_eq!(number_width(&diagram), "10. ".len() as u16);
+        let width = text_width(area, &diagram);
+        assert_eq!(width, 14);
+        let first = wrap_text("a long label that needs wrapping", width).remove(0);
+        assert!(format!("10. {first}").len() <= 18);
+    }
+}
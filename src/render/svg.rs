@@ -0,0 +1,640 @@
+use std::fmt::Write as _;
+
+use crate::core::{
+    ArrowHead, BoxColor, Event, LineStyle, NotePosition, ParticipantKind, RgbColor, SequenceDiagram,
+};
+
+const MARGIN: i32 = 20;
+const FONT_SIZE: i32 = 13;
+/// Estimated advance of one character in the monospace font.
+const CHAR_WIDTH: i32 = 8;
+const LINE_HEIGHT: i32 = 17;
+const TITLE_HEIGHT: i32 = 32;
+const BOX_LABEL_HEIGHT: i32 = 22;
+const HEADER_HEIGHT: i32 = 36;
+const MIN_SPACING: i32 = 140;
+const SELF_LOOP_WIDTH: i32 = 36;
+const STROKE: &str = "#333333";
+
+/// Renders the diagram as an SVG document. Coordinates are whole pixels and elements are
+/// written in diagram order, so the same diagram always produces the same bytes.
+pub fn to_svg(diagram: &SequenceDiagram) -> String {
+    let mut canvas = Canvas::default();
+    let count = diagram.participants.len().max(1);
+    let spacing = column_spacing(diagram);
+    let centers: Vec<i32> = (0..diagram.participants.len())
+        .map(|i| MARGIN + spacing / 2 + i as i32 * spacing)
+        .collect();
+    canvas.extend(0, 2 * MARGIN + count as i32 * spacing);
+
+    let mut y = MARGIN;
+    let title_y = diagram.metadata.title.as_ref().map(|_| {
+        y += TITLE_HEIGHT;
+        MARGIN + FONT_SIZE + 4
+    });
+    let box_top = y;
+    if diagram.boxes.iter().any(|b| !b.label.is_empty()) {
+        y += BOX_LABEL_HEIGHT;
+    }
+    let header_top = y;
+    let lifeline_top = header_top + HEADER_HEIGHT;
+
+    let rows = layout_rows(diagram, lifeline_top + 16);
+    let bottom = rows.last().map_or(lifeline_top + 16, |row| row.1) + 12;
+
+    draw_boxes(&mut canvas, diagram, &centers, box_top, bottom + 8);
+    draw_highlights(&mut canvas, diagram, &centers, &rows);
+    for &x in &centers {
+        canvas.push(format!(
+            r#"<line x1="{x}" y1="{lifeline_top}" x2="{x}" y2="{bottom}" stroke="{STROKE}" stroke-dasharray="3 3"/>"#
+        ));
+    }
+    draw_fragments(&mut canvas, diagram, &centers, &rows);
+    for (participant, &x) in diagram.participants.iter().zip(&centers) {
+        draw_header(
+            &mut canvas,
+            participant.kind,
+            participant.display_name(),
+            x,
+            header_top,
+        );
+    }
+    draw_events(&mut canvas, diagram, &centers, &rows);
+
+    let height = bottom + 8 + MARGIN;
+    if let (Some(title), Some(title_y)) = (&diagram.metadata.title, title_y) {
+        let x = i32::midpoint(canvas.min_x, canvas.max_x);
+        canvas.push(format!(
+            r#"<text x="{x}" y="{title_y}" text-anchor="middle" font-weight="bold">{}</text>"#,
+            escape(title)
+        ));
+    }
+    canvas.finish(height)
+}
+
+/// The elements of the document and the horizontal extent they cover.
+#[derive(Default)]
+struct Canvas {
+    elements: Vec<String>,
+    min_x: i32,
+    max_x: i32,
+}
+
+impl Canvas {
+    fn push(&mut self, element: String) {
+        self.elements.push(element);
+    }
+
+    fn extend(&mut self, left: i32, right: i32) {
+        self.min_x = self.min_x.min(left - MARGIN / 2);
+        self.max_x = self.max_x.max(right + MARGIN / 2);
+    }
+
+    fn finish(self, height: i32) -> String {
+        let Self {
+            elements,
+            min_x,
+            max_x,
+        } = self;
+        let width = max_x - min_x;
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="{min_x} 0 {width} {height}" font-family="monospace" font-size="{FONT_SIZE}">"#
+        );
+        svg.push('\n');
+        svg.push_str(MARKERS);
+        let _ = writeln!(
+            svg,
+            r#"  <rect x="{min_x}" y="0" width="{width}" height="{height}" fill="white"/>"#
+        );
+        for element in elements {
+            let _ = writeln!(svg, "  {element}");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+const MARKERS: &str = r##"  <defs>
+    <marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto">
+      <path d="M0,0 L10,5 L0,10 z" fill="#333333"/>
+    </marker>
+    <marker id="async" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto">
+      <path d="M0,0 L10,5 L0,10" fill="none" stroke="#333333" stroke-width="1.5"/>
+    </marker>
+    <marker id="cross" viewBox="0 0 10 10" refX="5" refY="5" markerWidth="8" markerHeight="8" orient="auto">
+      <path d="M0,0 L10,10 M0,10 L10,0" stroke="#333333" stroke-width="1.5"/>
+    </marker>
+  </defs>
+"##;
+
+fn text_width(text: &str) -> i32 {
+    text.split('\n')
+        .map(|line| line.chars().count() as i32)
+        .max()
+        .unwrap_or(0)
+        * CHAR_WIDTH
+}
+
+fn header_width(name: &str) -> i32 {
+    text_width(name) + 24
+}
+
+/// The distance between lifelines: wide enough for every header and message label.
+fn column_spacing(diagram: &SequenceDiagram) -> i32 {
+    let numbers = diagram.message_numbers();
+    let widest_message = diagram
+        .events
+        .iter()
+        .zip(&numbers)
+        .filter_map(|(event, number)| match event {
+            Event::Message { text, .. } => Some(text_width(&numbered(text, *number)) + 40),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let widest_header = diagram
+        .participants
+        .iter()
+        .map(|p| header_width(p.display_name()) + 24)
+        .max()
+        .unwrap_or(0);
+    MIN_SPACING.max(widest_message).max(widest_header)
+}
+
+fn numbered(text: &str, number: Option<u32>) -> String {
+    match number {
+        Some(n) => format!("{n}. {text}"),
+        None => text.to_string(),
+    }
+}
+
+/// Returns the top and bottom of each event's row.
+fn layout_rows(diagram: &SequenceDiagram, top: i32) -> Vec<(i32, i32)> {
+    let mut y = top;
+    diagram
+        .events
+        .iter()
+        .map(|event| {
+            let height = match event {
+                Event::Message { text, from, to, .. } => {
+                    let lines = text.split('\n').count() as i32;
+                    if from == to {
+                        lines * LINE_HEIGHT + 30
+                    } else {
+                        lines * LINE_HEIGHT + 16
+                    }
+                }
+                Event::Note { text, .. } => text.split('\n').count() as i32 * LINE_HEIGHT + 22,
+                Event::FragmentStart { .. } => 30,
+                Event::FragmentSection { .. } => 26,
+                Event::FragmentEnd => 14,
+                Event::HighlightStart { .. } | Event::HighlightEnd => 8,
+                Event::Activation { .. } => 0,
+            };
+            let row = (y, y + height);
+            y += height;
+            row
+        })
+        .collect()
+}
+
+fn fill(color: RgbColor) -> String {
+    let RgbColor { r, g, b, alpha } = color;
+    match alpha {
+        None => format!(r#"fill="rgb({r},{g},{b})""#),
+        Some(a) => {
+            let opacity = format!("{:.2}", f32::from(a) / 255.0);
+            let opacity = opacity.trim_end_matches('0').trim_end_matches('.');
+            format!(r#"fill="rgb({r},{g},{b})" fill-opacity="{opacity}""#)
+        }
+    }
+}
+
+fn draw_boxes(
+    canvas: &mut Canvas,
+    diagram: &SequenceDiagram,
+    centers: &[i32],
+    top: i32,
+    bottom: i32,
+) {
+    for b in &diagram.boxes {
+        let Some((first, last)) = diagram.box_span(b) else {
+            continue;
+        };
+        let (Some(&start), Some(&end)) = (centers.get(first), centers.get(last)) else {
+            continue;
+        };
+        let left = start - header_width(diagram.participants[first].display_name()) / 2 - 10;
+        let right = end + header_width(diagram.participants[last].display_name()) / 2 + 10;
+        let fill = match b.color {
+            BoxColor::Transparent => r#"fill="none""#.to_string(),
+            color => fill(color.light()),
+        };
+        canvas.push(format!(
+            r##"<rect x="{left}" y="{top}" width="{}" height="{}" {fill} stroke="#999999"/>"##,
+            right - left,
+            bottom - top
+        ));
+        if !b.label.is_empty() {
+            canvas.push(format!(
+                r#"<text x="{}" y="{}" text-anchor="middle" font-weight="bold">{}</text>"#,
+                i32::midpoint(left, right),
+                top + FONT_SIZE + 4,
+                escape(&b.label)
+            ));
+        }
+    }
+}
+
+/// The left and right edge of each block: around the participants it covers, with nested
+/// blocks inset so every frame stays visible.
+fn block_extents(diagram: &SequenceDiagram, centers: &[i32]) -> Vec<(i32, i32)> {
+    let blocks = diagram.blocks();
+    blocks
+        .iter()
+        .map(|block| {
+            let end = block.end.unwrap_or(diagram.event_count());
+            let nested = blocks
+                .iter()
+                .filter(|other| other.start > block.start && other.start < end)
+                .map(|other| other.depth - block.depth)
+                .max()
+                .unwrap_or(0);
+            let margin = 40 + 10 * nested as i32;
+            let (lo, hi) = diagram
+                .participant_span(block.start, end)
+                .unwrap_or((0, centers.len() - 1));
+            (centers[lo] - margin, centers[hi] + margin)
+        })
+        .collect()
+}
+
+fn draw_highlights(
+    canvas: &mut Canvas,
+    diagram: &SequenceDiagram,
+    centers: &[i32],
+    rows: &[(i32, i32)],
+) {
+    if centers.is_empty() {
+        return;
+    }
+    let extents = block_extents(diagram, centers);
+    for (block, (left, right)) in diagram.blocks().iter().zip(extents) {
+        let Event::HighlightStart { color } = diagram.events[block.start] else {
+            continue;
+        };
+        let top = rows[block.start].0;
+        let bottom = block
+            .end
+            .map_or_else(|| rows.last().map_or(top, |r| r.1), |end| rows[end].1);
+        canvas.push(format!(
+            r#"<rect x="{left}" y="{top}" width="{}" height="{}" {}/>"#,
+            right - left,
+            bottom - top,
+            fill(color)
+        ));
+    }
+}
+
+fn draw_fragments(
+    canvas: &mut Canvas,
+    diagram: &SequenceDiagram,
+    centers: &[i32],
+    rows: &[(i32, i32)],
+) {
+    if centers.is_empty() {
+        return;
+    }
+    let extents = block_extents(diagram, centers);
+    for (block, (left, right)) in diagram.blocks().iter().zip(extents) {
+        let Event::FragmentStart { kind, label } = &diagram.events[block.start] else {
+            continue;
+        };
+        canvas.extend(left, right);
+        let top = rows[block.start].0 + 4;
+        let bottom = block
+            .end
+            .map_or_else(|| rows.last().map_or(top, |r| r.1), |end| rows[end].0 + 4);
+        canvas.push(format!(
+            r#"<rect x="{left}" y="{top}" width="{}" height="{}" fill="none" stroke="{STROKE}"/>"#,
+            right - left,
+            bottom - top
+        ));
+        let keyword = kind.as_mermaid_str();
+        let tab = text_width(keyword) + 12;
+        canvas.push(format!(
+            r##"<path d="M{left},{} h{tab} v10 l-6,6 h-{}" fill="#eeeeee" stroke="{STROKE}"/>"##,
+            top,
+            tab - 6
+        ));
+        canvas.push(format!(
+            r#"<text x="{}" y="{}" font-weight="bold">{keyword}</text>"#,
+            left + 6,
+            top + FONT_SIZE
+        ));
+        if !label.is_empty() {
+            canvas.push(format!(
+                r#"<text x="{}" y="{}">[{}]</text>"#,
+                left + tab + 8,
+                top + FONT_SIZE,
+                escape(label)
+            ));
+        }
+        for &section in &block.sections {
+            let y = rows[section].0 + 4;
+            canvas.push(format!(
+                r#"<line x1="{left}" y1="{y}" x2="{right}" y2="{y}" stroke="{STROKE}" stroke-dasharray="4 3"/>"#
+            ));
+            if let Event::FragmentSection { label } = &diagram.events[section]
+                && !label.is_empty()
+            {
+                canvas.push(format!(
+                    r#"<text x="{}" y="{}">[{}]</text>"#,
+                    left + 8,
+                    y + FONT_SIZE + 2,
+                    escape(label)
+                ));
+            }
+        }
+    }
+}
+
+fn draw_header(canvas: &mut Canvas, kind: ParticipantKind, name: &str, x: i32, top: i32) {
+    let width = header_width(name);
+    canvas.extend(x - width / 2, x + width / 2);
+    let name = escape(name);
+    if kind == ParticipantKind::Actor {
+        canvas.push(format!(
+            r#"<path d="M{x},{} v10 M{},{} h16 M{},{} l8,-8 l8,8" fill="none" stroke="{STROKE}"/>"#,
+            top + 9,
+            x - 8,
+            top + 13,
+            x - 8,
+            top + 27,
+        ));
+        canvas.push(format!(
+            r#"<circle cx="{x}" cy="{}" r="4" fill="none" stroke="{STROKE}"/>"#,
+            top + 5
+        ));
+        canvas.push(format!(
+            r#"<text x="{x}" y="{}" text-anchor="middle">{name}</text>"#,
+            top + HEADER_HEIGHT - 1
+        ));
+        return;
+    }
+    let radius = match kind {
+        ParticipantKind::Database | ParticipantKind::Queue => 10,
+        _ => 3,
+    };
+    let stroke_width = match kind {
+        ParticipantKind::Collections => 3,
+        _ => 1,
+    };
+    canvas.push(format!(
+        r##"<rect x="{}" y="{top}" width="{width}" height="{}" rx="{radius}" fill="#eaeaff" stroke="{STROKE}" stroke-width="{stroke_width}"/>"##,
+        x - width / 2,
+        HEADER_HEIGHT - 4
+    ));
+    canvas.push(format!(
+        r#"<text x="{x}" y="{}" text-anchor="middle">{name}</text>"#,
+        top + HEADER_HEIGHT / 2 + 2
+    ));
+}
+
+fn draw_events(
+    canvas: &mut Canvas,
+    diagram: &SequenceDiagram,
+    centers: &[i32],
+    rows: &[(i32, i32)],
+) {
+    let numbers = diagram.message_numbers();
+    for (i, event) in diagram.events.iter().enumerate() {
+        let (top, _) = rows[i];
+        match event {
+            Event::Message {
+                from,
+                to,
+                text,
+                arrow,
+                ..
+            } => {
+                let (Some(from), Some(to)) = (diagram.position_of(*from), diagram.position_of(*to))
+                else {
+                    continue;
+                };
+                let lines: Vec<String> = numbered(text, numbers[i])
+                    .split('\n')
+                    .map(str::to_string)
+                    .collect();
+                let arrow_y = top + lines.len() as i32 * LINE_HEIGHT + 4;
+                let dash = match arrow.line {
+                    LineStyle::Solid => "",
+                    LineStyle::Dotted => r#" stroke-dasharray="5 3""#,
+                };
+                let marker = match arrow.head {
+                    ArrowHead::Arrow => r#" marker-end="url(#arrow)""#,
+                    ArrowHead::Open => "",
+                    ArrowHead::Cross => r#" marker-end="url(#cross)""#,
+                    ArrowHead::Async => r#" marker-end="url(#async)""#,
+                };
+                let (x1, x2) = (centers[from], centers[to]);
+                if from == to {
+                    let right = x1 + SELF_LOOP_WIDTH;
+                    canvas.push(format!(
+                        r#"<path d="M{x1},{arrow_y} H{right} V{} H{x1}" fill="none" stroke="{STROKE}"{dash}{marker}/>"#,
+                        arrow_y + 16
+                    ));
+                    let text_x = right + 6;
+                    for (row, line) in lines.iter().enumerate() {
+                        canvas.extend(text_x, text_x + text_width(line));
+                        canvas.push(format!(
+                            r#"<text x="{text_x}" y="{}">{}</text>"#,
+                            arrow_y + 12 + (row as i32 + 1 - lines.len() as i32) * LINE_HEIGHT,
+                            escape(line)
+                        ));
+                    }
+                } else {
+                    canvas.push(format!(
+                        r#"<line x1="{x1}" y1="{arrow_y}" x2="{x2}" y2="{arrow_y}" stroke="{STROKE}"{dash}{marker}/>"#
+                    ));
+                    let text_x = i32::midpoint(x1, x2);
+                    for (row, line) in lines.iter().enumerate() {
+                        canvas.push(format!(
+                            r#"<text x="{text_x}" y="{}" text-anchor="middle">{}</text>"#,
+                            top + (row as i32 + 1) * LINE_HEIGHT - 3,
+                            escape(line)
+                        ));
+                    }
+                }
+            }
+            Event::Note {
+                position,
+                participant_start,
+                participant_end,
+                text,
+            } => {
+                let (Some(start), Some(end)) = (
+                    diagram.position_of(*participant_start),
+                    diagram.position_of(*participant_end),
+                ) else {
+                    continue;
+                };
+                draw_note(
+                    canvas,
+                    *position,
+                    centers[start],
+                    centers[end],
+                    text,
+                    top + 6,
+                );
+            }
+            Event::Activation { .. }
+            | Event::FragmentStart { .. }
+            | Event::FragmentSection { .. }
+            | Event::FragmentEnd
+            | Event::HighlightStart { .. }
+            | Event::HighlightEnd => {}
+        }
+    }
+}
+
+fn draw_note(
+    canvas: &mut Canvas,
+    position: NotePosition,
+    start: i32,
+    end: i32,
+    text: &str,
+    top: i32,
+) {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let width = text_width(text) + 20;
+    let (left, width) = match position {
+        NotePosition::Right => (start + 10, width),
+        NotePosition::Left => (start - 10 - width, width),
+        NotePosition::Over => {
+            let (lo, hi) = (start.min(end), start.max(end));
+            if hi > lo {
+                let width = width.max(hi - lo + 40);
+                ((lo + hi - width) / 2, width)
+            } else {
+                (lo - width / 2, width)
+            }
+        }
+    };
+    canvas.extend(left, left + width);
+    canvas.push(format!(
+        r##"<rect x="{left}" y="{top}" width="{width}" height="{}" fill="#fff5ad" stroke="#aaaa33"/>"##,
+        lines.len() as i32 * LINE_HEIGHT + 8
+    ));
+    for (row, line) in lines.iter().enumerate() {
+        canvas.push(format!(
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            left + width / 2,
+            top + (row as i32 + 1) * LINE_HEIGHT,
+            escape(line)
+        ));
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg_is_deterministic() {
+        let input = "sequenceDiagram
+    box rgba(10, 20, 30, 0.5) Back & forth
+        participant A
+        participant B
+    end
+    A->>B: x < y
+    Note over A,B: done";
+        let svg = to_svg(&SequenceDiagram::from_mermaid(input).unwrap());
+
+        assert_eq!(svg, to_svg(&SequenceDiagram::from_mermaid(input).unwrap()));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains(r#"fill="rgb(10,20,30)" fill-opacity="0.5""#));
+        assert!(svg.contains(">Back &amp; forth</text>"));
+        assert!(svg.contains(">x &lt; y</text>"));
+        assert!(svg.contains(r#"marker-end="url(#arrow)""#));
+        assert!(svg.contains(r##"fill="#fff5ad""##));
+    }
+
+    /// The value of `name` on every `<tag` element in `svg`.
+    fn attrs<'a>(svg: &'a str, tag: &str, name: &str) -> Vec<&'a str> {
+        svg.lines()
+            .filter(|line| line.trim_start().starts_with(&format!("<{tag} ")))
+            .filter_map(|line| {
+                let start = line.find(&format!(" {name}=\""))? + name.len() + 3;
+                line[start..].split('"').next()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_svg_layout() {
+        let input = "sequenceDiagram
+    participant A as Alice
+    actor B as Bob
+    A->>B: Hello
+    B-->>A: Hi
+    A-xB: Bye";
+        let svg = to_svg(&SequenceDiagram::from_mermaid(input).unwrap());
+
+        // One lifeline per participant, with its name centered above it
+        let lifelines: Vec<&str> = svg
+            .lines()
+            .filter(|line| line.contains(r#"stroke-dasharray="3 3""#))
+            .collect();
+        assert_eq!(lifelines.len(), 2);
+        let (alice, bob) = (
+            attrs(lifelines[0], "line", "x1")[0],
+            attrs(lifelines[1], "line", "x1")[0],
+        );
+        assert!(svg.contains(&format!(
+            r#"<text x="{alice}" y="40" text-anchor="middle">Alice</text>"#
+        )));
+        assert!(svg.contains(&format!(
+            r#"<text x="{bob}" y="55" text-anchor="middle">Bob</text>"#
+        )));
+        assert_eq!(
+            attrs(&svg, "circle", "cx"),
+            vec![bob],
+            "Bob is drawn as an actor"
+        );
+
+        // Arrows run between the lifelines in order, each with its label above it
+        let arrows: Vec<&str> = svg
+            .lines()
+            .filter(|line| line.contains("marker-end="))
+            .collect();
+        let ends: Vec<(&str, &str)> = arrows
+            .iter()
+            .map(|line| (attrs(line, "line", "x1")[0], attrs(line, "line", "x2")[0]))
+            .collect();
+        assert_eq!(ends, vec![(alice, bob), (bob, alice), (alice, bob)]);
+        assert!(arrows[0].contains("url(#arrow)") && !arrows[0].contains("dasharray"));
+        assert!(arrows[1].contains("url(#arrow)") && arrows[1].contains("dasharray"));
+        assert!(arrows[2].contains("url(#cross)"));
+        let ys: Vec<i32> = arrows
+            .iter()
+            .map(|line| attrs(line, "line", "y1")[0].parse().unwrap())
+            .collect();
+        assert!(ys.is_sorted());
+        for (label, y) in ["Hello", "Hi", "Bye"].into_iter().zip(ys) {
+            let text = svg
+                .lines()
+                .find(|line| line.ends_with(&format!(">{label}</text>")))
+                .unwrap();
+            let label_y: i32 = attrs(text, "text", "y")[0].parse().unwrap();
+            assert!(label_y < y, "{label} is above its arrow");
+        }
+    }
+}
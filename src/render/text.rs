@@ -0,0 +1,299 @@
+use anyhow::{Result, bail};
+use ratatui::{
+    Terminal, backend::TestBackend, buffer::Buffer, layout::Rect, style::Modifier, text::Span,
+};
+use tui_world::World;
+
+use super::sequence::{header_width, number_width, render_diagram, text_width};
+use crate::{
+    core::{Event, SequenceDiagram},
+    theme::Theme,
+    ui::{EditorState, FIRST_MESSAGE_OFFSET, HEADER_HEIGHT, scroll::ScrollState},
+};
+
+/// The characters a text export may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// Unicode box drawing, as shown in the editor.
+    Unicode,
+    /// Plain ASCII, for places that mangle anything else.
+    Ascii,
+}
+
+/// The most cells a text export may draw, to keep a huge diagram from exhausting memory.
+const MAX_CELLS: usize = 1 << 24;
+
+/// Renders the diagram as plain text with the editor's layout. The canvas is sized to fit the
+/// whole diagram, so text is only broken at explicit line breaks.
+///
+/// Fails if the diagram is too large to draw on one canvas.
+pub fn to_text(diagram: &SequenceDiagram, charset: Charset) -> Result<String> {
+    let count = diagram.participants.len().max(1);
+    let width = column_spacing(diagram).saturating_mul(count.saturating_add(1));
+    let height =
+        usize::from(HEADER_HEIGHT + FIRST_MESSAGE_OFFSET).saturating_add(total_height(diagram));
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        bail!("The diagram is too large for a text export ({width}×{height} characters)");
+    };
+    if usize::from(width) * usize::from(height) > MAX_CELLS {
+        bail!("The diagram is too large for a text export ({width}×{height} characters)");
+    }
+    let area = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+
+    let mut world = World::default();
+    world.insert(Theme::default());
+    world.insert(diagram.clone());
+    world.insert(EditorState::new());
+    world.insert(ScrollState::new());
+    world
+        .get_mut::<ScrollState>()
+        .set_viewport(area.height, text_width(area, diagram));
+
+    let Ok(mut terminal) = Terminal::new(TestBackend::new(area.width, area.height));
+    let Ok(_) = terminal.draw(|f| render_diagram(f, area, &world, false));
+
+    let mut lines = Vec::new();
+    if let Some(title) = &diagram.metadata.title {
+        let pad = usize::from(area.width).saturating_sub(title.chars().count()) / 2;
+        lines.push(format!("{}{title}", " ".repeat(pad)));
+        lines.push(String::new());
+    }
+    lines.extend(buffer_lines(terminal.backend().buffer(), charset));
+    Ok(finish(lines))
+}
+
+/// The distance between lifelines: wide enough for every header, label and note on one line.
+fn column_spacing(diagram: &SequenceDiagram) -> usize {
+    // Text is wrapped with room for the widest number, whatever the message's own number.
+    let number_width = usize::from(number_width(diagram));
+    let widest_text = diagram
+        .events
+        .iter()
+        .map(|event| {
+            let (text, prefix) = match event {
+                Event::Message { text, .. } => (text.as_str(), number_width),
+                Event::Note { text, .. } => (text.as_str(), 0),
+                Event::FragmentStart { kind, label } => {
+                    (label.as_str(), kind.as_mermaid_str().len() + 3)
+                }
+                Event::FragmentSection { label } => (label.as_str(), "option".len() + 3),
+                Event::Activation { .. } => ("deactivate", 0),
+                Event::FragmentEnd | Event::HighlightStart { .. } | Event::HighlightEnd => ("", 0),
+            };
+            text.split('\n')
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0)
+                + prefix
+        })
+        .max()
+        .unwrap_or(0);
+    let widest_header = diagram
+        .participants
+        .iter()
+        .map(|p| usize::from(header_width(p)))
+        .max()
+        .unwrap_or(0);
+    (widest_text + 5).max(widest_header + 2).max(14)
+}
+
+fn total_height(diagram: &SequenceDiagram) -> usize {
+    diagram
+        .events
+        .iter()
+        .map(|e| usize::from(e.height(0)))
+        .sum()
+}
+
+/// Reads the buffer row by row. Notes are drawn reversed in the editor, which plain text
+/// cannot show, so their padding is replaced by brackets.
+fn buffer_lines(buffer: &Buffer, charset: Charset) -> Vec<String> {
+    let area = buffer.area;
+    (area.top()..area.bottom())
+        .map(|y| {
+            let mut line = String::new();
+            let mut covered = 0;
+            for x in area.left()..area.right() {
+                // Cells after a wide character only hold padding.
+                if covered > 0 {
+                    covered -= 1;
+                    continue;
+                }
+                let cell = &buffer[(x, y)];
+                covered = Span::raw(cell.symbol()).width().saturating_sub(1);
+                let reversed = |x: u16| {
+                    (area.left()..area.right()).contains(&x)
+                        && buffer[(x, y)].modifier.contains(Modifier::REVERSED)
+                };
+                if reversed(x) && !reversed(x.wrapping_sub(1)) {
+                    line.push('[');
+                } else if reversed(x) && !reversed(x + 1) {
+                    line.push(']');
+                } else {
+                    match charset {
+                        Charset::Unicode => line.push_str(cell.symbol()),
+                        Charset::Ascii => line.extend(cell.symbol().chars().map(ascii)),
+                    }
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// Removes trailing whitespace, the common indentation and trailing blank lines.
+fn finish(mut lines: Vec<String>) -> String {
+    for line in &mut lines {
+        line.truncate(line.trim_end().len());
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+/// The ASCII stand-in for a glyph drawn by the renderer.
+fn ascii(c: char) -> char {
+    match c {
+        '─' | '━' | '═' => '-',
+        '╌' | '┄' | '┈' => '.',
+        '│' | '┃' | '║' | '⊢' => '|',
+        '╎' | '┆' | '┊' | '⋮' => ':',
+        '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' | '╭' | '╮' | '╰' | '╯' | '┏' | '┓'
+        | '┗' | '┛' | '╔' | '╗' | '╚' | '╝' => '+',
+        '▶' | '▷' => '>',
+        '◀' | '◁' => '<',
+        '✕' => 'x',
+        '☺' | '○' => 'o',
+        '↻' => '@',
+        '⇉' => '=',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(input: &str, charset: Charset) -> String {
+        to_text(&SequenceDiagram::from_mermaid(input).unwrap(), charset).unwrap()
+    }
+
+    #[test]
+    fn test_text_message() {
+        let input = "sequenceDiagram\n    A->>B: Hello\n    B-->>A: Hi";
+        assert_eq!(
+            text(input, Charset::Unicode),
+            "┌───┐         ┌───┐
+│ A │         │ B │
+└───┘         └───┘
+  │             │
+  │    Hello    │
+  ──────────────▶
+  │             │
+  │     Hi      │
+  ◀╌╌╌╌╌╌╌╌╌╌╌╌╌╌
+  │             │
+  │             │
+"
+        );
+        let ascii = text(input, Charset::Ascii);
+        assert!(ascii.contains("| A |         | B |\n"));
+        assert!(ascii.contains("  -------------->\n"));
+        assert!(ascii.contains("  <..............\n"));
+        assert!(ascii.is_ascii());
+    }
+
+    #[test]
+    fn test_text_note() {
+        let input = "sequenceDiagram\n    A->>B: Hello\n    Note over A,B: done";
+        let lines: Vec<String> = text(input, Charset::Ascii)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let note = lines.iter().position(|l| l.contains("done")).unwrap();
+        let message = lines.iter().position(|l| l.contains("Hello")).unwrap();
+        assert!(note > message, "the note comes after the message");
+        // The note spans both lifelines.
+        let lifelines: Vec<usize> = lines[3].match_indices('|').map(|(x, _)| x).collect();
+        let (left, right) = (
+            lines[note].find('[').unwrap(),
+            lines[note].find(']').unwrap(),
+        );
+        assert_eq!(lifelines.len(), 2);
+        assert!(left < lifelines[0] && lifelines[1] <= right);
+    }
+
+    #[test]
+    fn test_text_fragment() {
+        let input = "sequenceDiagram
+    A->>B: before
+    loop Every minute
+        A-xB: ping
+    end";
+        let output = text(input, Charset::Ascii);
+        let lines: Vec<&str> = output.lines().collect();
+        let top = lines
+            .iter()
+            .position(|l| l.starts_with("+[loop] Every minute-"))
+            .unwrap();
+        let bottom = lines.iter().rposition(|l| l.starts_with("+---")).unwrap();
+        assert!(lines[top..bottom].iter().any(|l| l.contains("ping")));
+        assert!(lines[top..bottom].iter().any(|l| l.ends_with("x  |")));
+        assert!(lines[..top].iter().any(|l| l.contains("before")));
+        assert_eq!(lines[top].len(), lines[bottom].len());
+    }
+
+    #[test]
+    fn test_text_highlight() {
+        let input = "sequenceDiagram
+    rect rgba(0, 0, 255, 0.1)
+        A->>B: inside
+    end";
+        let output = text(input, Charset::Unicode);
+        assert!(output.contains("inside"));
+        assert!(!output.contains("rect"), "{output}");
+        assert!(!output.contains("end"), "{output}");
+    }
+
+    #[test]
+    fn test_text_too_large() {
+        let mut diagram = SequenceDiagram::new();
+        for n in 0..25 {
+            diagram.add_participant(format!("P{n}"));
+        }
+        diagram.add_message(0, 1, "x".repeat(3000));
+        let error = to_text(&diagram, Charset::Unicode).unwrap_err();
+        assert!(error.to_string().contains("too large"), "{error}");
+
+        while diagram.participant_count() > 2 {
+            diagram.remove_participant(2);
+        }
+        assert!(to_text(&diagram, Charset::Unicode).is_ok());
+    }
+
+    #[test]
+    fn test_text_title() {
+        let input = "---\ntitle: Login\n---\nsequenceDiagram\n    A->>B: Hello";
+        let output = text(input, Charset::Unicode);
+        let mut lines = output.lines();
+        assert_eq!(lines.next().map(str::trim), Some("Login"));
+        assert_eq!(lines.next(), Some(""));
+    }
+}
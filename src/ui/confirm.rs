@@ -6,10 +6,18 @@ use ratatui::{
 };
 use tui_world::World;
 
+use super::{EditorMode, EditorState};
 use crate::theme::Theme;
 
 pub fn render_confirm_dialog(frame: &mut Frame, world: &World) {
     let theme = world.get::<Theme>();
+    let (title, question) = match world.get::<EditorState>().mode {
+        EditorMode::ConfirmQuit => (" Unsaved Changes ", "Quit without saving?"),
+        EditorMode::ConfirmRestore => (" Recover ", "Restore autosaved changes?"),
+        EditorMode::ConfirmReload => (" File Changed ", "Reload and drop your edits?"),
+        EditorMode::ConfirmOverwrite => (" File Exists ", "Replace the existing file?"),
+        _ => (" Clear Diagram ", "Clear the entire diagram?"),
+    };
 
     let area = frame.area();
 
@@ -21,7 +29,7 @@ pub fn render_confirm_dialog(frame: &mut Frame, world: &World) {
     frame.render_widget(Clear, popup_area);
 
     let block = Block::default()
-        .title(" Clear Diagram ")
+        .title(title)
         .title_alignment(Alignment::Center)
         .title_style(theme.accent)
         .borders(Borders::ALL)
@@ -31,7 +39,7 @@ pub fn render_confirm_dialog(frame: &mut Frame, world: &World) {
     frame.render_widget(block, popup_area);
 
     frame.render_widget(
-        Paragraph::new("Clear the entire diagram?")
+        Paragraph::new(question)
             .style(theme.text)
             .alignment(Alignment::Center),
         Rect {
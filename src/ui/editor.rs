@@ -1,6 +1,10 @@
 use super::Selection;
 use crate::core::BoxColor;
+use crate::core::FragmentKind;
+use crate::core::Metadata;
 use crate::core::NotePosition;
+use crate::core::ParticipantKind;
+use anyhow::{Result, bail};
 use std::time::Instant;
 
 #[derive(Default, Clone, PartialEq, Eq)]
@@ -15,8 +19,13 @@ pub enum EditorMode {
     EditSelectFrom,
     EditSelectTo,
     RenameParticipant,
+    RenameParticipantLabel,
     Help,
     ConfirmClear,
+    ConfirmQuit,
+    ConfirmRestore,
+    ConfirmReload,
+    ConfirmOverwrite,
     SelectNoteParticipant,
     SelectNotePosition,
     SelectNoteEndParticipant,
@@ -29,6 +38,21 @@ pub enum EditorMode {
     SelectBoxEnd,
     SelectBoxColor,
     InputBoxLabel,
+    SelectFragmentKind,
+    EditFragmentKind,
+    InputFragmentLabel,
+    EditFragmentLabel,
+    InputFragmentSection,
+    SelectHighlightColor,
+    EditHighlightColor,
+    SelectParticipantKind,
+    InputAutonumber,
+    InputGotoMessage,
+    Diagnostics,
+    EditMetadata,
+    InputMetadataField,
+    SelectMarkdownBlock,
+    InputSavePath,
 }
 
 impl EditorMode {
@@ -52,6 +76,18 @@ impl EditorMode {
         matches!(self, Self::SelectFrom | Self::EditSelectFrom)
     }
 
+    /// Text inputs that accept line breaks.
+    pub fn is_multiline(&self) -> bool {
+        matches!(
+            self,
+            Self::InputMessage
+                | Self::EditMessage
+                | Self::InputNoteText
+                | Self::EditNoteText
+                | Self::InputMetadataField
+        )
+    }
+
     pub fn is_text_input(&self) -> bool {
         matches!(
             self,
@@ -59,13 +95,93 @@ impl EditorMode {
                 | Self::InputMessage
                 | Self::EditMessage
                 | Self::RenameParticipant
+                | Self::RenameParticipantLabel
                 | Self::InputNoteText
                 | Self::EditNoteText
                 | Self::InputBoxLabel
+                | Self::InputFragmentLabel
+                | Self::EditFragmentLabel
+                | Self::InputFragmentSection
+                | Self::InputAutonumber
+                | Self::InputGotoMessage
+                | Self::InputMetadataField
+                | Self::InputSavePath
         )
     }
 }
 
+/// A row of the metadata editor.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MetadataField {
+    Title,
+    AccTitle,
+    AccDescr,
+    Config,
+}
+
+impl MetadataField {
+    pub const ALL: [Self; 4] = [Self::Title, Self::AccTitle, Self::AccDescr, Self::Config];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::AccTitle => "Accessible title",
+            Self::AccDescr => "Accessible description",
+            Self::Config => "Config",
+        }
+    }
+
+    /// The field as edited, with config values as `key: value` lines.
+    pub fn value(self, metadata: &Metadata) -> String {
+        match self {
+            Self::Title => metadata.title.clone().unwrap_or_default(),
+            Self::AccTitle => metadata.acc_title.clone().unwrap_or_default(),
+            Self::AccDescr => metadata.acc_descr.clone().unwrap_or_default(),
+            Self::Config => metadata
+                .config
+                .iter()
+                .map(|(key, value)| format!("{key}: {value}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// Sets the field from its edited text; empty text clears it.
+    pub fn set(self, metadata: &mut Metadata, input: &str) -> Result<()> {
+        let input = input.trim();
+        // Titles are drawn on a single border row
+        let text = match self {
+            Self::Title | Self::AccTitle => input.replace('\n', " "),
+            Self::AccDescr | Self::Config => input.to_string(),
+        };
+        let text = Some(text).filter(|t| !t.is_empty());
+        match self {
+            Self::Title => metadata.title = text,
+            Self::AccTitle => metadata.acc_title = text,
+            Self::AccDescr => metadata.acc_descr = text,
+            Self::Config => {
+                let mut config = Vec::new();
+                for line in input.lines().filter(|l| !l.trim().is_empty()) {
+                    let Some((key, value)) = line.split_once(':') else {
+                        bail!("Config lines need 'key: value'");
+                    };
+                    let key = key.trim();
+                    let key = key.strip_prefix("config.").unwrap_or(key);
+                    if key.is_empty()
+                        || key.split('.').any(str::is_empty)
+                        || value.trim().is_empty()
+                    {
+                        bail!("Config lines need 'key: value'");
+                    }
+                    config.push((key.to_string(), value.trim().to_string()));
+                }
+                metadata.config = config;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct StatusMessage {
     pub text: String,
@@ -90,6 +206,14 @@ pub struct EditorState {
     pub box_start: Option<usize>,
     pub box_end: Option<usize>,
     pub box_color: BoxColor,
+    /// The box being edited by the box modes, instead of adding a new one.
+    pub editing_box: Option<usize>,
+    pub fragment_kind: FragmentKind,
+    pub participant_kind: ParticipantKind,
+    /// The events to wrap in a new fragment or highlight region.
+    pub wrap_range: Option<(usize, usize)>,
+    /// The other end of a multi-event selection, set with `v`.
+    pub range_anchor: Option<usize>,
 }
 
 impl EditorState {
@@ -112,6 +236,10 @@ impl EditorState {
         self.box_start = None;
         self.box_end = None;
         self.box_color = BoxColor::default();
+        self.editing_box = None;
+        self.fragment_kind = FragmentKind::default();
+        self.participant_kind = ParticipantKind::default();
+        self.wrap_range = None;
     }
 
     pub fn set_status(&mut self, msg: impl Into<String>) {
@@ -133,5 +261,15 @@ impl EditorState {
 
     pub fn clear_selection(&mut self) {
         self.selection = Selection::None;
+        self.range_anchor = None;
+    }
+
+    /// Returns the selected event range, spanning from the range anchor to the selected event.
+    pub fn event_range(&self) -> Option<(usize, usize)> {
+        let Selection::Event(idx) = self.selection else {
+            return None;
+        };
+        let anchor = self.range_anchor.unwrap_or(idx);
+        Some((anchor.min(idx), anchor.max(idx)))
     }
 }
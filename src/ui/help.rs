@@ -34,6 +34,10 @@ const HELP_SECTIONS: &[HelpSection] = &[
                 keys: "j/k, ↓/↑",
                 description: "Select message/note",
             },
+            HelpEntry {
+                keys: "g",
+                description: "Go to message number",
+            },
         ],
     },
     HelpSection {
@@ -51,6 +55,10 @@ const HELP_SECTIONS: &[HelpSection] = &[
                 keys: "n/N",
                 description: "Insert note after/before",
             },
+            HelpEntry {
+                keys: "Alt+Enter",
+                description: "New line in message/note",
+            },
         ],
     },
     HelpSection {
@@ -64,10 +72,38 @@ const HELP_SECTIONS: &[HelpSection] = &[
                 keys: "r",
                 description: "Rename selected",
             },
+            HelpEntry {
+                keys: "t",
+                description: "Change participant type",
+            },
             HelpEntry {
                 keys: "d",
                 description: "Delete selected",
             },
+            HelpEntry {
+                keys: "a",
+                description: "Cycle arrowhead",
+            },
+            HelpEntry {
+                keys: "-",
+                description: "Toggle dotted arrow",
+            },
+            HelpEntry {
+                keys: "+",
+                description: "Toggle activation",
+            },
+            HelpEntry {
+                keys: "c/x",
+                description: "Message creates/destroys target",
+            },
+            HelpEntry {
+                keys: "#",
+                description: "Set autonumber start/step",
+            },
+            HelpEntry {
+                keys: "T",
+                description: "Edit title and metadata",
+            },
             HelpEntry {
                 keys: "C",
                 description: "Clear diagram",
@@ -87,12 +123,41 @@ const HELP_SECTIONS: &[HelpSection] = &[
             },
         ],
     },
+    HelpSection {
+        title: "[Fragment]",
+        entries: &[
+            HelpEntry {
+                keys: "v",
+                description: "Mark range of messages/notes",
+            },
+            HelpEntry {
+                keys: "f",
+                description: "Wrap range in fragment",
+            },
+            HelpEntry {
+                keys: "o",
+                description: "Add else/and/option section",
+            },
+            HelpEntry {
+                keys: "R",
+                description: "Highlight range",
+            },
+            HelpEntry {
+                keys: "u",
+                description: "Unwrap fragment/highlight",
+            },
+        ],
+    },
     HelpSection {
         title: "[Box]",
         entries: &[
             HelpEntry {
                 keys: "b",
-                description: "Add box",
+                description: "Add box/Edit box here",
+            },
+            HelpEntry {
+                keys: "i",
+                description: "Move into/out of box",
             },
             HelpEntry {
                 keys: "B",
@@ -103,10 +168,38 @@ const HELP_SECTIONS: &[HelpSection] = &[
     HelpSection {
         title: "[Other]",
         entries: &[
+            HelpEntry {
+                keys: "Ctrl+s",
+                description: "Save",
+            },
+            HelpEntry {
+                keys: "Ctrl+o",
+                description: "Save as",
+            },
             HelpEntry {
                 keys: "E",
                 description: "Export to Mermaid",
             },
+            HelpEntry {
+                keys: "P",
+                description: "Export to PlantUML",
+            },
+            HelpEntry {
+                keys: "W",
+                description: "Export to JSON",
+            },
+            HelpEntry {
+                keys: "S",
+                description: "Export to SVG",
+            },
+            HelpEntry {
+                keys: "X/A",
+                description: "Export as text/ASCII text",
+            },
+            HelpEntry {
+                keys: "D",
+                description: "Show import diagnostics",
+            },
             HelpEntry {
                 keys: "?",
                 description: "Toggle help",
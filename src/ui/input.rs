@@ -6,9 +6,9 @@ use ratatui::{
 };
 use tui_world::World;
 
-use super::{EditorMode, EditorState};
+use super::{EditorMode, EditorState, MetadataField};
 use crate::{
-    core::{NotePosition, SequenceDiagram},
+    core::{NotePosition, Participant, SequenceDiagram},
     theme::Theme,
 };
 
@@ -20,17 +20,21 @@ pub fn render_input_popup(frame: &mut Frame, world: &World) {
     let (title, prompt) = match &editor.mode {
         EditorMode::InputParticipant => ("Add Participant".to_string(), Some("Name:".to_string())),
         EditorMode::RenameParticipant => {
-            ("Rename Participant".to_string(), Some("Name:".to_string()))
+            ("Rename Participant".to_string(), Some("Id:".to_string()))
         }
+        EditorMode::RenameParticipantLabel => (
+            "Rename Participant".to_string(),
+            Some("Label (empty for none):".to_string()),
+        ),
         EditorMode::InputMessage | EditorMode::EditMessage => {
             let from_name = editor
                 .message_from
                 .and_then(|i| diagram.participants.get(i))
-                .map_or("?", String::as_str);
+                .map_or("?", Participant::display_name);
             let to_name = editor
                 .message_to
                 .and_then(|i| diagram.participants.get(i))
-                .map_or("?", String::as_str);
+                .map_or("?", Participant::display_name);
             let title = if editor.mode == EditorMode::EditMessage {
                 "Edit Message"
             } else {
@@ -43,11 +47,11 @@ pub fn render_input_popup(frame: &mut Frame, world: &World) {
             let start_name = editor
                 .note_participant_start
                 .and_then(|i| diagram.participants.get(i))
-                .map_or("?", String::as_str);
+                .map_or("?", Participant::display_name);
             let end_name = editor
                 .note_participant_end
                 .and_then(|i| diagram.participants.get(i))
-                .map_or("?", String::as_str);
+                .map_or("?", Participant::display_name);
 
             let pos_str = match position {
                 NotePosition::Right => format!("Right of {start_name}"),
@@ -68,15 +72,47 @@ pub fn render_input_popup(frame: &mut Frame, world: &World) {
             };
             (title.to_string(), Some(format!("{pos_str}:")))
         }
-        EditorMode::InputBoxLabel => ("Add Box".to_string(), Some("Label:".to_string())),
+        EditorMode::InputBoxLabel => {
+            let title = if editor.editing_box.is_some() {
+                "Edit Box"
+            } else {
+                "Add Box"
+            };
+            (title.to_string(), Some("Label:".to_string()))
+        }
+        EditorMode::InputFragmentLabel => (
+            format!("Add {}", editor.fragment_kind.as_mermaid_str()),
+            Some("Label:".to_string()),
+        ),
+        EditorMode::EditFragmentLabel => ("Edit Fragment".to_string(), Some("Label:".to_string())),
+        EditorMode::InputFragmentSection => ("Add Section".to_string(), Some("Label:".to_string())),
+        EditorMode::InputAutonumber => (
+            "Autonumber".to_string(),
+            Some("Start and step (empty to disable):".to_string()),
+        ),
+        EditorMode::InputGotoMessage => ("Go to Message".to_string(), Some("Number:".to_string())),
+        EditorMode::InputSavePath => (
+            "Save As".to_string(),
+            Some("Path (.mmd, .puml, .json or .md):".to_string()),
+        ),
+        EditorMode::InputMetadataField => {
+            let field = MetadataField::ALL[editor.selected_index];
+            let prompt = if field == MetadataField::Config {
+                "One 'key: value' per line, e.g. sequence.mirrorActors: true".to_string()
+            } else {
+                format!("{} (empty for none):", field.label())
+            };
+            ("Diagram Metadata".to_string(), Some(prompt))
+        }
         _ => return,
     };
 
-    let input = &editor.input_buffer;
+    let input_lines: Vec<&str> = editor.input_buffer.split('\n').collect();
+    let input_height = input_lines.len() as u16;
     let area = frame.area();
 
     let popup_width = 50.min(area.width.saturating_sub(4));
-    let popup_height = if prompt.is_some() { 5 } else { 4 };
+    let popup_height = if prompt.is_some() { 5 } else { 4 } + input_height - 1;
 
     let popup_area = centered_rect(popup_width, popup_height, area);
 
@@ -108,22 +144,29 @@ pub fn render_input_popup(frame: &mut Frame, world: &World) {
         x: inner.x + padding,
         y: input_y,
         width: inner.width.saturating_sub(padding * 2),
-        height: 1,
+        height: input_height,
     };
 
-    let input_line = Line::from(vec![
-        Span::styled(input, theme.text),
-        Span::styled("█", theme.accent),
-    ]);
-    frame.render_widget(Paragraph::new(input_line), input_area);
+    let mut lines: Vec<Line> = input_lines
+        .iter()
+        .map(|line| Line::from(Span::styled(*line, theme.text)))
+        .collect();
+    if let Some(last) = lines.last_mut() {
+        last.push_span(Span::styled("█", theme.accent));
+    }
+    frame.render_widget(Paragraph::new(lines), input_area);
 
     let hint_area = Rect {
         x: inner.x + padding,
-        y: input_y + 1,
+        y: input_y + input_height,
         width: inner.width.saturating_sub(padding * 2),
         height: 1,
     };
-    let hint_text = "Enter: confirm | Esc: cancel";
+    let hint_text = if editor.mode.is_multiline() {
+        "Alt+Enter: new line | Enter: confirm | Esc: cancel"
+    } else {
+        "Enter: confirm | Esc: cancel"
+    };
     frame.render_widget(
         Paragraph::new(hint_text)
             .style(theme.muted)
@@ -6,7 +6,7 @@ pub mod scroll;
 mod selection;
 pub mod status_bar;
 
-pub use editor::{EditorMode, EditorState};
+pub use editor::{EditorMode, EditorState, MetadataField};
 pub use selection::Selection;
 
 /// Height of the participant header area
@@ -7,6 +7,8 @@ use crate::ui::HEADER_HEIGHT;
 pub struct ScrollState {
     pub offset: usize,
     viewport_height: u16,
+    /// The width message and note text is wrapped to, `0` before the first render.
+    text_width: u16,
 }
 
 impl ScrollState {
@@ -14,11 +16,21 @@ impl ScrollState {
         Self {
             offset: 0,
             viewport_height: 0,
+            text_width: 0,
         }
     }
 
-    pub fn set_viewport(&mut self, height: u16) {
+    pub fn set_viewport(&mut self, height: u16, text_width: u16) {
         self.viewport_height = height.saturating_sub(HEADER_HEIGHT);
+        self.text_width = text_width;
+    }
+
+    pub fn text_width(&self) -> u16 {
+        self.text_width
+    }
+
+    pub fn event_height(&self, event: &Event) -> u16 {
+        event.height(self.text_width)
     }
 
     pub fn ensure_visible(&mut self, index: usize, diagram: &SequenceDiagram) {
@@ -36,10 +48,11 @@ impl ScrollState {
         let mut end = self.offset;
 
         for event in diagram.events.iter().skip(self.offset) {
-            if height_used + event.height() > self.viewport_height {
+            let event_height = self.event_height(event);
+            if height_used + event_height > self.viewport_height {
                 break;
             }
-            height_used += event.height();
+            height_used += event_height;
             end += 1;
         }
 
@@ -47,7 +60,7 @@ impl ScrollState {
     }
 
     pub fn needs_scroll(&self, diagram: &SequenceDiagram) -> bool {
-        Self::total_height(diagram) > self.viewport_height
+        self.total_height(diagram) > self.viewport_height
     }
 
     pub fn scrollbar_state(&self, diagram: &SequenceDiagram) -> ScrollbarState {
@@ -61,7 +74,7 @@ impl ScrollState {
         let mut offset = index;
 
         for i in (0..=index).rev() {
-            let event_height = diagram.events.get(i).map_or(3, Event::height);
+            let event_height = diagram.events.get(i).map_or(3, |e| self.event_height(e));
             if height + event_height > self.viewport_height {
                 break;
             }
@@ -72,7 +85,7 @@ impl ScrollState {
         offset
     }
 
-    fn total_height(diagram: &SequenceDiagram) -> u16 {
-        diagram.events.iter().map(Event::height).sum()
+    fn total_height(&self, diagram: &SequenceDiagram) -> u16 {
+        diagram.events.iter().map(|e| self.event_height(e)).sum()
     }
 }
@@ -7,7 +7,12 @@ use ratatui::{
 use tui_world::World;
 
 use super::{EditorMode, EditorState, Selection};
-use crate::{core::SequenceDiagram, theme::Theme};
+use crate::{
+    app::{self, AppState},
+    core::SequenceDiagram,
+    file::OpenFile,
+    theme::Theme,
+};
 
 pub fn render_status_bar(frame: &mut Frame, area: Rect, world: &World) {
     let editor = world.get::<EditorState>();
@@ -18,15 +23,28 @@ pub fn render_status_bar(frame: &mut Frame, area: Rect, world: &World) {
     let status_message = editor.get_status();
     let participant_count = diagram.participant_count();
     let has_selection = editor.selection != Selection::None;
+    let diagnostic_count = world.get::<AppState>().diagnostics.len();
+    let file_name = world
+        .get::<AppState>()
+        .file
+        .as_ref()
+        .map_or_else(|| "untitled".to_string(), OpenFile::name);
+    let dirty = app::is_dirty(world);
 
     let (mode_text, mode_style) = match mode {
         EditorMode::Normal => ("NORMAL", theme.status_normal),
         EditorMode::InputParticipant | EditorMode::InputMessage => ("INPUT", theme.status_input),
         EditorMode::SelectFrom | EditorMode::EditSelectFrom => ("SELECT FROM", theme.status_select),
         EditorMode::SelectTo | EditorMode::EditSelectTo => ("SELECT TO", theme.status_select),
-        EditorMode::EditMessage | EditorMode::RenameParticipant => ("EDIT", theme.status_input),
+        EditorMode::EditMessage
+        | EditorMode::RenameParticipant
+        | EditorMode::RenameParticipantLabel => ("EDIT", theme.status_input),
         EditorMode::Help => ("HELP", theme.status_help),
-        EditorMode::ConfirmClear => ("CONFIRM", theme.status_select),
+        EditorMode::ConfirmClear
+        | EditorMode::ConfirmQuit
+        | EditorMode::ConfirmRestore
+        | EditorMode::ConfirmReload
+        | EditorMode::ConfirmOverwrite => ("CONFIRM", theme.status_select),
         EditorMode::SelectNoteParticipant | EditorMode::EditNoteParticipant => {
             ("SELECT PARTICIPANT", theme.status_select)
         }
@@ -41,6 +59,24 @@ pub fn render_status_bar(frame: &mut Frame, area: Rect, world: &World) {
         EditorMode::SelectBoxEnd => ("BOX END", theme.status_select),
         EditorMode::SelectBoxColor => ("BOX COLOR", theme.status_select),
         EditorMode::InputBoxLabel => ("BOX LABEL", theme.status_input),
+        EditorMode::SelectFragmentKind | EditorMode::EditFragmentKind => {
+            ("FRAGMENT", theme.status_select)
+        }
+        EditorMode::InputFragmentLabel | EditorMode::EditFragmentLabel => {
+            ("FRAGMENT LABEL", theme.status_input)
+        }
+        EditorMode::InputFragmentSection => ("SECTION", theme.status_input),
+        EditorMode::InputAutonumber => ("AUTONUMBER", theme.status_input),
+        EditorMode::InputGotoMessage => ("GO TO", theme.status_input),
+        EditorMode::InputSavePath => ("SAVE AS", theme.status_input),
+        EditorMode::SelectHighlightColor | EditorMode::EditHighlightColor => {
+            ("HIGHLIGHT", theme.status_select)
+        }
+        EditorMode::SelectParticipantKind => ("PARTICIPANT TYPE", theme.status_select),
+        EditorMode::Diagnostics => ("DIAGNOSTICS", theme.status_help),
+        EditorMode::EditMetadata => ("METADATA", theme.status_select),
+        EditorMode::InputMetadataField => ("METADATA", theme.status_input),
+        EditorMode::SelectMarkdownBlock => ("MARKDOWN", theme.status_select),
     };
 
     let hints = match mode {
@@ -61,9 +97,17 @@ pub fn render_status_bar(frame: &mut Frame, area: Rect, world: &World) {
         | EditorMode::InputMessage
         | EditorMode::EditMessage
         | EditorMode::RenameParticipant
+        | EditorMode::RenameParticipantLabel
         | EditorMode::InputNoteText
         | EditorMode::EditNoteText
-        | EditorMode::InputBoxLabel => "Enter: confirm  Esc: cancel",
+        | EditorMode::InputBoxLabel
+        | EditorMode::InputFragmentLabel
+        | EditorMode::EditFragmentLabel
+        | EditorMode::InputFragmentSection
+        | EditorMode::InputAutonumber
+        | EditorMode::InputGotoMessage
+        | EditorMode::InputMetadataField
+        | EditorMode::InputSavePath => "Enter: confirm  Esc: cancel",
         EditorMode::SelectFrom
         | EditorMode::SelectTo
         | EditorMode::EditSelectFrom
@@ -77,15 +121,41 @@ pub fn render_status_bar(frame: &mut Frame, area: Rect, world: &World) {
         EditorMode::SelectNotePosition | EditorMode::EditNotePosition => {
             "↑↓: change position  Enter: confirm  Esc: cancel"
         }
-        EditorMode::SelectBoxColor => "↑↓: change color  Enter: confirm  Esc: cancel",
+        EditorMode::SelectBoxColor
+        | EditorMode::SelectHighlightColor
+        | EditorMode::EditHighlightColor => "↑↓: change color  Enter: confirm  Esc: cancel",
+        EditorMode::SelectFragmentKind | EditorMode::EditFragmentKind => {
+            "↑↓: change fragment  Enter: confirm  Esc: cancel"
+        }
+        EditorMode::SelectParticipantKind => "↑↓: change type  Enter: confirm  Esc: cancel",
+        EditorMode::Diagnostics => "↑↓: navigate  Enter: go to event  Esc: close",
+        EditorMode::EditMetadata => "↑↓: navigate  Enter: edit  Esc: close",
+        EditorMode::SelectMarkdownBlock => "↑↓: navigate  Enter: edit  Esc: quit",
         EditorMode::Help => "?: close",
-        EditorMode::ConfirmClear => "y/Enter: confirm  n/Esc: cancel",
+        EditorMode::ConfirmClear
+        | EditorMode::ConfirmQuit
+        | EditorMode::ConfirmRestore
+        | EditorMode::ConfirmReload
+        | EditorMode::ConfirmOverwrite => "y/Enter: confirm  n/Esc: cancel",
     };
 
     let mut spans = vec![
         Span::styled(format!(" {mode_text} "), mode_style),
         Span::raw(" "),
+        Span::styled(file_name, theme.text),
     ];
+    if dirty {
+        spans.push(Span::styled(" [+]", theme.accent));
+    }
+    spans.push(Span::raw("  "));
+
+    if *mode == EditorMode::Normal && diagnostic_count > 0 {
+        spans.push(Span::styled(
+            format!("⚠ {diagnostic_count} import problems (D)"),
+            theme.accent,
+        ));
+        spans.push(Span::raw("  "));
+    }
 
     if let Some(msg) = status_message {
         spans.push(Span::styled(msg, theme.success));